@@ -7,7 +7,7 @@ use convert_case::{Case, Casing};
 use indexmap::IndexMap;
 use multimap::MultiMap;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::Write;
 use std::fs::File;
@@ -88,6 +88,10 @@ struct I2cDevice {
     /// device is removable
     #[serde(default)]
     removable: bool,
+
+    /// sensors on the device, if any
+    #[serde(default)]
+    sensors: Vec<I2cSensor>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -124,6 +128,366 @@ struct I2cPmbus {
     rails: Option<Vec<String>>,
 }
 
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+enum SensorKind {
+    Temperature,
+    Voltage,
+    Current,
+    Fan,
+}
+
+//
+// The parts that may have sensors: those that a task has a driver to read.
+// The sensor inventory names the device of each sensor as a variant of the
+// generated `sensors::Device`, one for each of these parts, allowing a task
+// to match exhaustively on the device of a sensor.
+//
+const SENSOR_DEVICES: &[&str] = &[
+    "adt7420",
+    "isl68224",
+    "max31790",
+    "max6634",
+    "mcp9808",
+    "pct2075",
+    "raa229618",
+    "tmp116",
+    "tmp117",
+    "tps546b24a",
+];
+
+///
+/// Returns the name of the type for a part in generated code: the part name,
+/// capitalized (e.g., `Tmp117` for "tmp117").
+///
+fn part_type(part: &str) -> String {
+    format!("{}{}", part[..1].to_uppercase(), &part[1..])
+}
+
+//
+// A sensor on a device.  A device with more than one sensor of a kind (e.g.,
+// a fan controller or a multi-rail power controller) has its sensors of that
+// kind indexed from 0 in the order in which they appear; a sensor with an
+// explicit index sets the index from which those that follow it count.
+//
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2cSensor {
+    /// kind of sensor
+    kind: SensorKind,
+
+    /// sensor name
+    name: String,
+
+    /// location of sensor, if any
+    location: Option<String>,
+
+    /// index of sensor on the device (e.g., fan or rail), if not implied
+    index: Option<u8>,
+}
+
+impl I2cDevice {
+    ///
+    /// Names the device for messages: its part (and its name and refdes, if
+    /// any), its address (and mux and segment, if any), its description,
+    /// and the number of its entry (from 1) in the list of devices.
+    ///
+    fn describe(&self, entry: usize) -> String {
+        let mut s = format!("device {}", self.device);
+
+        if let Some(name) = &self.name {
+            s.push_str(&format!(" \"{}\"", name));
+        }
+
+        if let Some(refdes) = &self.refdes {
+            s.push_str(&format!(" ({})", refdes));
+        }
+
+        s.push_str(&format!(" at address 0x{:x}", self.address));
+
+        match (self.mux, self.segment) {
+            (Some(mux), Some(segment)) => {
+                s.push_str(&format!(" on mux {}, segment {}", mux, segment));
+            }
+            (Some(mux), None) => s.push_str(&format!(" on mux {}", mux)),
+            (None, Some(segment)) => {
+                s.push_str(&format!(" on segment {}", segment));
+            }
+            (None, None) => {}
+        }
+
+        format!("{} (\"{}\", entry {})", s, self.description, entry + 1)
+    }
+
+    /// Returns each sensor on the device, along with its index.
+    fn sensors(&self) -> impl Iterator<Item = (&I2cSensor, u8)> {
+        let mut next = HashMap::new();
+
+        self.sensors.iter().map(move |sensor| {
+            let index = next.entry(sensor.kind).or_insert(0);
+            let rval = sensor.index.unwrap_or(*index);
+            *index = rval + 1;
+            (sensor, rval)
+        })
+    }
+}
+
+impl I2cConfig {
+    ///
+    /// Checks the configuration for mistakes that would otherwise result in
+    /// generated code that doesn't build or (worse) that silently misbehaves:
+    /// devices that refer to controllers, ports, buses or muxes that don't
+    /// exist; devices whose addresses collide with one another or with a mux;
+    /// and pins that are assigned more than once.
+    ///
+    fn validate(&self) -> Result<()> {
+        let mut controllers = HashMap::new();
+        let mut buses = HashMap::new();
+        let mut pins = HashMap::new();
+
+        let mut assign = |gpio_port: &str, pin: u8, owner: String| {
+            let key = (gpio_port.to_string(), pin);
+
+            if let Some(prev) = pins.insert(key, owner.clone()) {
+                bail!(
+                    "pin P{}{} is assigned to both {} and {}",
+                    gpio_port,
+                    pin,
+                    prev,
+                    owner
+                );
+            }
+
+            Ok(())
+        };
+
+        for c in &self.controllers {
+            if controllers.insert(c.controller, c).is_some() {
+                bail!("controller I2C{} appears twice", c.controller);
+            }
+
+            for (p, port) in &c.ports {
+                if let Some(name) = &port.name {
+                    if buses.insert(name, (c.controller, p)).is_some() {
+                        bail!("i2c bus {} appears twice", name);
+                    }
+                }
+
+                for pinset in &port.pins {
+                    let gpio_port = pinset.gpio_port.as_ref().unwrap_or(p);
+
+                    for &pin in &pinset.pins {
+                        let owner = format!("I2C{}, port {}", c.controller, p);
+                        assign(gpio_port, pin, owner)?;
+                    }
+                }
+
+                for (mindex, mux) in port.muxes.iter().enumerate() {
+                    if let Some(prev) = port.muxes[..mindex]
+                        .iter()
+                        .position(|m| m.address == mux.address)
+                    {
+                        bail!(
+                            "muxes {} and {} on I2C{}, port {} are both \
+                            at address 0x{:x}",
+                            prev + 1,
+                            mindex + 1,
+                            c.controller,
+                            p,
+                            mux.address
+                        );
+                    }
+
+                    //
+                    // A missing pin port on an enable is reported when we
+                    // generate the muxes.
+                    //
+                    if let Some(enable) = &mux.enable {
+                        if let Some(gpio_port) = &enable.gpio_port {
+                            for &pin in &enable.pins {
+                                let owner = format!(
+                                    "the enable of mux {} on I2C{}, port {}",
+                                    mindex + 1,
+                                    c.controller,
+                                    p
+                                );
+                                assign(gpio_port, pin, owner)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut located: Vec<(_, _, _, Option<_>, String)> = vec![];
+        let mut sensors = HashMap::new();
+
+        for (entry, d) in self.devices.iter().flatten().enumerate() {
+            let desc = d.describe(entry);
+
+            let (controller, p) = match (d.controller, &d.bus, &d.port) {
+                (None, None, _) => {
+                    bail!("{} must have a bus or controller", desc);
+                }
+                (Some(_), Some(_), _) => {
+                    bail!("{} has both a bus and a controller", desc);
+                }
+                (None, Some(_), Some(_)) => {
+                    bail!("{} has both a bus and a port", desc);
+                }
+                (None, Some(bus), None) => match buses.get(bus) {
+                    Some((controller, p)) => (*controller, *p),
+                    None => bail!("{} specifies unknown bus \"{}\"", desc, bus),
+                },
+                (Some(controller), None, port) => {
+                    let c = match controllers.get(&controller) {
+                        Some(c) => c,
+                        None => bail!(
+                            "{} specifies unknown controller I2C{}",
+                            desc,
+                            controller
+                        ),
+                    };
+
+                    match port {
+                        Some(port) => match c.ports.get_key_value(port) {
+                            Some((p, _)) => (controller, p),
+                            None => bail!(
+                                "{} specifies unknown port {} on I2C{}",
+                                desc,
+                                port,
+                                controller
+                            ),
+                        },
+                        None if c.ports.len() == 1 => {
+                            (controller, c.ports.get_index(0).unwrap().0)
+                        }
+                        None => bail!(
+                            "{} must specify a port, as I2C{} has {} ports",
+                            desc,
+                            controller,
+                            c.ports.len()
+                        ),
+                    }
+                }
+            };
+
+            let muxes = &controllers[&controller].ports[p].muxes;
+
+            let segment = match (d.mux, d.segment) {
+                (None, None) => None,
+                (Some(mux), Some(segment)) => {
+                    if mux == 0 || mux as usize > muxes.len() {
+                        bail!(
+                            "{} specifies mux {}, but I2C{}, port {} has \
+                            {} mux(es)",
+                            desc,
+                            mux,
+                            controller,
+                            p,
+                            muxes.len()
+                        );
+                    }
+
+                    if segment == 0 || segment > 8 {
+                        bail!("{} specifies invalid segment {}", desc, segment);
+                    }
+
+                    Some((mux, segment))
+                }
+                (Some(_), None) => {
+                    bail!("{} specifies a mux but no segment", desc);
+                }
+                (None, Some(_)) => {
+                    bail!("{} specifies a segment but no mux", desc);
+                }
+            };
+
+            if !d.sensors.is_empty()
+                && !SENSOR_DEVICES.contains(&d.device.as_str())
+            {
+                bail!("{} has sensors, but no driver reads them", desc);
+            }
+
+            let mut indices = HashSet::new();
+
+            for (sensor, index) in d.sensors() {
+                let kind = sensor.kind;
+
+                let key = (kind, &sensor.name);
+
+                if let Some(prev) = sensors.insert(key, desc.clone()) {
+                    bail!(
+                        "{:?} sensor \"{}\" is on both {} and {}",
+                        kind,
+                        sensor.name,
+                        prev,
+                        desc
+                    );
+                }
+
+                if !indices.insert((kind, index)) {
+                    bail!(
+                        "{} has more than one {:?} sensor with index {}",
+                        desc,
+                        kind,
+                        index
+                    );
+                }
+            }
+
+            //
+            // Muxes sit on the port itself, so a mux's address is taken on
+            // every segment.  A device on the port itself at a mux's address
+            // must be the mux, listed as a device -- which we know it to be
+            // if it's the part that the mux's driver drives.
+            //
+            for (mindex, mux) in muxes.iter().enumerate() {
+                if mux.address != d.address {
+                    continue;
+                }
+
+                if segment.is_none() && d.device == mux.driver {
+                    continue;
+                }
+
+                bail!(
+                    "{} collides with mux {} ({}) on I2C{}, port {}",
+                    desc,
+                    mindex + 1,
+                    mux.driver,
+                    controller,
+                    p
+                );
+            }
+
+            //
+            // Likewise, a device directly on the port is visible from every
+            // segment.
+            //
+            for (address, c, port, s, other) in &located {
+                if *c != controller || *port != p || *address != d.address {
+                    continue;
+                }
+
+                if *s == segment || s.is_none() || segment.is_none() {
+                    bail!(
+                        "{} and {} are at the same address on I2C{}, port {}",
+                        other,
+                        desc,
+                        controller,
+                        p
+                    );
+                }
+            }
+
+            located.push((d.address, controller, p, segment, desc));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum Artifact {
     /// part of a complete distribution of an application
@@ -186,6 +550,10 @@ impl ConfigGenerator {
             },
         };
 
+        if let Err(err) = i2c.validate() {
+            panic!("invalid config.i2c: {}", err);
+        }
+
         let mut controllers = vec![];
         let mut buses = HashMap::new();
         let mut ports = IndexMap::new();
@@ -199,12 +567,7 @@ impl ConfigGenerator {
             //
             for (index, (p, port)) in c.ports.iter().enumerate() {
                 if let Some(name) = &port.name {
-                    match buses.insert(name.clone(), (c.controller, index)) {
-                        Some(_) => {
-                            panic!("i2c bus {} appears twice", name);
-                        }
-                        None => {}
-                    }
+                    buses.insert(name.clone(), (c.controller, index));
                 }
 
                 if c.ports.len() == 1 {
@@ -221,35 +584,6 @@ impl ConfigGenerator {
             controllers.push(c);
         }
 
-        if let Some(devices) = &i2c.devices {
-            for d in devices {
-                match (d.controller, d.bus.as_ref()) {
-                    (None, None) => {
-                        panic!(
-                            "device {} at address 0x{:x} must have \
-                            a bus or controller",
-                            d.device, d.address
-                        );
-                    }
-                    (Some(_), Some(_)) => {
-                        panic!(
-                            "device {} at address 0x{:x} has both \
-                            a bus and a controller",
-                            d.device, d.address
-                        );
-                    }
-                    (_, Some(bus)) if buses.get(bus).is_none() => {
-                        panic!(
-                            "device {} at address 0x{:x} specifies \
-                            unknown bus \"{}\"",
-                            d.device, d.address, bus
-                        );
-                    }
-                    (_, _) => {}
-                }
-            }
-        }
-
         Self {
             output: String::new(),
             disposition: disposition,
@@ -489,11 +823,7 @@ impl ConfigGenerator {
                         "None".to_string()
                     };
 
-                    let driver_struct = format!(
-                        "{}{}",
-                        (&mux.driver[..1].to_string()).to_uppercase(),
-                        &mux.driver[1..]
-                    );
+                    let driver_struct = part_type(&mux.driver);
 
                     write!(
                         &mut s,
@@ -567,6 +897,14 @@ impl ConfigGenerator {
             },
         };
 
+        let segment = match (d.mux, d.segment) {
+            (Some(mux), Some(segment)) => format!(
+                "Some((drv_i2c_api::Mux::M{}, drv_i2c_api::Segment::S{}))",
+                mux, segment
+            ),
+            _ => "None".to_string(),
+        };
+
         format!(
             r##"
             // {description}
@@ -579,7 +917,7 @@ impl ConfigGenerator {
             description = d.description,
             controller = controller,
             port = port,
-            segment = "None",
+            segment = segment,
             address = d.address,
         )
     }
@@ -762,6 +1100,105 @@ impl ConfigGenerator {
         Ok(())
     }
 
+    pub fn generate_sensors(&mut self) -> Result<()> {
+        let mut sensors = vec![];
+
+        for d in &self.devices {
+            for (sensor, index) in d.sensors() {
+                sensors.push((d, sensor, index));
+            }
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+    pub mod sensors {{
+        #[allow(unused_imports)]
+        use drv_i2c_api::{{I2cDevice, Controller, PortIndex}};
+        use userlib::TaskId;
+
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        #[allow(dead_code)]
+        pub enum Kind {{
+            Temperature,
+            Voltage,
+            Current,
+            Fan,
+        }}
+
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        #[allow(dead_code)]
+        pub enum Device {{"##
+        )?;
+
+        for part in SENSOR_DEVICES {
+            write!(&mut self.output, "\n            {},", part_type(part))?;
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+        }}
+
+        #[derive(Copy, Clone, Debug)]
+        #[allow(dead_code)]
+        pub struct Sensor {{
+            /// kind of sensor
+            pub kind: Kind,
+            /// name of sensor
+            pub name: &'static str,
+            /// location of sensor, if specified
+            pub location: Option<&'static str>,
+            /// the part that is the device
+            pub device: Device,
+            /// index of sensor on the device (e.g., fan or rail)
+            pub index: u8,
+            /// the device itself
+            pub i2c: I2cDevice,
+        }}
+
+        pub const NUM_SENSORS: usize = {};
+
+        #[allow(dead_code, unused_variables)]
+        pub fn sensors(task: TaskId) -> [Sensor; NUM_SENSORS] {{
+            ["##,
+            sensors.len()
+        )?;
+
+        for (d, sensor, index) in sensors {
+            let out = self.generate_device(d);
+
+            write!(
+                &mut self.output,
+                r##"
+                Sensor {{
+                    kind: Kind::{kind:?},
+                    name: {name:?},
+                    location: {location:?},
+                    device: Device::{device},
+                    index: {index},
+                    i2c: {i2c},
+                }},"##,
+                kind = sensor.kind,
+                name = sensor.name,
+                location = sensor.location,
+                device = part_type(&d.device),
+                index = index,
+                i2c = out,
+            )?;
+        }
+
+        writeln!(
+            &mut self.output,
+            r##"
+            ]
+        }}
+    }}"##
+        )?;
+
+        Ok(())
+    }
+
     pub fn generate_ports(&mut self) -> Result<()> {
         writeln!(
             &mut self.output,
@@ -841,6 +1278,7 @@ pub fn codegen(disposition: Disposition, artifact: Artifact) -> Result<()> {
         Disposition::Devices => {
             g.generate_devices()?;
             g.generate_pmbus()?;
+            g.generate_sensors()?;
         }
     }
 
@@ -850,3 +1288,374 @@ pub fn codegen(disposition: Disposition, artifact: Artifact) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // Two ports on one controller: F (named "front") has a mux with an
+    // enable pin, and H has none.
+    //
+    const CONTROLLERS: &str = r#"
+        [[controllers]]
+        controller = 2
+
+        [controllers.ports.F]
+        name = "front"
+        pins = [ { pins = [ 0, 1 ], af = 4 } ]
+
+        [[controllers.ports.F.muxes]]
+        driver = "pca9548"
+        address = 0x70
+        enable = { gpio_port = "G", pins = [ 2 ], af = 0 }
+
+        [controllers.ports.H]
+        pins = [ { pins = [ 4, 5 ], af = 4 } ]
+    "#;
+
+    fn validate(config: &str) -> Result<()> {
+        toml::from_str::<I2cConfig>(config).unwrap().validate()
+    }
+
+    /// Validates `devices` on [`CONTROLLERS`], returning the error.
+    fn error(devices: &str) -> String {
+        match validate(&format!("{}{}", CONTROLLERS, devices)) {
+            Ok(_) => panic!("invalid configuration passed validation"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let devices = r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "on segment 1"
+            mux = 1
+            segment = 1
+
+            [[devices]]
+            device = "tmp117"
+            controller = 2
+            port = "F"
+            address = 0x48
+            description = "on segment 2"
+            mux = 1
+            segment = 2
+
+            [[devices]]
+            device = "tmp117"
+            controller = 2
+            port = "H"
+            address = 0x48
+            description = "on another port"
+
+            [[devices]]
+            device = "pca9548"
+            bus = "front"
+            address = 0x70
+            description = "the mux itself"
+        "#;
+
+        validate(&format!("{}{}", CONTROLLERS, devices)).unwrap();
+    }
+
+    #[test]
+    fn duplicate_address() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "first"
+            mux = 1
+            segment = 3
+
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "second"
+            mux = 1
+            segment = 3
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x48 on mux 1, segment 3 (\"first\", \
+            entry 1) and device tmp117 at address 0x48 on mux 1, segment 3 \
+            (\"second\", entry 2) are at the same address on I2C2, port F"
+        );
+
+        //
+        // A device on the port itself collides with a device at its address
+        // on any segment.
+        //
+        let err = error(
+            r#"
+            [[devices]]
+            device = "max6634"
+            controller = 2
+            port = "F"
+            address = 0x4c
+            description = "root"
+
+            [[devices]]
+            device = "sbtsi"
+            bus = "front"
+            address = 0x4c
+            description = "segment"
+            mux = 1
+            segment = 8
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device max6634 at address 0x4c (\"root\", entry 1) and device \
+            sbtsi at address 0x4c on mux 1, segment 8 (\"segment\", entry 2) \
+            are at the same address on I2C2, port F"
+        );
+    }
+
+    #[test]
+    fn unknown_bus() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "back"
+            address = 0x48
+            description = "nowhere"
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x48 (\"nowhere\", entry 1) specifies \
+            unknown bus \"back\""
+        );
+    }
+
+    #[test]
+    fn unknown_port() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            controller = 2
+            port = "B"
+            address = 0x48
+            description = "nowhere"
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x48 (\"nowhere\", entry 1) specifies \
+            unknown port B on I2C2"
+        );
+    }
+
+    #[test]
+    fn unknown_mux() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "nowhere"
+            mux = 2
+            segment = 1
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x48 on mux 2, segment 1 (\"nowhere\", \
+            entry 1) specifies mux 2, but I2C2, port F has 1 mux(es)"
+        );
+    }
+
+    #[test]
+    fn mux_collision() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x70
+            description = "behind the mux"
+            mux = 1
+            segment = 4
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x70 on mux 1, segment 4 (\"behind the \
+            mux\", entry 1) collides with mux 1 (pca9548) on I2C2, port F"
+        );
+
+        //
+        // On the port itself, only the mux may be at its address.
+        //
+        let err = error(
+            r#"
+            [[devices]]
+            device = "pca9545"
+            bus = "front"
+            address = 0x70
+            refdes = "U7"
+            description = "the wrong mux"
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device pca9545 (U7) at address 0x70 (\"the wrong mux\", entry \
+            1) collides with mux 1 (pca9548) on I2C2, port F"
+        );
+    }
+
+    #[test]
+    fn duplicate_sensor() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "inlet"
+            mux = 1
+            segment = 1
+            sensors = [ { kind = "temperature", name = "inlet" } ]
+
+            [[devices]]
+            device = "tmp117"
+            name = "Outlet"
+            bus = "front"
+            address = 0x49
+            refdes = "U12"
+            description = "outlet"
+            mux = 1
+            segment = 1
+            sensors = [ { kind = "temperature", name = "inlet" } ]
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "Temperature sensor \"inlet\" is on both device tmp117 at \
+            address 0x48 on mux 1, segment 1 (\"inlet\", entry 1) and device \
+            tmp117 \"Outlet\" (U12) at address 0x49 on mux 1, segment 1 \
+            (\"outlet\", entry 2)"
+        );
+    }
+
+    #[test]
+    fn sensor_without_driver() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "sbtsi"
+            bus = "front"
+            address = 0x4c
+            description = "CPU temperature"
+            mux = 1
+            segment = 2
+            sensors = [ { kind = "temperature", name = "cpu" } ]
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device sbtsi at address 0x4c on mux 1, segment 2 (\"CPU \
+            temperature\", entry 1) has sensors, but no driver reads them"
+        );
+    }
+
+    #[test]
+    fn mux_without_segment() {
+        let err = error(
+            r#"
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x48
+            description = "inlet"
+
+            [[devices]]
+            device = "tmp117"
+            bus = "front"
+            address = 0x49
+            description = "outlet"
+            mux = 1
+            "#,
+        );
+
+        assert_eq!(
+            err,
+            "device tmp117 at address 0x49 on mux 1 (\"outlet\", entry 2) \
+            specifies a mux but no segment"
+        );
+    }
+
+    #[test]
+    fn pin_across_ports() {
+        let err = validate(
+            r#"
+            [[controllers]]
+            controller = 2
+
+            [controllers.ports.F]
+            pins = [ { pins = [ 0, 1 ], af = 4 } ]
+
+            [controllers.ports.H]
+            pins = [ { gpio_port = "F", pins = [ 1, 2 ], af = 4 } ]
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "pin PF1 is assigned to both I2C2, port F and I2C2, port H"
+        );
+    }
+
+    #[test]
+    fn pin_mux_enable() {
+        let err = validate(
+            r#"
+            [[controllers]]
+            controller = 2
+
+            [controllers.ports.F]
+            pins = [ { pins = [ 0, 1 ], af = 4 } ]
+
+            [[controllers.ports.F.muxes]]
+            driver = "pca9548"
+            address = 0x70
+            enable = { gpio_port = "G", pins = [ 2 ], af = 0 }
+
+            [[controllers.ports.F.muxes]]
+            driver = "pca9548"
+            address = 0x71
+            enable = { gpio_port = "G", pins = [ 2 ], af = 0 }
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "pin PG2 is assigned to both the enable of mux 1 on I2C2, port F \
+            and the enable of mux 2 on I2C2, port F"
+        );
+    }
+}
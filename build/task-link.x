@@ -22,6 +22,13 @@ SECTIONS
   {
     *(.rodata .rodata.*);
 
+    /* Registry of ring buffers, emitted by the ringbuf! macro.  It is kept
+       only in tasks that opt in to it; see userlib's build.rs. */
+    . = ALIGN(4);
+    __ringbuf_registry_start = .;
+    INCLUDE ringbuf-registry.x
+    __ringbuf_registry_end = .;
+
     /* 4-byte align the end (VMA) of this section.
        This is required by LLD to ensure the LMA of the following .data
        section will have the correct alignment. */
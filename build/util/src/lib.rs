@@ -50,3 +50,17 @@ pub fn config<T: DeserializeOwned>() -> Result<T> {
     println!("cargo:rerun-if-env-changed=HUBRIS_APP_CONFIG");
     Ok(rval)
 }
+
+///
+/// Pulls the task-specific configuration (that is, the `[tasks.X.config]`
+/// section of the `app.toml`) for purposes of a build task.  As with
+/// [`config`], `T` should contain only those parts that the build task cares
+/// about, and this will fail if the configuration doesn't exist or can't
+/// parse.
+///
+pub fn task_config<T: DeserializeOwned>() -> Result<T> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASK_CONFIG");
+    let config = env::var("HUBRIS_TASK_CONFIG")?;
+    let rval = toml::from_slice(config.as_bytes())?;
+    Ok(rval)
+}
@@ -15,10 +15,11 @@ use anyhow::{anyhow, bail, Context, Result};
 
 use indexmap::IndexMap;
 use path_slash::PathBufExt;
+use serde::Serialize;
 
 use crate::{
-    elf, task_slot, Config, LoadSegment, Output, Peripheral, Signing,
-    Supervisor, Task,
+    elf, task_slot, Config, Heartbeat, LoadSegment, Output, Peripheral,
+    RestartPolicy, Signing, Supervisor, Task,
 };
 
 use lpc55_support::{crc_image, sign_ecc, signed_image};
@@ -28,6 +29,9 @@ use lpc55_support::{crc_image, sign_ecc, signed_image};
 /// padded that a bit.
 const DEFAULT_KERNEL_STACK: u32 = 1024;
 
+/// Size of the bootloader's boot record, which must match `stage0`.
+const BOOT_RECORD_SIZE: u32 = 512;
+
 pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
     let cfg_contents = std::fs::read(&cfg)?;
     let toml: Config = toml::from_slice(&cfg_contents)?;
@@ -102,6 +106,7 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
     // Build each task.
     let task_names = toml.tasks.keys().cloned().collect::<Vec<_>>();
     let task_names = task_names.join(",");
+    let supervisor_config = supervisor_config(&toml.tasks)?;
     let mut all_output_sections = BTreeMap::default();
     let mut entry_points = HashMap::<_, _>::default();
 
@@ -162,16 +167,47 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
             std::process::exit(1);
         };
 
-        bootloader_memory.insert(String::from("FLASH"), flash.clone());
+        let imageb_flash =
+            match (bootloader.imageb_flash_start, bootloader.imageb_flash_size)
+            {
+                (Some(start), Some(size)) => match start.checked_add(size) {
+                    Some(end) => Some(start..end),
+                    None => {
+                        eprintln!("image B flash size is incorrect");
+                        std::process::exit(1);
+                    }
+                },
+                (None, None) => None,
+                _ => {
+                    eprintln!("image B needs both a flash start and size");
+                    std::process::exit(1);
+                }
+            };
+
+        //
+        // The last page of the bootloader's flash holds its boot record,
+        // which it rewrites -- so it must be kept out of the (signed)
+        // bootloader image.
+        //
+        let boot_record = flash.end - BOOT_RECORD_SIZE..flash.end;
+
+        bootloader_memory
+            .insert(String::from("FLASH"), flash.start..boot_record.start);
+        bootloader_memory.insert(String::from("BOOT_RECORD"), boot_record);
         bootloader_memory.insert(String::from("RAM"), ram.clone());
         bootloader_memory.insert(String::from("SRAM"), sram.clone());
         bootloader_memory
             .insert(String::from("IMAGEA_FLASH"), image_flash.clone());
         bootloader_memory.insert(String::from("IMAGEA_RAM"), image_ram.clone());
 
+        if let Some(imageb_flash) = imageb_flash {
+            bootloader_memory
+                .insert(String::from("IMAGEB_FLASH"), imageb_flash);
+        }
+
         let kernel_start = allocs.kernel.get("flash").unwrap().start;
 
-        if kernel_start != bootloader_memory.get("FLASH").unwrap().end {
+        if kernel_start != flash.end {
             panic!("mismatch between bootloader end and hubris start! check app.toml!");
         }
 
@@ -198,6 +234,7 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
             verbose,
             edges,
             &task_names,
+            "",
             &None,
             &shared_syms,
             &None,
@@ -247,9 +284,17 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
         File::create(Path::new(&format!("target/table.ld"))).unwrap();
     }
 
-    for name in toml.tasks.keys() {
+    for (ndx, name) in toml.tasks.keys().enumerate() {
         let task_toml = &toml.tasks[name];
 
+        // Only the supervisor (which is always task 0) is built with the
+        // supervisor configuration, lest a change to it rebuild every task.
+        let task_supervisor_config = if ndx == 0 {
+            supervisor_config.as_str()
+        } else {
+            ""
+        };
+
         generate_task_linker_script(
             "memory.x",
             &allocs.tasks[name],
@@ -275,6 +320,7 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
             verbose,
             edges,
             &task_names,
+            task_supervisor_config,
             &toml.secure,
             &shared_syms,
             &task_toml.config,
@@ -336,6 +382,7 @@ pub fn package(verbose: bool, edges: bool, cfg: &Path) -> Result<()> {
         verbose,
         edges,
         "",
+        "",
         &toml.secure,
         &None,
         &None,
@@ -694,12 +741,34 @@ fn generate_bootloader_linker_script(
     writeln!(linkscr, "  LONG(ORIGIN(IMAGEA_FLASH));").unwrap();
     writeln!(linkscr, "  PROVIDE(address_of_imagea_ram = .);").unwrap();
     writeln!(linkscr, "  LONG(ORIGIN(IMAGEA_RAM));").unwrap();
-    writeln!(linkscr, "  PROVIDE(address_of_test_region = .);").unwrap();
-    writeln!(
-        linkscr,
-        "  LONG(ORIGIN(IMAGEA_FLASH) + LENGTH(IMAGEA_FLASH));"
-    )
-    .unwrap();
+    writeln!(linkscr, "  PROVIDE(address_of_boot_record = .);").unwrap();
+    writeln!(linkscr, "  LONG(ORIGIN(BOOT_RECORD));").unwrap();
+
+    //
+    // Image B is optional; the bootloader takes an address of zero to mean
+    // that there isn't one. The region for testing flash writes follows
+    // whichever image comes last.
+    //
+    writeln!(linkscr, "  PROVIDE(address_of_imageb_flash = .);").unwrap();
+
+    if map.contains_key("IMAGEB_FLASH") {
+        writeln!(linkscr, "  LONG(ORIGIN(IMAGEB_FLASH));").unwrap();
+        writeln!(linkscr, "  PROVIDE(address_of_test_region = .);").unwrap();
+        writeln!(
+            linkscr,
+            "  LONG(MAX(ORIGIN(IMAGEA_FLASH) + LENGTH(IMAGEA_FLASH), \
+            ORIGIN(IMAGEB_FLASH) + LENGTH(IMAGEB_FLASH)));"
+        )
+        .unwrap();
+    } else {
+        writeln!(linkscr, "  LONG(0);").unwrap();
+        writeln!(linkscr, "  PROVIDE(address_of_test_region = .);").unwrap();
+        writeln!(
+            linkscr,
+            "  LONG(ORIGIN(IMAGEA_FLASH) + LENGTH(IMAGEA_FLASH));"
+        )
+        .unwrap();
+    }
     writeln!(linkscr, "  }} > FLASH").unwrap();
 
     writeln!(linkscr, "}} INSERT BEFORE .bss").unwrap();
@@ -848,6 +917,7 @@ fn build(
     verbose: bool,
     edges: bool,
     task_names: &str,
+    supervisor_config: &str,
     secure: &Option<bool>,
     shared_syms: &Option<&[String]>,
     config: &Option<toml::Value>,
@@ -907,6 +977,10 @@ fn build(
     cmd.env("HUBRIS_TASKS", task_names);
     cmd.env("HUBRIS_BOARD", board_name);
 
+    if !supervisor_config.is_empty() {
+        cmd.env("HUBRIS_SUPERVISOR_CONFIG", supervisor_config);
+    }
+
     if let Some(s) = shared_syms {
         if !s.is_empty() {
             cmd.env("SHARED_SYMS", s.join(","));
@@ -1740,6 +1814,131 @@ fn cargo_clean(name: &str, target: &str) -> Result<()> {
     Ok(())
 }
 
+/// A task's restart policy as seen by the supervisor, with defaults filled in
+/// and the task's dependents resolved from everyone's task slots.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SupervisorRestartPolicy<'a> {
+    backoff: u32,
+    backoff_max: u32,
+    max_restarts: Option<u32>,
+    window: u32,
+    notification: u32,
+    dependents: Vec<&'a str>,
+}
+
+/// The parts of a task's configuration that concern the supervisor.
+#[derive(Debug, Serialize)]
+struct SupervisorTaskConfig<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart: Option<SupervisorRestartPolicy<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heartbeat: Option<&'a Heartbeat>,
+}
+
+/// Validates the restart policies and heartbeats of all tasks, and renders
+/// them as TOML for consumption by the supervisor's build script (via the
+/// `HUBRIS_SUPERVISOR_CONFIG` environment variable). Tasks with neither are
+/// omitted.
+fn supervisor_config(tasks: &IndexMap<String, Task>) -> Result<String> {
+    let mut config = IndexMap::new();
+
+    for (ndx, (name, task)) in tasks.iter().enumerate() {
+        let restart = match &task.restart {
+            Some(policy) => Some(restart_policy(tasks, name, policy)?),
+            None => None,
+        };
+
+        if let Some(heartbeat) = &task.heartbeat {
+            // The supervisor is always task 0, and it can hardly be expected
+            // to send a heartbeat to itself.
+            if ndx == 0 {
+                bail!(
+                    "task '{}': the supervisor cannot have a heartbeat",
+                    name
+                );
+            }
+
+            if heartbeat.period == 0 {
+                bail!("task '{}': heartbeat period must be non-zero", name);
+            }
+        }
+
+        if restart.is_none() && task.heartbeat.is_none() {
+            continue;
+        }
+
+        config.insert(
+            name.as_str(),
+            SupervisorTaskConfig {
+                restart,
+                heartbeat: task.heartbeat.as_ref(),
+            },
+        );
+    }
+
+    if config.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(toml::to_string(&config)?)
+    }
+}
+
+/// Validates a task's restart policy, filling in defaults and resolving the
+/// task's dependents from everyone's task slots.
+fn restart_policy<'a>(
+    tasks: &'a IndexMap<String, Task>,
+    name: &str,
+    policy: &RestartPolicy,
+) -> Result<SupervisorRestartPolicy<'a>> {
+    let backoff_max = policy.backoff_max.unwrap_or(policy.backoff);
+
+    if backoff_max < policy.backoff {
+        bail!(
+            "task '{}': restart backoff-max ({}) is less than backoff ({})",
+            name,
+            backoff_max,
+            policy.backoff
+        );
+    }
+
+    if policy.window == 0 {
+        bail!("task '{}': restart window must be non-zero", name);
+    }
+
+    if policy.notify_dependents == Some(0) {
+        bail!("task '{}': notify-dependents must be non-zero", name);
+    }
+
+    // A task's dependents are those tasks that can send to it, which is
+    // to say, those that have it in one of their task slots.
+    let dependents = match policy.notify_dependents {
+        Some(_) => tasks
+            .iter()
+            .filter(|(_, t)| t.task_slots.values().any(|s| s == name))
+            .map(|(n, _)| n.as_str())
+            .collect(),
+        None => vec![],
+    };
+
+    if policy.notify_dependents.is_some() && dependents.is_empty() {
+        println!(
+            "warning: task '{}' has notify-dependents set, \
+            but no task has it in its task-slots",
+            name
+        );
+    }
+
+    Ok(SupervisorRestartPolicy {
+        backoff: policy.backoff,
+        backoff_max,
+        max_restarts: policy.max_restarts,
+        window: policy.window,
+        notification: policy.notify_dependents.unwrap_or(0),
+        dependents,
+    })
+}
+
 fn resolve_task_slots(
     task_name: &String,
     all_tasks_toml: &IndexMap<String, Task>,
@@ -7,7 +7,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use indexmap::IndexMap;
 
@@ -19,6 +19,7 @@ mod flash;
 mod gdb;
 mod humility;
 mod license;
+mod ringbuf;
 mod task_slot;
 mod test;
 
@@ -120,6 +121,15 @@ enum Xtask {
 
     /// Check that all .rs files have the MPL header
     LicenseCheck,
+
+    /// Decode a dump of a task's ring buffers
+    Ringbuf {
+        /// Path to task executable
+        task_bin: PathBuf,
+
+        /// Path to the dump, as returned by the task
+        dump: PathBuf,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -168,6 +178,8 @@ struct Bootloader {
     imagea_flash_size: u32,
     imagea_ram_start: u32,
     imagea_ram_size: u32,
+    imageb_flash_start: Option<u32>,
+    imageb_flash_size: Option<u32>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -223,9 +235,49 @@ struct Task {
     #[serde(default)]
     task_slots: IndexMap<String, String>,
     #[serde(default)]
+    restart: Option<RestartPolicy>,
+    #[serde(default)]
+    heartbeat: Option<Heartbeat>,
+    #[serde(default)]
     config: Option<toml::Value>,
 }
 
+/// Policy that the supervisor applies when restarting a faulted task. Tasks
+/// without a `restart` section are restarted immediately, forever.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    /// Delay before the first restart in a window, in milliseconds. The delay
+    /// doubles with each successive restart, up to `backoff_max`.
+    #[serde(default)]
+    backoff: u32,
+    /// Upper bound on the restart delay, in milliseconds.
+    backoff_max: Option<u32>,
+    /// Maximum number of restarts within `window`, after which the task is
+    /// held rather than restarted.
+    max_restarts: Option<u32>,
+    /// Length of the window over which restarts are counted, in milliseconds.
+    /// Once a window has elapsed, the restart count and backoff are reset.
+    window: u32,
+    /// Notification bits to post to every task that has this task in its
+    /// `task-slots` when this task is restarted.
+    notify_dependents: Option<u32>,
+}
+
+/// Heartbeat that a task must post to the supervisor (via `task-jefe-api`)
+/// to be considered alive.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Heartbeat {
+    /// Maximum time between heartbeats, in milliseconds. A task that misses
+    /// its heartbeat is faulted (and restarted per its restart policy).
+    period: u32,
+    /// If set, a missed heartbeat additionally causes the supervisor to stop
+    /// feeding the hardware watchdog, resetting the chip.
+    #[serde(default)]
+    critical: bool,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct Peripheral {
@@ -366,6 +418,9 @@ fn main() -> Result<()> {
                 std::process::exit(1);
             }
         }
+        Xtask::Ringbuf { task_bin, dump } => {
+            ringbuf::decode(&task_bin, &dump)?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,377 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoding of ring buffer dumps
+//!
+//! A server that has opted in will serialize its ring buffers in response to
+//! `userlib::ringbuf_registry::DUMP_OP` (see that module for the format).
+//! This decodes such a dump, checking each ring buffer against the symbol
+//! table of the task's ELF, and prints the entries oldest first.  The
+//! payloads are printed as raw bytes; interpreting them requires the type
+//! information in the ELF's debug info, which is Humility's bailiwick.
+
+use anyhow::{bail, Context, Result};
+use scroll::{Pread, LE};
+use std::path::PathBuf;
+
+/// Magic number at the start of a dump ("RBD1").
+const DUMP_MAGIC: u32 = 0x3144_4252;
+
+/// Version of the dump format that we understand.
+const DUMP_VERSION: u16 = 1;
+
+/// Flag set on a ring buffer that is timestamped.
+const FLAG_TIMESTAMPED: u8 = 1 << 0;
+
+#[derive(Debug)]
+pub struct Timestamp {
+    pub ticks: u64,
+}
+
+#[derive(Debug)]
+pub struct Entry<'a> {
+    pub line: u16,
+    pub generation: u16,
+    pub count: u32,
+    pub times: Option<(Timestamp, Timestamp)>,
+    pub payload: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct Ringbuf<'a> {
+    pub name: &'a str,
+    pub address: u32,
+    pub last: Option<usize>,
+    pub entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Ringbuf<'a> {
+    /// Returns the entries that have been recorded, oldest first.
+    pub fn recorded(&self) -> Vec<&Entry<'a>> {
+        let last = match self.last {
+            Some(last) => last,
+            None => return vec![],
+        };
+
+        let (newer, older) = self.entries.split_at(last + 1);
+
+        older
+            .iter()
+            .chain(newer.iter())
+            .filter(|e| e.count != 0)
+            .collect()
+    }
+}
+
+fn timestamp(src: &[u8], offset: &mut usize) -> Result<Timestamp> {
+    Ok(Timestamp {
+        ticks: src.gread_with(offset, LE)?,
+    })
+}
+
+/// Parses a dump into its ring buffers.
+pub fn parse(src: &[u8]) -> Result<Vec<Ringbuf<'_>>> {
+    let offset = &mut 0;
+
+    let magic: u32 = src.gread_with(offset, LE)?;
+    let version: u16 = src.gread_with(offset, LE)?;
+    let nringbufs: u16 = src.gread_with(offset, LE)?;
+
+    if magic != DUMP_MAGIC {
+        bail!("bad magic 0x{:08x}; not a ring buffer dump", magic);
+    }
+
+    if version != DUMP_VERSION {
+        bail!("unsupported dump version {}", version);
+    }
+
+    let mut ringbufs = vec![];
+
+    for _ in 0..nringbufs {
+        let len: u16 = src.gread_with(offset, LE)?;
+        let name: &str =
+            src.gread_with(offset, scroll::ctx::StrCtx::Length(len as usize))?;
+
+        let address: u32 = src.gread_with(offset, LE)?;
+        let flags: u8 = src.gread_with(offset, LE)?;
+        let _reserved: u8 = src.gread_with(offset, LE)?;
+        let nentries: u16 = src.gread_with(offset, LE)?;
+        let size: u16 = src.gread_with(offset, LE)?;
+        let last: u16 = src.gread_with(offset, LE)?;
+
+        let mut entries = vec![];
+
+        for _ in 0..nentries {
+            let line = src.gread_with(offset, LE)?;
+            let generation = src.gread_with(offset, LE)?;
+            let count = src.gread_with(offset, LE)?;
+
+            let times = if flags & FLAG_TIMESTAMPED != 0 {
+                Some((timestamp(src, offset)?, timestamp(src, offset)?))
+            } else {
+                None
+            };
+
+            let payload = src
+                .get(*offset..*offset + size as usize)
+                .with_context(|| format!("{}: truncated entry", name))?;
+            *offset += size as usize;
+
+            entries.push(Entry {
+                line,
+                generation,
+                count,
+                times,
+                payload,
+            });
+        }
+
+        let last = match last {
+            0xffff => None,
+            last if (last as usize) < entries.len() => Some(last as usize),
+            last => bail!("{}: bad last index {}", name, last),
+        };
+
+        ringbufs.push(Ringbuf {
+            name,
+            address,
+            last,
+            entries,
+        });
+    }
+
+    if *offset != src.len() {
+        bail!("{} trailing bytes in dump", src.len() - *offset);
+    }
+
+    Ok(ringbufs)
+}
+
+/// Finds the name of the symbol at `address` in `elf`, if any.
+fn symbol<'a>(elf: &'a goblin::elf::Elf, address: u32) -> Option<&'a str> {
+    elf.syms
+        .iter()
+        .find(|sym| sym.st_value == address as u64 && sym.st_size != 0)
+        .and_then(|sym| elf.strtab.get_at(sym.st_name))
+}
+
+pub fn decode(task_bin: &PathBuf, dump: &PathBuf) -> Result<()> {
+    let task_bin = std::fs::read(task_bin)?;
+    let elf = goblin::elf::Elf::parse(&task_bin)?;
+    let dump = std::fs::read(dump)?;
+
+    for ringbuf in parse(&dump)? {
+        //
+        // The variable's symbol is mangled, but will contain the last
+        // component of its path; if it doesn't, the dump came from a
+        // different build.
+        //
+        let var = ringbuf.name.rsplit("::").next().unwrap();
+
+        match symbol(&elf, ringbuf.address) {
+            Some(sym) if sym.contains(var) => {}
+            _ => bail!(
+                "{} at 0x{:08x} not found in task; is this the right ELF?",
+                ringbuf.name,
+                ringbuf.address
+            ),
+        }
+
+        println!("{} (0x{:08x}):", ringbuf.name, ringbuf.address);
+        println!(" LINE GEN   COUNT PAYLOAD");
+
+        for e in ringbuf.recorded() {
+            let payload: Vec<String> =
+                e.payload.iter().map(|b| format!("{:02x}", b)).collect();
+
+            print!(
+                "{:5} {:3} {:7} {}",
+                e.line,
+                e.generation,
+                e.count,
+                payload.join(" ")
+            );
+
+            if let Some((first, last)) = &e.times {
+                print!(" (first {}, last {})", first.ticks, last.ticks);
+            }
+
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a dump, as `userlib::ringbuf_registry` would.
+    struct Dump(Vec<u8>);
+
+    impl Dump {
+        fn new(nringbufs: u16) -> Self {
+            let mut dump = Dump(vec![]);
+            dump.put(&DUMP_MAGIC.to_le_bytes());
+            dump.put(&DUMP_VERSION.to_le_bytes());
+            dump.put(&nringbufs.to_le_bytes());
+            dump
+        }
+
+        fn put(&mut self, bytes: &[u8]) -> &mut Self {
+            self.0.extend_from_slice(bytes);
+            self
+        }
+
+        fn ringbuf(
+            &mut self,
+            name: &str,
+            timestamped: bool,
+            nentries: u16,
+            size: u16,
+            last: u16,
+        ) -> &mut Self {
+            self.put(&(name.len() as u16).to_le_bytes());
+            self.put(name.as_bytes());
+            self.put(&0x2000_0400u32.to_le_bytes());
+            self.put(&[timestamped as u8, 0]);
+            self.put(&nentries.to_le_bytes());
+            self.put(&size.to_le_bytes());
+            self.put(&last.to_le_bytes())
+        }
+
+        fn entry(
+            &mut self,
+            line: u16,
+            count: u32,
+            times: Option<[u64; 2]>,
+            payload: &[u8],
+        ) -> &mut Self {
+            self.put(&line.to_le_bytes());
+            self.put(&1u16.to_le_bytes());
+            self.put(&count.to_le_bytes());
+
+            if let Some(times) = times {
+                for ticks in times.iter() {
+                    self.put(&ticks.to_le_bytes());
+                }
+            }
+
+            self.put(payload)
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let dump = Dump::new(0);
+        assert!(parse(&dump.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recorded_oldest_first() {
+        let mut dump = Dump::new(1);
+        dump.ringbuf("task_foo::__RINGBUF", false, 3, 2, 0)
+            .entry(10, 1, None, &[0xaa, 0xbb])
+            .entry(0, 0, None, &[0, 0])
+            .entry(12, 4, None, &[0xcc, 0xdd]);
+
+        let ringbufs = parse(&dump.0).unwrap();
+        assert_eq!(ringbufs.len(), 1);
+
+        let r = &ringbufs[0];
+        assert_eq!(r.name, "task_foo::__RINGBUF");
+        assert_eq!(r.address, 0x2000_0400);
+        assert_eq!(r.last, Some(0));
+
+        // The entry after the most recent is the oldest; unused entries (with
+        // a count of zero) are skipped.
+        let recorded = r.recorded();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].line, 12);
+        assert_eq!(recorded[0].count, 4);
+        assert_eq!(recorded[0].payload, &[0xcc, 0xdd]);
+        assert_eq!(recorded[1].line, 10);
+        assert!(recorded[1].times.is_none());
+    }
+
+    #[test]
+    fn never_recorded() {
+        let mut dump = Dump::new(1);
+        dump.ringbuf("task_foo::__RINGBUF", false, 1, 1, 0xffff)
+            .entry(0, 0, None, &[0]);
+
+        let ringbufs = parse(&dump.0).unwrap();
+        assert_eq!(ringbufs[0].last, None);
+        assert!(ringbufs[0].recorded().is_empty());
+    }
+
+    #[test]
+    fn timestamped() {
+        let mut dump = Dump::new(2);
+        dump.ringbuf("task_foo::A_RINGBUF", true, 1, 4, 0)
+            .entry(7, 3, Some([100, 250]), &[1, 2, 3, 4])
+            .ringbuf("task_foo::B_RINGBUF", false, 1, 0, 0)
+            .entry(8, 1, None, &[]);
+
+        let ringbufs = parse(&dump.0).unwrap();
+        assert_eq!(ringbufs.len(), 2);
+
+        let e = &ringbufs[0].entries[0];
+        let (first, last) = e.times.as_ref().unwrap();
+        assert_eq!((first.ticks, last.ticks), (100, 250));
+        assert_eq!(e.payload, &[1, 2, 3, 4]);
+
+        assert_eq!(ringbufs[1].name, "task_foo::B_RINGBUF");
+        assert!(ringbufs[1].entries[0].payload.is_empty());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut dump = Dump::new(0);
+        dump.0[0] ^= 0xff;
+        assert!(parse(&dump.0).is_err());
+    }
+
+    #[test]
+    fn bad_version() {
+        let mut dump = Dump::new(0);
+        dump.0[4] = DUMP_VERSION as u8 + 1;
+        assert!(parse(&dump.0).is_err());
+    }
+
+    #[test]
+    fn bad_last() {
+        let mut dump = Dump::new(1);
+        dump.ringbuf("task_foo::__RINGBUF", false, 1, 1, 1).entry(
+            1,
+            1,
+            None,
+            &[0],
+        );
+        assert!(parse(&dump.0).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut dump = Dump::new(1);
+        dump.ringbuf("task_foo::__RINGBUF", true, 1, 4, 0).entry(
+            7,
+            3,
+            Some([100, 250]),
+            &[1, 2, 3, 4],
+        );
+
+        for len in 0..dump.0.len() {
+            assert!(parse(&dump.0[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn trailing() {
+        let mut dump = Dump::new(0);
+        dump.put(&[0]);
+        assert!(parse(&dump.0).is_err());
+    }
+}
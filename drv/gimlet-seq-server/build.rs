@@ -17,7 +17,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn compress(input: &[u8]) -> Vec<u8> {
     let mut output = vec![];
-    gnarle::compress(input, |chunk| {
+    gnarle::compress_framed(input, |chunk| {
         output.extend_from_slice(chunk);
         Ok::<_, std::convert::Infallible>(())
     })
@@ -238,14 +238,20 @@ fn reprogram_fpga(
     // one transaction, but we'll want chunking later -- so let's make sure
     // chunking works.
     let mut bitstream = COMPRESSED_BITSTREAM;
-    let mut decompressor = gnarle::Decompressor::default();
+    let mut decompressor = gnarle::Decompressor::framed();
     let mut chunk = [0; 256];
+
+    // The bitstream is baked into our image, so if it's corrupt, no amount of
+    // retrying is going to help.
     while !bitstream.is_empty() || !decompressor.is_idle() {
         let out =
-            gnarle::decompress(&mut decompressor, &mut bitstream, &mut chunk);
+            gnarle::decompress(&mut decompressor, &mut bitstream, &mut chunk)
+                .unwrap();
         ice40::continue_bitstream_load(&spi, out)?;
     }
 
+    decompressor.finish().unwrap();
+
     ice40::finish_bitstream_load(&spi, &gpio, &config)
 }
 
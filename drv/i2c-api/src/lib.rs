@@ -19,24 +19,60 @@
 //! - The segment on the multiplexer, if a multiplexer is specified
 //! - The address of the device itself
 //!
+//! # SMBus
+//!
+//! Beyond plain reads and writes, the server supports the SMBus block
+//! transactions (block read, block write and block process call), and
+//! SMBus Packet Error Checking, in which a CRC-8 (see [`Pec`]) follows the
+//! data of a transaction.  The `_pec` variants of the [`I2cDevice`] methods
+//! append a PEC to what is written, and check the PEC that follows what is
+//! read, failing with [`ResponseCode::PecMismatch`] if it is wrong.
+//!
 
 #![no_std]
 
+use ringbuf::Count;
 use zerocopy::{AsBytes, FromBytes};
 
+pub use smbus_pec::Pec;
+
 use userlib::*;
 
-#[derive(FromPrimitive, PartialEq)]
+#[derive(Copy, Clone, FromPrimitive, PartialEq)]
 pub enum Op {
     WriteRead = 1,
     WriteReadBlock = 2,
+    WriteReadPec = 3,
+    WriteReadBlockPec = 4,
+    WriteBlock = 5,
+    WriteBlockPec = 6,
+    BlockProcessCall = 7,
+    BlockProcessCallPec = 8,
+    Scan = 9,
+    Stats = 10,
+    MuxInterrupts = 11,
+    /// Dumps the server's ring buffers; see `userlib::ringbuf_registry`.
+    DumpRingbufs = userlib::ringbuf_registry::DUMP_OP as isize,
+}
+
+impl Op {
+    /// Returns true if the operation uses SMBus Packet Error Checking.
+    pub fn pec(self) -> bool {
+        match self {
+            Op::WriteReadPec
+            | Op::WriteReadBlockPec
+            | Op::WriteBlockPec
+            | Op::BlockProcessCallPec => true,
+            _ => false,
+        }
+    }
 }
 
 /// The response code returned from the I2C controller (or from the kernel in
 /// the case of [`ResponseCode::Dead`]).  These response codes pretty specific,
 /// not because the caller is expected to necessarily handle them differently,
 /// but to give upstack software some modicum of context surrounding the error.
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Count)]
 #[repr(u32)]
 pub enum ResponseCode {
     /// Server has died
@@ -81,6 +117,10 @@ pub enum ResponseCode {
     BusLockedMux = 20,
     /// I2C controller appeared to be locked and was reset
     ControllerLocked = 21,
+    /// SMBus Packet Error Code received from the device did not match
+    PecMismatch = 22,
+    /// Indicated mux does not report interrupts
+    NoMuxInterrupts = 23,
 }
 
 ///
@@ -118,6 +158,48 @@ pub enum ReservedAddress {
     TenBit11 = 0b1111_111,
 }
 
+///
+/// How to probe for the presence of a device at an address, as when
+/// scanning a bus.  Neither method is entirely benign: some devices act on
+/// a write even when it has no data (some EEPROMs, for example, treat it as
+/// the start of a write cycle), while a read can have side effects (e.g.,
+/// clearing status) on others.  The method should be chosen with the
+/// devices that may be on the bus in mind.
+///
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ProbeMethod {
+    /// Send just the address, with the write bit set: an SMBus Quick
+    /// Command.
+    QuickWrite = 0,
+    /// Read (and discard) a single byte.
+    ReadByte = 1,
+}
+
+/// Size of the bitmap returned by a scan: a bit for each 7-bit address.
+pub const SCAN_BITMAP_SIZE: usize = 128 / 8;
+
+///
+/// Health statistics for a bus -- that is, for a controller and port, or
+/// for a segment of a mux on one -- as returned by [`bus_stats`].  The
+/// counts wrap.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct BusStats {
+    /// Transactions attempted (not including the probes of a scan)
+    pub transactions: u32,
+    /// Transactions NACK'd by the device
+    pub nacks: u32,
+    /// Transactions that timed out with the bus held low
+    pub timeouts: u32,
+    /// Transactions that lost arbitration
+    pub arbitration_losses: u32,
+    /// Resets of the controller (and of the mux, if any) after errors
+    pub resets: u32,
+    /// Failures to select a segment on the mux
+    pub mux_failures: u32,
+}
+
 ///
 /// The port index for a given I2C device.  Some controllers can have multiple
 /// ports (which themselves are connected to different I2C buses), but only
@@ -140,7 +222,7 @@ pub struct PortIndex(pub u8);
 /// A multiplexer identifier for a given I2C bus.  Multiplexer identifiers
 /// need not start at 0.
 ///
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum Mux {
     M1 = 1,
@@ -170,7 +252,7 @@ pub enum Segment {
 /// The 5-tuple that uniquely identifies an I2C device.  The multiplexer and
 /// the segment are optional, but if one is present, the other must be.
 ///
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct I2cDevice {
     pub task: TaskId,
     pub controller: Controller,
@@ -280,6 +362,100 @@ impl I2cDevice {
     }
 }
 
+///
+/// Scans the bus identified by the controller, port and (optionally) mux
+/// and segment, probing each address that isn't reserved (see
+/// [`ReservedAddress`]) with the specified method.  Returns a bitmap of the
+/// addresses that acknowledged: bit `addr % 8` of byte `addr / 8` is set if
+/// there is a device at `addr`.
+///
+pub fn scan(
+    task: TaskId,
+    controller: Controller,
+    port: PortIndex,
+    segment: Option<(Mux, Segment)>,
+    method: ProbeMethod,
+) -> Result<[u8; SCAN_BITMAP_SIZE], ResponseCode> {
+    let mut bitmap = [0u8; SCAN_BITMAP_SIZE];
+    let mut response = 0_usize;
+
+    let (code, _) = sys_send(
+        task,
+        Op::Scan as u16,
+        &Marshal::marshal(&(method as u8, controller, port, segment)),
+        response.as_bytes_mut(),
+        &[Lease::from(&mut bitmap[..])],
+    );
+
+    if code != 0 {
+        Err(ResponseCode::from_u32(code).ok_or(ResponseCode::BadResponse)?)
+    } else {
+        Ok(bitmap)
+    }
+}
+
+///
+/// Returns the health statistics for the bus identified by the controller,
+/// port and (optionally) mux and segment, clearing them afterwards if
+/// `clear` is set.  A bus that has seen no activity has statistics of zero.
+///
+pub fn bus_stats(
+    task: TaskId,
+    controller: Controller,
+    port: PortIndex,
+    segment: Option<(Mux, Segment)>,
+    clear: bool,
+) -> Result<BusStats, ResponseCode> {
+    let mut stats = BusStats::default();
+    let mut response = 0_usize;
+
+    let (code, _) = sys_send(
+        task,
+        Op::Stats as u16,
+        &Marshal::marshal(&(clear as u8, controller, port, segment)),
+        response.as_bytes_mut(),
+        &[Lease::from(stats.as_bytes_mut())],
+    );
+
+    if code != 0 {
+        Err(ResponseCode::from_u32(code).ok_or(ResponseCode::BadResponse)?)
+    } else {
+        Ok(stats)
+    }
+}
+
+///
+/// Returns the segments of the specified mux that have their interrupt line
+/// asserted, as a bitmask in which bit 0 is [`Segment::S1`].  Fails with
+/// [`ResponseCode::NoMuxInterrupts`] if the mux doesn't pass interrupts
+/// through.
+///
+pub fn mux_interrupts(
+    task: TaskId,
+    controller: Controller,
+    port: PortIndex,
+    mux: Mux,
+) -> Result<u8, ResponseCode> {
+    let mut response = 0_u8;
+
+    //
+    // The segment is ignored, but the message carries one all the same.
+    //
+    let (code, _) = sys_send(
+        task,
+        Op::MuxInterrupts as u16,
+        &Marshal::marshal(&(0, controller, port, Some((mux, Segment::S1)))),
+        response.as_bytes_mut(),
+        &[],
+    );
+
+    if code != 0 {
+        Err(ResponseCode::from_u32(code).ok_or(ResponseCode::BadResponse)?)
+    } else {
+        Ok(response)
+    }
+}
+
 impl From<ResponseCode> for u32 {
     fn from(rc: ResponseCode) -> Self {
         rc as u32
@@ -493,4 +669,155 @@ impl I2cDevice {
             Ok(())
         }
     }
+
+    ///
+    /// Like [`read_reg`], but with SMBus Packet Error Checking: the value is
+    /// followed by a PEC byte from the device, which is checked.
+    ///
+    pub fn read_reg_pec<R: AsBytes, V: Default + AsBytes + FromBytes>(
+        &self,
+        reg: R,
+    ) -> Result<V, ResponseCode> {
+        let mut val = V::default();
+
+        self.send(
+            Op::WriteReadPec,
+            &[Lease::from(reg.as_bytes()), Lease::from(val.as_bytes_mut())],
+        )?;
+
+        Ok(val)
+    }
+
+    ///
+    /// Like [`read_block`], but with SMBus Packet Error Checking: the block
+    /// is followed by a PEC byte from the device, which is checked.
+    ///
+    pub fn read_block_pec<R: AsBytes>(
+        &self,
+        reg: R,
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        self.send(
+            Op::WriteReadBlockPec,
+            &[Lease::from(reg.as_bytes()), Lease::from(buf)],
+        )
+    }
+
+    ///
+    /// Like [`write`], but with SMBus Packet Error Checking: the buffer is
+    /// followed by a PEC byte, for the device to check.
+    ///
+    pub fn write_pec(&self, buffer: &[u8]) -> Result<(), ResponseCode> {
+        let empty = [0u8; 1];
+
+        self.send(
+            Op::WriteReadPec,
+            &[Lease::from(buffer), Lease::from(&empty[0..0])],
+        )?;
+
+        Ok(())
+    }
+
+    ///
+    /// Performs an SMBus block write: writes the register, followed by the
+    /// number of bytes in the specified buffer, followed by the buffer
+    /// itself.
+    ///
+    pub fn write_block<R: AsBytes>(
+        &self,
+        reg: R,
+        buf: &[u8],
+    ) -> Result<(), ResponseCode> {
+        self.send(
+            Op::WriteBlock,
+            &[Lease::from(reg.as_bytes()), Lease::from(buf)],
+        )?;
+
+        Ok(())
+    }
+
+    ///
+    /// Like [`write_block`], but with SMBus Packet Error Checking.
+    ///
+    pub fn write_block_pec<R: AsBytes>(
+        &self,
+        reg: R,
+        buf: &[u8],
+    ) -> Result<(), ResponseCode> {
+        self.send(
+            Op::WriteBlockPec,
+            &[Lease::from(reg.as_bytes()), Lease::from(buf)],
+        )?;
+
+        Ok(())
+    }
+
+    ///
+    /// Performs an SMBus block write-block read process call: does a block
+    /// write of `wbuf` (as with [`write_block`]) and then, without an
+    /// intervening STOP, a block read into `rbuf` (as with [`read_block`]),
+    /// returning the number of bytes read.
+    ///
+    pub fn block_process_call<R: AsBytes>(
+        &self,
+        reg: R,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        self.send(
+            Op::BlockProcessCall,
+            &[
+                Lease::from(reg.as_bytes()),
+                Lease::from(wbuf),
+                Lease::from(rbuf),
+            ],
+        )
+    }
+
+    ///
+    /// Like [`block_process_call`], but with SMBus Packet Error Checking.
+    ///
+    pub fn block_process_call_pec<R: AsBytes>(
+        &self,
+        reg: R,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        self.send(
+            Op::BlockProcessCallPec,
+            &[
+                Lease::from(reg.as_bytes()),
+                Lease::from(wbuf),
+                Lease::from(rbuf),
+            ],
+        )
+    }
+
+    fn send(
+        &self,
+        op: Op,
+        leases: &[Lease<'_>],
+    ) -> Result<usize, ResponseCode> {
+        let mut response = 0_usize;
+
+        let (code, _) = sys_send(
+            self.task,
+            op as u16,
+            &Marshal::marshal(&(
+                self.address,
+                self.controller,
+                self.port,
+                self.segment,
+            )),
+            response.as_bytes_mut(),
+            leases,
+        );
+
+        if code != 0 {
+            Err(ResponseCode::from_u32(code)
+                .ok_or(ResponseCode::BadResponse)?)
+        } else {
+            Ok(response)
+        }
+    }
 }
@@ -4,26 +4,14 @@
 
 //! Driver for the ADM1272 hot-swap controller
 
+use crate::pmbus_device::{Faults, Format, Generic, PmbusDevice};
 use drv_i2c_api::*;
 use num_traits::float::FloatCore;
 use pmbus::commands::*;
 use ringbuf::*;
 use userlib::units::*;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Error {
-    BadRead { cmd: u8, code: ResponseCode },
-    BadWrite { cmd: u8, code: ResponseCode },
-    BadData { cmd: u8 },
-    InvalidData { err: pmbus::Error },
-    InvalidConfig,
-}
-
-impl From<pmbus::Error> for Error {
-    fn from(err: pmbus::Error) -> Self {
-        Error::InvalidData { err: err }
-    }
-}
+pub use crate::pmbus_device::Error;
 
 #[allow(dead_code)]
 struct Coefficients {
@@ -33,8 +21,8 @@ struct Coefficients {
 }
 
 pub struct Adm1272 {
-    /// Underlying I2C device
-    device: I2cDevice,
+    /// Underlying PMBus device
+    device: PmbusDevice,
     /// Value of the rsense resistor, in milliohms
     rsense: i32,
     /// Our (cached) coefficients
@@ -45,7 +33,7 @@ pub struct Adm1272 {
 
 impl core::fmt::Display for Adm1272 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "adm1272: {}", &self.device)
+        write!(f, "adm1272: {}", self.device.device())
     }
 }
 
@@ -62,7 +50,7 @@ ringbuf!(Trace, 32, Trace::None);
 impl Adm1272 {
     pub fn new(device: &I2cDevice, rsense: Ohms) -> Self {
         Self {
-            device: *device,
+            device: PmbusDevice::new(device, Format::Direct, Generic),
             rsense: (rsense.0 * 1000.0).round() as i32,
             coefficients: None,
             config: None,
@@ -76,7 +64,7 @@ impl Adm1272 {
             return Ok(*config);
         }
 
-        let config = pmbus_read!(self.device, adm1272::PMON_CONFIG)?;
+        let config = pmbus_read!(self.device.device(), adm1272::PMON_CONFIG)?;
         ringbuf_entry!(Trace::Config(config));
         self.config = Some(config);
 
@@ -88,7 +76,7 @@ impl Adm1272 {
         config: adm1272::PMON_CONFIG::CommandData,
     ) -> Result<(), Error> {
         ringbuf_entry!(Trace::WriteConfig(config));
-        pmbus_write!(self.device, adm1272::PMON_CONFIG, config)
+        pmbus_write!(self.device.device(), adm1272::PMON_CONFIG, config)
     }
 
     //
@@ -211,23 +199,37 @@ impl Adm1272 {
 
     pub fn read_vin(&mut self) -> Result<Volts, Error> {
         self.enable_vin_sampling()?;
-        let vin = pmbus_read!(self.device, adm1272::READ_VIN)?;
-        Ok(Volts(vin.get(&self.load_coefficients()?.voltage)?.0))
+        let coefficients = self.load_coefficients()?.voltage;
+        Ok(Volts(self.device.read_direct(
+            READ_VIN::CommandData::code(),
+            &coefficients,
+        )?))
     }
 
     pub fn read_vout(&mut self) -> Result<Volts, Error> {
         self.enable_vout_sampling()?;
-        let vout = pmbus_read!(self.device, adm1272::READ_VOUT)?;
-        Ok(Volts(vout.get(&self.load_coefficients()?.voltage)?.0))
+        let coefficients = self.load_coefficients()?.voltage;
+        Ok(Volts(self.device.read_direct(
+            READ_VOUT::CommandData::code(),
+            &coefficients,
+        )?))
     }
 
     pub fn read_iout(&mut self) -> Result<Amperes, Error> {
-        let iout = pmbus_read!(self.device, adm1272::READ_IOUT)?;
-        Ok(Amperes(iout.get(&self.load_coefficients()?.current)?.0))
+        let coefficients = self.load_coefficients()?.current;
+        Ok(Amperes(self.device.read_direct(
+            READ_IOUT::CommandData::code(),
+            &coefficients,
+        )?))
     }
 
     pub fn peak_iout(&mut self) -> Result<Amperes, Error> {
-        let iout = pmbus_read!(self.device, adm1272::PEAK_IOUT)?;
-        Ok(Amperes(iout.get(&self.load_coefficients()?.current)?.0))
+        let coefficients = self.load_coefficients()?.current;
+        let cmd = adm1272::PEAK_IOUT::CommandData::code();
+        Ok(Amperes(self.device.read_direct(cmd, &coefficients)?))
+    }
+
+    pub fn read_status(&mut self) -> Result<Faults, Error> {
+        self.device.read_status()
     }
 }
@@ -2,85 +2,73 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+//! Driver for the ISL68224 power controller
+
+use crate::pmbus_device::{Decoder, Faults, Format, PmbusDevice};
 use drv_i2c_api::*;
-use pmbus::commands::isl68224::*;
-use pmbus::*;
+use pmbus::commands::isl68224;
 use userlib::units::*;
 
-pub struct Isl68224 {
-    device: I2cDevice,
-    rail: u8,
-    mode: Option<pmbus::VOutModeCommandData>,
-}
+pub use crate::pmbus_device::Error;
 
-impl core::fmt::Display for Isl68224 {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "isl68224: {}", &self.device)
+/// Decodes output voltage and current with the ISL68224's own commands.
+struct Isl68224Decoder;
+
+impl Decoder for Isl68224Decoder {
+    fn vout(
+        &self,
+        buf: &[u8],
+        mode: pmbus::VOutModeCommandData,
+    ) -> Result<Volts, Error> {
+        let vout = pmbus_decode!(buf, isl68224::READ_VOUT)?;
+        Ok(Volts(vout.get(mode)?.0))
+    }
+
+    fn iout(&self, buf: &[u8]) -> Result<Amperes, Error> {
+        let iout = pmbus_decode!(buf, isl68224::READ_IOUT)?;
+        Ok(Amperes(iout.get()?.0))
     }
 }
 
-#[derive(Debug)]
-pub enum Error {
-    BadRead { cmd: u8, code: ResponseCode },
-    BadWrite { cmd: u8, code: ResponseCode },
-    BadData { cmd: u8 },
-    InvalidData { err: pmbus::Error },
+pub struct Isl68224 {
+    device: PmbusDevice<Isl68224Decoder>,
 }
 
-impl From<pmbus::Error> for Error {
-    fn from(err: pmbus::Error) -> Self {
-        Error::InvalidData { err: err }
+impl core::fmt::Display for Isl68224 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "isl68224: {}", self.device.device())
     }
 }
 
 impl Isl68224 {
     pub fn new(device: &I2cDevice, rail: u8) -> Self {
         Isl68224 {
-            device: *device,
-            rail: rail,
-            mode: None,
+            device: PmbusDevice::new_rail(
+                device,
+                rail,
+                Format::Linear11,
+                Isl68224Decoder,
+            ),
         }
     }
 
-    fn read_mode(&mut self) -> Result<pmbus::VOutModeCommandData, Error> {
-        Ok(match self.mode {
-            None => {
-                let mode = pmbus_read!(self.device, commands::VOUT_MODE)?;
-                self.mode = Some(mode);
-                mode
-            }
-            Some(mode) => mode,
-        })
-    }
-
-    fn set_rail(&mut self) -> Result<(), Error> {
-        let page = PAGE::CommandData(self.rail);
-        pmbus_write!(self.device, PAGE, page)
-    }
-
     pub fn turn_off(&mut self) -> Result<(), Error> {
-        self.set_rail()?;
-        let mut operation = pmbus_read!(self.device, OPERATION)?;
-        operation.set_on_off_state(OPERATION::OnOffState::Off);
-        pmbus_write!(self.device, OPERATION, operation)
+        self.device.turn_off()
     }
 
     pub fn turn_on(&mut self) -> Result<(), Error> {
-        self.set_rail()?;
-        let mut operation = pmbus_read!(self.device, OPERATION)?;
-        operation.set_on_off_state(OPERATION::OnOffState::On);
-        pmbus_write!(self.device, OPERATION, operation)
+        self.device.turn_on()
     }
 
     pub fn read_vout(&mut self) -> Result<Volts, Error> {
-        self.set_rail()?;
-        let vout = pmbus_read!(self.device, READ_VOUT)?;
-        Ok(Volts(vout.get(self.read_mode()?)?.0))
+        self.device.read_vout()
     }
 
     pub fn read_iout(&mut self) -> Result<Amperes, Error> {
-        self.set_rail()?;
-        let iout = pmbus_read!(self.device, READ_IOUT)?;
-        Ok(Amperes(iout.get()?.0))
+        self.device.read_iout()
+    }
+
+    pub fn read_status(&mut self) -> Result<Faults, Error> {
+        self.device.read_status()
     }
 }
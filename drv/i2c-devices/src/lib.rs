@@ -14,6 +14,7 @@
 //! - [`max31790`]: MAX31790 fan controller
 //! - [`mcp9808`]: MCP9808 temperature sensor
 //! - [`pct2075`]: PCT2075 temperature sensor
+//! - [`pmbus_device`]: Generic PMBus device
 //! - [`raa229618`]: RAA229618 power controller
 //! - [`tmp116`]: TMP116 temperature sensor
 //! - [`tps546b24a`]: TPS546B24A buck converter
@@ -58,6 +59,26 @@ macro_rules! pmbus_read {
     };
 }
 
+macro_rules! pmbus_decode {
+    ($buf:expr, $cmd:ident) => {
+        match $cmd::CommandData::from_slice($buf) {
+            Some(data) => Ok(data),
+            None => Err(Error::BadData {
+                cmd: $cmd::CommandData::code(),
+            }),
+        }
+    };
+
+    ($buf:expr, $dev:ident::$cmd:ident) => {
+        match $dev::$cmd::CommandData::from_slice($buf) {
+            Some(data) => Ok(data),
+            None => Err(Error::BadData {
+                cmd: $dev::$cmd::CommandData::code(),
+            }),
+        }
+    };
+}
+
 macro_rules! pmbus_write {
     ($device:expr, $dev:ident::$cmd:ident, $data:expr) => {{
         let mut payload = [0u8; $dev::$cmd::CommandData::len() + 1];
@@ -100,6 +121,7 @@ pub mod max31790;
 pub mod max6634;
 pub mod mcp9808;
 pub mod pct2075;
+pub mod pmbus_device;
 pub mod raa229618;
 pub mod tmp116;
 pub mod tps546b24a;
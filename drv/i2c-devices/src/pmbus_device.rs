@@ -0,0 +1,447 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Generic driver for PMBus devices
+//!
+//! This driver works with any device that complies with PMBus.  It decodes
+//! telemetry in each of the formats that PMBus defines for it:
+//!
+//! - Output voltage is in the format given by `VOUT_MODE` (as decoded by
+//!   the `pmbus` crate), unless the device is in DIRECT format.
+//!
+//! - Other telemetry is in LINEAR11 or DIRECT, depending on the device (see
+//!   [`Format`]).
+//!
+//! A device that isn't in DIRECT format may define its own commands for
+//! output voltage and current; its [`Decoder`] decodes them.
+//!
+//! For DIRECT, the coefficients are those that the device reports for the
+//! command via `COEFFICIENTS`.  A device whose coefficients depend on its
+//! configuration (and which therefore may not implement `COEFFICIENTS`) can
+//! instead supply them to [`PmbusDevice::read_direct`].
+//!
+//! A [`PmbusDevice`] can be for one rail of a multi-rail controller, in
+//! which case it selects that rail (via `PAGE`) before every command.  The
+//! drivers for particular PMBus devices are thin specializations of this
+//! one.
+
+use drv_i2c_api::*;
+use pmbus::commands::*;
+use userlib::units::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    BadRead { cmd: u8, code: ResponseCode },
+    BadWrite { cmd: u8, code: ResponseCode },
+    BadData { cmd: u8 },
+    InvalidData { err: pmbus::Error },
+    InvalidConfig,
+}
+
+impl From<pmbus::Error> for Error {
+    fn from(err: pmbus::Error) -> Self {
+        Error::InvalidData { err: err }
+    }
+}
+
+/// The format of telemetry other than output voltage.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    /// LINEAR11, with output voltage and current decoded by the device's
+    /// [`Decoder`]
+    Linear11,
+    /// DIRECT, with coefficients as reported by `COEFFICIENTS`
+    Direct,
+}
+
+///
+/// A device's decoding of its responses to `READ_VOUT` and `READ_IOUT`.  By
+/// default, these are decoded as PMBus defines them; a device that defines
+/// its own commands (in the `pmbus` crate) decodes them with those.
+///
+pub trait Decoder {
+    /// Decodes a response to `READ_VOUT`, given the output voltage mode.
+    fn vout(
+        &self,
+        buf: &[u8],
+        mode: pmbus::VOutModeCommandData,
+    ) -> Result<Volts, Error> {
+        let vout = pmbus_decode!(buf, READ_VOUT)?;
+        Ok(Volts(vout.get(mode)?.0))
+    }
+
+    /// Decodes a response to `READ_IOUT`, as LINEAR11.
+    fn iout(&self, buf: &[u8]) -> Result<Amperes, Error> {
+        match buf {
+            [lo, hi] => {
+                let raw = u16::from_le_bytes([*lo, *hi]);
+                Ok(Amperes(pmbus::Linear11(raw).to_real()))
+            }
+            _ => Err(Error::BadData {
+                cmd: READ_IOUT::CommandData::code(),
+            }),
+        }
+    }
+}
+
+/// The decoding of a device that defines no commands of its own.
+pub struct Generic;
+
+impl Decoder for Generic {}
+
+/// The telemetry that we read, each of which has its own coefficients.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Reading {
+    Vin,
+    Iin,
+    Vout,
+    Iout,
+    Temperature,
+    Pout,
+    Pin,
+}
+
+impl Reading {
+    const COUNT: usize = 7;
+
+    fn code(&self) -> u8 {
+        match self {
+            Reading::Vin => READ_VIN::CommandData::code(),
+            Reading::Iin => READ_IIN::CommandData::code(),
+            Reading::Vout => READ_VOUT::CommandData::code(),
+            Reading::Iout => READ_IOUT::CommandData::code(),
+            Reading::Temperature => READ_TEMPERATURE_1::CommandData::code(),
+            Reading::Pout => READ_POUT::CommandData::code(),
+            Reading::Pin => READ_PIN::CommandData::code(),
+        }
+    }
+}
+
+///
+/// Decodes the response to `COEFFICIENTS`: m and b (each a signed, little
+/// endian word), then R (a signed byte).
+///
+fn decode_coefficients(buf: &[u8]) -> Option<pmbus::Coefficients> {
+    match buf {
+        [m0, m1, b0, b1, r] => Some(pmbus::Coefficients {
+            m: i16::from_le_bytes([*m0, *m1]).into(),
+            b: i16::from_le_bytes([*b0, *b1]).into(),
+            R: *r as i8,
+        }),
+        _ => None,
+    }
+}
+
+//
+// The bits of `STATUS_WORD` that indicate something to report in one of
+// the sub-status registers.
+//
+const STATUS_VOUT_BIT: u16 = 1 << 15;
+const STATUS_IOUT_BIT: u16 = 1 << 14;
+const STATUS_INPUT_BIT: u16 = 1 << 13;
+const STATUS_MFR_SPECIFIC_BIT: u16 = 1 << 12;
+const STATUS_FANS_BIT: u16 = 1 << 10;
+const STATUS_OTHER_BIT: u16 = 1 << 9;
+const STATUS_TEMPERATURE_BIT: u16 = 1 << 2;
+const STATUS_CML_BIT: u16 = 1 << 1;
+
+///
+/// The faults (and warnings) that a device reports: `STATUS_WORD`, along
+/// with each of the sub-status registers that it indicates has something to
+/// report.  (A sub-status register that it doesn't indicate isn't read.)
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Faults {
+    pub status: STATUS_WORD::CommandData,
+    pub vout: Option<STATUS_VOUT::CommandData>,
+    pub iout: Option<STATUS_IOUT::CommandData>,
+    pub input: Option<STATUS_INPUT::CommandData>,
+    pub temperature: Option<STATUS_TEMPERATURE::CommandData>,
+    pub cml: Option<STATUS_CML::CommandData>,
+    pub other: Option<STATUS_OTHER::CommandData>,
+    pub mfr_specific: Option<STATUS_MFR_SPECIFIC::CommandData>,
+    pub fans_1_2: Option<STATUS_FANS_1_2::CommandData>,
+}
+
+impl Faults {
+    /// Returns true if the device has nothing to report.
+    pub fn is_empty(&self) -> bool {
+        self.status.0 == 0
+    }
+}
+
+pub struct PmbusDevice<D: Decoder = Generic> {
+    device: I2cDevice,
+    /// Rail (page) to select before each command, if any
+    rail: Option<u8>,
+    format: Format,
+    /// Our (cached) output voltage mode
+    mode: Option<pmbus::VOutModeCommandData>,
+    /// Our (cached) coefficients, by reading
+    coefficients: [Option<pmbus::Coefficients>; Reading::COUNT],
+    decoder: D,
+}
+
+impl<D: Decoder> core::fmt::Display for PmbusDevice<D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.rail {
+            Some(rail) => write!(f, "pmbus: {} rail {}", &self.device, rail),
+            None => write!(f, "pmbus: {}", &self.device),
+        }
+    }
+}
+
+impl<D: Decoder> PmbusDevice<D> {
+    /// Returns a driver for a device with a single rail.
+    pub fn new(device: &I2cDevice, format: Format, decoder: D) -> Self {
+        Self {
+            device: *device,
+            rail: None,
+            format: format,
+            mode: None,
+            coefficients: [None; Reading::COUNT],
+            decoder: decoder,
+        }
+    }
+
+    /// Returns a driver for one rail of a multi-rail device.
+    pub fn new_rail(
+        device: &I2cDevice,
+        rail: u8,
+        format: Format,
+        decoder: D,
+    ) -> Self {
+        Self {
+            rail: Some(rail),
+            ..Self::new(device, format, decoder)
+        }
+    }
+
+    pub fn device(&self) -> &I2cDevice {
+        &self.device
+    }
+
+    fn select_rail(&self) -> Result<(), Error> {
+        match self.rail {
+            Some(rail) => {
+                pmbus_write!(self.device, PAGE, PAGE::CommandData(rail))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn read_bytes(&self, cmd: u8) -> Result<[u8; 2], Error> {
+        self.select_rail()?;
+
+        self.device.read_reg::<u8, [u8; 2]>(cmd).map_err(|code| {
+            Error::BadRead {
+                cmd: cmd,
+                code: code,
+            }
+        })
+    }
+
+    fn read_word(&self, cmd: u8) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_bytes(cmd)?))
+    }
+
+    /// Returns the output voltage mode.
+    pub fn vout_mode(&mut self) -> Result<pmbus::VOutModeCommandData, Error> {
+        if let Some(mode) = self.mode {
+            return Ok(mode);
+        }
+
+        self.select_rail()?;
+        let mode = pmbus_read!(self.device, VOUT_MODE)?;
+        self.mode = Some(mode);
+
+        Ok(mode)
+    }
+
+    /// Returns the DIRECT coefficients that the device reports for `cmd`.
+    pub fn read_coefficients(
+        &self,
+        cmd: u8,
+    ) -> Result<pmbus::Coefficients, Error> {
+        let code = COEFFICIENTS::CommandData::code();
+        let mut buf = [0u8; 5];
+
+        self.select_rail()?;
+
+        //
+        // The second byte of the request indicates that we want the
+        // coefficients for reading (rather than writing) the command.
+        //
+        match self.device.block_process_call(code, &[cmd, 1], &mut buf) {
+            Ok(len) => buf
+                .get(..len)
+                .and_then(decode_coefficients)
+                .ok_or(Error::BadData { cmd: code }),
+            Err(err) => Err(Error::BadRead {
+                cmd: code,
+                code: err,
+            }),
+        }
+    }
+
+    fn coefficients(
+        &mut self,
+        reading: Reading,
+    ) -> Result<pmbus::Coefficients, Error> {
+        if let Some(coefficients) = self.coefficients[reading as usize] {
+            return Ok(coefficients);
+        }
+
+        let coefficients = self.read_coefficients(reading.code())?;
+        self.coefficients[reading as usize] = Some(coefficients);
+
+        Ok(coefficients)
+    }
+
+    /// Reads `cmd`, in DIRECT format with the given coefficients.
+    pub fn read_direct(
+        &self,
+        cmd: u8,
+        coefficients: &pmbus::Coefficients,
+    ) -> Result<f32, Error> {
+        Ok(pmbus::Direct(self.read_word(cmd)?, *coefficients).to_real())
+    }
+
+    fn read_value(&mut self, reading: Reading) -> Result<f32, Error> {
+        match self.format {
+            Format::Linear11 => {
+                Ok(pmbus::Linear11(self.read_word(reading.code())?).to_real())
+            }
+            Format::Direct => {
+                let coefficients = self.coefficients(reading)?;
+                self.read_direct(reading.code(), &coefficients)
+            }
+        }
+    }
+
+    pub fn read_vin(&mut self) -> Result<Volts, Error> {
+        Ok(Volts(self.read_value(Reading::Vin)?))
+    }
+
+    pub fn read_iin(&mut self) -> Result<Amperes, Error> {
+        Ok(Amperes(self.read_value(Reading::Iin)?))
+    }
+
+    pub fn read_vout(&mut self) -> Result<Volts, Error> {
+        match self.format {
+            Format::Linear11 => {
+                let mode = self.vout_mode()?;
+                let buf = self.read_bytes(Reading::Vout.code())?;
+                self.decoder.vout(&buf, mode)
+            }
+            Format::Direct => Ok(Volts(self.read_value(Reading::Vout)?)),
+        }
+    }
+
+    pub fn read_iout(&mut self) -> Result<Amperes, Error> {
+        match self.format {
+            Format::Linear11 => {
+                let buf = self.read_bytes(Reading::Iout.code())?;
+                self.decoder.iout(&buf)
+            }
+            Format::Direct => Ok(Amperes(self.read_value(Reading::Iout)?)),
+        }
+    }
+
+    pub fn read_temperature(&mut self) -> Result<Celsius, Error> {
+        Ok(Celsius(self.read_value(Reading::Temperature)?))
+    }
+
+    pub fn read_pout(&mut self) -> Result<Watts, Error> {
+        Ok(Watts(self.read_value(Reading::Pout)?))
+    }
+
+    pub fn read_pin(&mut self) -> Result<Watts, Error> {
+        Ok(Watts(self.read_value(Reading::Pin)?))
+    }
+
+    /// Reads `STATUS_WORD`, and the sub-status registers that it indicates.
+    pub fn read_status(&mut self) -> Result<Faults, Error> {
+        self.select_rail()?;
+        let status = pmbus_read!(self.device, STATUS_WORD)?;
+        let set = |bit: u16| status.0 & bit != 0;
+
+        let mut faults = Faults {
+            status: status,
+            vout: None,
+            iout: None,
+            input: None,
+            temperature: None,
+            cml: None,
+            other: None,
+            mfr_specific: None,
+            fans_1_2: None,
+        };
+
+        if set(STATUS_VOUT_BIT) {
+            faults.vout = Some(pmbus_read!(self.device, STATUS_VOUT)?);
+        }
+
+        if set(STATUS_IOUT_BIT) {
+            faults.iout = Some(pmbus_read!(self.device, STATUS_IOUT)?);
+        }
+
+        if set(STATUS_INPUT_BIT) {
+            faults.input = Some(pmbus_read!(self.device, STATUS_INPUT)?);
+        }
+
+        if set(STATUS_TEMPERATURE_BIT) {
+            faults.temperature =
+                Some(pmbus_read!(self.device, STATUS_TEMPERATURE)?);
+        }
+
+        if set(STATUS_CML_BIT) {
+            faults.cml = Some(pmbus_read!(self.device, STATUS_CML)?);
+        }
+
+        if set(STATUS_OTHER_BIT) {
+            faults.other = Some(pmbus_read!(self.device, STATUS_OTHER)?);
+        }
+
+        if set(STATUS_MFR_SPECIFIC_BIT) {
+            faults.mfr_specific =
+                Some(pmbus_read!(self.device, STATUS_MFR_SPECIFIC)?);
+        }
+
+        if set(STATUS_FANS_BIT) {
+            faults.fans_1_2 = Some(pmbus_read!(self.device, STATUS_FANS_1_2)?);
+        }
+
+        Ok(faults)
+    }
+
+    /// Clears any faults that the device has latched.
+    pub fn clear_faults(&mut self) -> Result<(), Error> {
+        let code = CLEAR_FAULTS::CommandData::code();
+
+        self.select_rail()?;
+        self.device.write(&[code]).map_err(|err| Error::BadWrite {
+            cmd: code,
+            code: err,
+        })
+    }
+
+    fn set_on_off_state(
+        &mut self,
+        state: OPERATION::OnOffState,
+    ) -> Result<(), Error> {
+        self.select_rail()?;
+        let mut operation = pmbus_read!(self.device, OPERATION)?;
+        operation.set_on_off_state(state);
+        pmbus_write!(self.device, OPERATION, operation)
+    }
+
+    pub fn turn_on(&mut self) -> Result<(), Error> {
+        self.set_on_off_state(OPERATION::OnOffState::On)
+    }
+
+    pub fn turn_off(&mut self) -> Result<(), Error> {
+        self.set_on_off_state(OPERATION::OnOffState::Off)
+    }
+}
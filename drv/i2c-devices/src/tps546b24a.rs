@@ -4,61 +4,62 @@
 
 //! Driver for the TPS546B24A buck converter
 
+use crate::pmbus_device::{Decoder, Faults, Format, PmbusDevice};
 use drv_i2c_api::*;
-use pmbus::commands::*;
+use pmbus::commands::tps546b24a;
 use userlib::units::*;
 
-pub struct Tps546b24a {
-    device: I2cDevice,
-    mode: Option<pmbus::VOutModeCommandData>,
-}
+pub use crate::pmbus_device::Error;
 
-impl core::fmt::Display for Tps546b24a {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "tps546b24a: {}", &self.device)
+/// Decodes output voltage and current with the TPS546B24A's own commands.
+struct Tps546b24aDecoder;
+
+impl Decoder for Tps546b24aDecoder {
+    fn vout(
+        &self,
+        buf: &[u8],
+        mode: pmbus::VOutModeCommandData,
+    ) -> Result<Volts, Error> {
+        let vout = pmbus_decode!(buf, tps546b24a::READ_VOUT)?;
+        Ok(Volts(vout.get(mode)?.0))
+    }
+
+    fn iout(&self, buf: &[u8]) -> Result<Amperes, Error> {
+        let iout = pmbus_decode!(buf, tps546b24a::READ_IOUT)?;
+        Ok(Amperes(iout.get()?.0))
     }
 }
 
-#[derive(Debug)]
-pub enum Error {
-    BadRead { cmd: u8, code: ResponseCode },
-    BadWrite { cmd: u8, code: ResponseCode },
-    BadData { cmd: u8 },
-    InvalidData { err: pmbus::Error },
+pub struct Tps546b24a {
+    device: PmbusDevice<Tps546b24aDecoder>,
 }
 
-impl From<pmbus::Error> for Error {
-    fn from(err: pmbus::Error) -> Self {
-        Error::InvalidData { err: err }
+impl core::fmt::Display for Tps546b24a {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "tps546b24a: {}", self.device.device())
     }
 }
 
 impl Tps546b24a {
     pub fn new(device: &I2cDevice) -> Self {
         Tps546b24a {
-            device: *device,
-            mode: None,
+            device: PmbusDevice::new(
+                device,
+                Format::Linear11,
+                Tps546b24aDecoder,
+            ),
         }
     }
 
-    fn read_mode(&mut self) -> Result<pmbus::VOutModeCommandData, Error> {
-        Ok(match self.mode {
-            None => {
-                let mode = pmbus_read!(self.device, VOUT_MODE)?;
-                self.mode = Some(mode);
-                mode
-            }
-            Some(mode) => mode,
-        })
-    }
-
     pub fn read_vout(&mut self) -> Result<Volts, Error> {
-        let vout = pmbus_read!(self.device, tps546b24a::READ_VOUT)?;
-        Ok(Volts(vout.get(self.read_mode()?)?.0))
+        self.device.read_vout()
     }
 
     pub fn read_iout(&mut self) -> Result<Amperes, Error> {
-        let iout = pmbus_read!(self.device, tps546b24a::READ_IOUT)?;
-        Ok(Amperes(iout.get()?.0))
+        self.device.read_iout()
+    }
+
+    pub fn read_status(&mut self) -> Result<Faults, Error> {
+        self.device.read_status()
     }
 }
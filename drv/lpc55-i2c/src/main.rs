@@ -38,7 +38,13 @@ enum Op {
 #[repr(u32)]
 enum ResponseCode {
     BadArg = 2,
+    /// The controller isn't in the state that we expected, for some reason
+    /// other than a NACK.
     Busy = 3,
+    /// The device didn't acknowledge its address.
+    NoDevice = 4,
+    /// The device didn't acknowledge a byte that we wrote.
+    Nack = 5,
 }
 
 impl From<ResponseCode> for u32 {
@@ -176,6 +182,19 @@ fn muck_with_gpios(syscon: &Syscon) {
         .unwrap();
 }
 
+/// Returns why the controller isn't in the state that we expected.
+fn failure(i2c: &device::i2c0::RegisterBlock) -> ResponseCode {
+    let state = i2c.stat.read().mststate();
+
+    if state.is_nack_address() {
+        ResponseCode::NoDevice
+    } else if state.is_nack_data() {
+        ResponseCode::Nack
+    } else {
+        ResponseCode::Busy
+    }
+}
+
 fn write_a_buffer(
     i2c: &device::i2c0::RegisterBlock,
     mut txs: Transmit,
@@ -192,7 +211,7 @@ fn write_a_buffer(
     }
 
     if !i2c.stat.read().mststate().is_transmit_ready() {
-        return Err(ResponseCode::Busy);
+        return Err(failure(i2c));
     }
 
     let borrow = txs.caller.borrow(0);
@@ -210,7 +229,7 @@ fn write_a_buffer(
         }
 
         if !i2c.stat.read().mststate().is_transmit_ready() {
-            return Err(ResponseCode::Busy);
+            return Err(failure(i2c));
         }
     }
 
@@ -219,7 +238,7 @@ fn write_a_buffer(
     while i2c.stat.read().mstpending().is_in_progress() {}
 
     if !i2c.stat.read().mststate().is_idle() {
-        return Err(ResponseCode::Busy);
+        return Err(failure(i2c));
     }
 
     txs.caller.reply(());
@@ -238,7 +257,7 @@ fn read_a_buffer(
     while i2c.stat.read().mstpending().is_in_progress() {}
 
     if !i2c.stat.read().mststate().is_receive_ready() {
-        return Err(ResponseCode::BadArg);
+        return Err(failure(i2c));
     }
 
     let borrow = txs.caller.borrow(0);
@@ -252,7 +271,7 @@ fn read_a_buffer(
         while i2c.stat.read().mstpending().is_in_progress() {}
 
         if !i2c.stat.read().mststate().is_receive_ready() {
-            return Err(ResponseCode::BadArg);
+            return Err(failure(i2c));
         }
 
         txs.pos += 1;
@@ -266,7 +285,7 @@ fn read_a_buffer(
     while i2c.stat.read().mstpending().is_in_progress() {}
 
     if !i2c.stat.read().mststate().is_idle() {
-        return Err(ResponseCode::BadArg);
+        return Err(failure(i2c));
     }
 
     txs.caller.reply(());
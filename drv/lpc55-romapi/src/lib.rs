@@ -691,7 +691,8 @@ pub fn get_cmpa_data(
     offset: u32,
     len: u32,
 ) -> Result<(), FlashStatus> {
-    assert!(len <= (data.len() as u32));
+    // len is in bytes
+    assert!(len as usize <= core::mem::size_of_val(data));
 
     let mut f: FlashConfig = Default::default();
     f.mode_config.sys_freq_in_mhz = 100;
@@ -720,6 +721,44 @@ pub fn get_cmpa_data(
     })
 }
 
+pub fn get_cfpa_data(
+    data: &mut [u32],
+    offset: u32,
+    len: u32,
+) -> Result<(), FlashStatus> {
+    // len is in bytes
+    assert!(len as usize <= core::mem::size_of_val(data));
+
+    let mut f: FlashConfig = Default::default();
+    f.mode_config.sys_freq_in_mhz = 100;
+
+    handle_flash_status(unsafe {
+        (bootloader_tree()
+            .flash_driver
+            .version1_flash_driver
+            .flash_init)(&mut f)
+    })?;
+
+    handle_flash_status(unsafe {
+        (bootloader_tree()
+            .flash_driver
+            .version1_flash_driver
+            .ffr_init)(&mut f)
+    })?;
+
+    handle_flash_status(unsafe {
+        (bootloader_tree()
+            .flash_driver
+            .version1_flash_driver
+            .ffr_get_customer_infield_data)(
+            &mut f,
+            data.as_mut_ptr(),
+            offset,
+            len,
+        )
+    })
+}
+
 // Keep this as a sample function for now
 pub fn get_bootloader_version() -> u32 {
     let version = &bootloader_tree().version;
@@ -8,6 +8,12 @@
 //!
 //! # IPC protocol
 //!
+//! This speaks the protocol of `drv-spi-api`: each of `read`, `write` and
+//! `exchange` takes the index of the device as its message, which must be 0
+//! (this part being the one device).  As we are an SPI device rather than a
+//! controller, there is no chip select to control, and `lock` and `release`
+//! are refused.
+//!
 //! ## `read` (1)
 //!
 //! Reads the buffer into lease #0. Returns when completed
@@ -19,7 +25,9 @@
 //!
 //! ## `exchange` (3)
 //!
-//! Sends the contents of lease #0 and writes received data into lease #1
+//! Sends the contents of lease #0 and writes received data into lease #1.
+//! Lease #1 may be longer than lease #0, in which case zeros are sent once
+//! lease #0 is exhausted; it may not be shorter.
 
 #![no_std]
 #![no_main]
@@ -27,34 +35,29 @@
 use drv_lpc55_gpio_api::*;
 use drv_lpc55_spi as spi_core;
 use drv_lpc55_syscon_api::{Peripheral, Syscon};
+use drv_spi_api::{Operation, SpiError};
 use lpc55_pac as device;
 use userlib::*;
 
 task_slot!(SYSCON, syscon_driver);
 task_slot!(GPIO, gpio_driver);
 
-#[repr(u32)]
-enum ResponseCode {
-    BadArg = 2,
-    Busy = 3,
-}
-
-// Read/Write is defined from the perspective of the SPI device
-#[derive(FromPrimitive, PartialEq)]
-enum Op {
-    Read = 1,
-    Write = 2,
-    Exchange = 3,
-}
-
 struct Transmit {
     task: hl::Caller<()>,
+    /// Number of bytes to clock
     len: usize,
+    /// Number of bytes sent
     rpos: usize,
+    /// Number of bytes to send from `rlease_num`; after these, we send zeros
+    rlen: usize,
     rlease_num: usize,
+    /// Number of bytes received
     wpos: usize,
+    /// Number of bytes to receive into `wlease_num`; after these, we discard
+    /// what we receive
+    wlen: usize,
     wlease_num: usize,
-    op: Op,
+    op: Operation,
 }
 
 struct SpiDat<'a> {
@@ -62,14 +65,6 @@ struct SpiDat<'a> {
     dat: Option<Transmit>,
 }
 
-// TODO: it is super unfortunate to have to write this by hand, but deriving
-// ToPrimitive makes us check at runtime whether the value fits
-impl From<ResponseCode> for u32 {
-    fn from(rc: ResponseCode) -> Self {
-        rc as u32
-    }
-}
-
 #[export_name = "main"]
 fn main() -> ! {
     let syscon = Syscon::from(SYSCON.get_task_id());
@@ -115,9 +110,12 @@ fn main() -> ! {
 
     sys_irq_control(1, true);
 
+    // Our messages are just the index of the device.
+    let mut buffer = [0u8; 1];
+
     loop {
         hl::recv(
-            &mut [],
+            &mut buffer,
             mask,
             &mut dat,
             |datref, bits| {
@@ -132,7 +130,7 @@ fn main() -> ! {
 
                     if let Some(txs) = &datref.dat {
                         if txs.rpos == txs.len && txs.wpos == txs.len {
-                            if txs.op == Op::Read {
+                            if txs.op == Operation::Read {
                                 datref.spi.disable_tx();
                             }
                             core::mem::replace(&mut datref.dat, None)
@@ -145,62 +143,83 @@ fn main() -> ! {
                 sys_irq_control(1, true);
             },
             |datref, op, msg| match op {
-                Op::Write => {
-                    let ((), caller) =
-                        msg.fixed_with_leases(1).ok_or(ResponseCode::BadArg)?;
+                Operation::Write => {
+                    let (&device_index, caller) = msg
+                        .fixed_with_leases::<u8, ()>(1)
+                        .ok_or(SpiError::BadArg)?;
+
+                    check_device(device_index)?;
 
                     // Deny incoming transfers if we're already running one.
                     if datref.dat.is_some() {
-                        return Err(ResponseCode::Busy);
+                        return Err(SpiError::Busy);
                     }
 
                     let borrow = caller.borrow(0);
 
                     let borrow_info =
-                        borrow.info().ok_or(ResponseCode::BadArg)?;
+                        borrow.info().ok_or(SpiError::BadLeaseArg)?;
 
                     if !borrow_info.attributes.contains(LeaseAttributes::READ) {
-                        return Err(ResponseCode::BadArg);
+                        return Err(SpiError::BadLeaseAttributes);
                     }
 
+                    if borrow_info.len == 0 {
+                        return Err(SpiError::BadTransferSize);
+                    }
+
+                    // We don't receive anything, so reception is done.
                     datref.dat = Some(Transmit {
                         task: caller,
-                        rpos: borrow_info.len,
-                        wpos: 0,
                         len: borrow_info.len,
-                        op: Op::Write,
+                        rpos: 0,
+                        rlen: borrow_info.len,
                         rlease_num: 0,
+                        wpos: borrow_info.len,
+                        wlen: 0,
                         wlease_num: 0,
+                        op: Operation::Write,
                     });
 
                     datref.spi.enable_tx();
 
                     Ok(())
                 }
-                Op::Read => {
-                    let ((), caller) =
-                        msg.fixed_with_leases(1).ok_or(ResponseCode::BadArg)?;
+                Operation::Read => {
+                    let (&device_index, caller) = msg
+                        .fixed_with_leases::<u8, ()>(1)
+                        .ok_or(SpiError::BadArg)?;
+
+                    check_device(device_index)?;
 
                     if datref.dat.is_some() {
-                        return Err(ResponseCode::Busy);
+                        return Err(SpiError::Busy);
                     }
 
                     let borrow = caller.borrow(0);
                     let borrow_info =
-                        borrow.info().ok_or(ResponseCode::BadArg)?;
+                        borrow.info().ok_or(SpiError::BadLeaseArg)?;
                     if !borrow_info.attributes.contains(LeaseAttributes::WRITE)
                     {
-                        return Err(ResponseCode::BadArg);
+                        return Err(SpiError::BadLeaseAttributes);
+                    }
+
+                    if borrow_info.len == 0 {
+                        return Err(SpiError::BadTransferSize);
                     }
 
+                    // We don't send anything (see write_byte), so sending is
+                    // done.
                     datref.dat = Some(Transmit {
                         task: caller,
-                        rpos: borrow_info.len,
-                        wpos: 0,
                         len: borrow_info.len,
-                        op: Op::Read,
+                        rpos: borrow_info.len,
+                        rlen: 0,
                         rlease_num: 0,
+                        wpos: 0,
+                        wlen: borrow_info.len,
                         wlease_num: 0,
+                        op: Operation::Read,
                     });
 
                     // Turning off receive without send is difficult (requires a
@@ -211,40 +230,49 @@ fn main() -> ! {
 
                     Ok(())
                 }
-                Op::Exchange => {
-                    let ((), caller) =
-                        msg.fixed_with_leases(2).ok_or(ResponseCode::BadArg)?;
+                Operation::Exchange => {
+                    let (&device_index, caller) = msg
+                        .fixed_with_leases::<u8, ()>(2)
+                        .ok_or(SpiError::BadArg)?;
+
+                    check_device(device_index)?;
 
                     if datref.dat.is_some() {
-                        return Err(ResponseCode::Busy);
+                        return Err(SpiError::Busy);
                     }
 
                     let borrow_send = caller.borrow(0);
                     let send_info =
-                        borrow_send.info().ok_or(ResponseCode::BadArg)?;
+                        borrow_send.info().ok_or(SpiError::BadSource)?;
                     if !send_info.attributes.contains(LeaseAttributes::READ) {
-                        return Err(ResponseCode::BadArg);
+                        return Err(SpiError::BadSourceAttributes);
                     }
 
                     let borrow_recv = caller.borrow(1);
                     let recv_info =
-                        borrow_recv.info().ok_or(ResponseCode::BadArg)?;
+                        borrow_recv.info().ok_or(SpiError::BadSink)?;
                     if !recv_info.attributes.contains(LeaseAttributes::WRITE) {
-                        return Err(ResponseCode::BadArg);
+                        return Err(SpiError::BadSinkAttributes);
+                    }
+
+                    if recv_info.len < send_info.len {
+                        return Err(SpiError::ShortSinkLength);
                     }
 
-                    if recv_info.len != send_info.len {
-                        return Err(ResponseCode::BadArg);
+                    if recv_info.len == 0 {
+                        return Err(SpiError::BadTransferSize);
                     }
 
                     datref.dat = Some(Transmit {
                         task: caller,
+                        len: recv_info.len,
                         rpos: 0,
+                        rlen: send_info.len,
                         rlease_num: 0,
                         wpos: 0,
+                        wlen: recv_info.len,
                         wlease_num: 1,
-                        len: recv_info.len,
-                        op: Op::Exchange,
+                        op: Operation::Exchange,
                     });
 
                     datref.spi.enable_tx();
@@ -252,11 +280,24 @@ fn main() -> ! {
 
                     Ok(())
                 }
+                Operation::Lock | Operation::Release => {
+                    // We have no chip select to control.
+                    Err(SpiError::BadArg)
+                }
             },
         );
     }
 }
 
+/// Checks that a client is addressing the one device that we are.
+fn check_device(device_index: u8) -> Result<(), SpiError> {
+    if device_index == 0 {
+        Ok(())
+    } else {
+        Err(SpiError::BadDevice)
+    }
+}
+
 fn turn_on_flexcomm(syscon: &Syscon) {
     // HSLSPI = High Speed Spi = Flexcomm 8
     // The L stands for Let this just be named consistently for once
@@ -334,7 +375,7 @@ fn muck_with_gpios(syscon: &Syscon) {
 fn write_byte(spi: &mut spi_core::Spi, tx: &mut Option<Transmit>) {
     let txs = if let Some(txs) = tx { txs } else { return };
 
-    if txs.op == Op::Read {
+    if txs.op == Operation::Read {
         // This hardware block expects us to send at the same time we're
         // receiving. There is a bit to turn it off but accessing it is
         // not easy. For now just send 0 if we're trying to receive but
@@ -347,8 +388,13 @@ fn write_byte(spi: &mut spi_core::Spi, tx: &mut Option<Transmit>) {
         return;
     }
 
-    if let Some(byte) = txs.task.borrow(txs.rlease_num).read_at::<u8>(txs.rpos)
-    {
+    let byte = if txs.rpos < txs.rlen {
+        txs.task.borrow(txs.rlease_num).read_at::<u8>(txs.rpos)
+    } else {
+        Some(0)
+    };
+
+    if let Some(byte) = byte {
         txs.rpos += 1;
         spi.send_u8(byte);
         if txs.rpos == txs.len {
@@ -360,7 +406,7 @@ fn write_byte(spi: &mut spi_core::Spi, tx: &mut Option<Transmit>) {
         core::mem::replace(tx, None)
             .unwrap()
             .task
-            .reply_fail(ResponseCode::BadArg);
+            .reply_fail(SpiError::BadSourceByte);
     }
 }
 
@@ -375,9 +421,13 @@ fn read_byte(spi: &mut spi_core::Spi, tx: &mut Option<Transmit>) {
 
     let byte = spi.read_u8();
 
-    let borrow = txs.task.borrow(txs.wlease_num);
+    let stored = if txs.wpos < txs.wlen {
+        txs.task.borrow(txs.wlease_num).write_at(txs.wpos, byte)
+    } else {
+        Some(())
+    };
 
-    if let Some(_) = borrow.write_at(txs.wpos, byte) {
+    if stored.is_some() {
         txs.wpos += 1;
         if txs.wpos == txs.len {
             spi.disable_rx();
@@ -388,6 +438,6 @@ fn read_byte(spi: &mut spi_core::Spi, tx: &mut Option<Transmit>) {
         core::mem::replace(tx, None)
             .unwrap()
             .task
-            .reply_fail(ResponseCode::BadArg);
+            .reply_fail(SpiError::BadSinkByte);
     }
 }
@@ -12,6 +12,14 @@
 //! ## `write` (1)
 //!
 //! Sends the contents of lease #0. Returns when completed.
+//!
+//! ## `read` (2)
+//!
+//! Moves bytes that have been received into lease #0, which must be
+//! writable, and returns the number of bytes moved as a `u32`. This doesn't
+//! block: if nothing has been received, it returns zero. Received bytes are
+//! buffered (up to [`RX_BUFFER_SIZE`]) until they are read; bytes that
+//! arrive when the buffer is full are dropped.
 
 #![no_std]
 #![no_main]
@@ -25,6 +33,7 @@ use zerocopy::AsBytes;
 task_slot!(SYSCON, syscon_driver);
 
 const OP_WRITE: u32 = 1;
+const OP_READ: u32 = 2;
 
 task_slot!(GPIO, gpio_driver);
 
@@ -42,6 +51,57 @@ struct Transmit {
     pos: usize,
 }
 
+/// Size of the buffer for received bytes that have yet to be read.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Bytes that have been received, but not yet read.
+struct Receive {
+    buf: [u8; RX_BUFFER_SIZE],
+    /// Index of the oldest byte.
+    head: usize,
+    len: usize,
+}
+
+impl Receive {
+    fn push(&mut self, byte: u8) {
+        if self.len < self.buf.len() {
+            self.buf[(self.head + self.len) % self.buf.len()] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Moves as many bytes as will fit into lease #0 of `task`, which is
+    /// `len` bytes long, returning the number moved (or `None` if the lease
+    /// went away).
+    fn drain(&mut self, task: TaskId, len: usize) -> Option<usize> {
+        let n = self.len.min(len);
+
+        // The bytes may wrap around the end of the buffer, in which case
+        // they come in two pieces.
+        let first = n.min(self.buf.len() - self.head);
+        let pieces = [
+            &self.buf[self.head..self.head + first],
+            &self.buf[..n - first],
+        ];
+
+        let mut offset = 0;
+
+        for piece in pieces.iter().filter(|piece| !piece.is_empty()) {
+            let (rc, written) = sys_borrow_write(task, 0, offset, piece);
+
+            if rc != 0 || written != piece.len() {
+                return None;
+            }
+
+            offset += written;
+        }
+
+        self.head = (self.head + n) % self.buf.len();
+        self.len -= n;
+        Some(n)
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     // Turn the actual peripheral on so that we can interact with it.
@@ -61,13 +121,23 @@ fn main() -> ! {
     // Set USART mode
     flexcomm.pselid.write(|w| w.persel().usart());
 
-    usart.fifocfg.modify(|_, w| w.enabletx().enabled());
+    usart
+        .fifocfg
+        .modify(|_, w| w.enabletx().enabled().enablerx().enabled());
 
     // We actually get interrupts from the FIFO
-    // Trigger when the FIFO is empty for now
-    usart
-        .fifotrig
-        .modify(|_, w| unsafe { w.txlvl().bits(0).txlvlena().enabled() });
+    // Trigger when the TX FIFO is empty for now, and when the RX FIFO has
+    // anything in it
+    usart.fifotrig.modify(|_, w| unsafe {
+        w.txlvl()
+            .bits(0)
+            .txlvlena()
+            .enabled()
+            .rxlvl()
+            .bits(0)
+            .rxlvlena()
+            .enabled()
+    });
 
     // This puts us at 9600 baud because it divides nicely with the
     // 12mhz clock
@@ -92,12 +162,19 @@ fn main() -> ! {
             .enabled()
     });
 
-    // USART side yet, so this won't trigger notifications yet.
+    // Have the receiver interrupt us as bytes arrive.
+    usart.fifointenset.write(|w| w.rxlvl().enabled());
+
     sys_irq_control(1, true);
 
     // Field messages.
     let mask = 1;
     let mut tx: Option<Transmit> = None;
+    let mut rx = Receive {
+        buf: [0; RX_BUFFER_SIZE],
+        head: 0,
+        len: 0,
+    };
 
     loop {
         let msginfo = sys_recv_open(&mut [], mask);
@@ -118,6 +195,16 @@ fn main() -> ! {
                     }
                 }
 
+                while usart.fifostat.read().rxnotempty().bit() {
+                    rx.push(usart.fiford.read().rxdata().bits() as u8);
+                }
+
+                if usart.fifostat.read().rxerr().bit() {
+                    // The RX FIFO overflowed; whatever we missed is lost.
+                    // This is a write to clear bit.
+                    usart.fifostat.write(|w| w.rxerr().set_bit());
+                }
+
                 sys_irq_control(1, true);
             }
         } else {
@@ -164,6 +251,40 @@ fn main() -> ! {
 
                     // We'll do the rest as interrupts arrive.
                 }
+                OP_READ => {
+                    // Check the lease count and characteristics.
+                    if msginfo.lease_count != 1 {
+                        sys_reply(
+                            msginfo.sender,
+                            ResponseCode::BadArg as u32,
+                            &[],
+                        );
+                        continue;
+                    }
+
+                    let (rc, atts, len) = sys_borrow_info(msginfo.sender, 0);
+                    if rc != 0 || atts & 2 == 0 {
+                        sys_reply(
+                            msginfo.sender,
+                            ResponseCode::BadArg as u32,
+                            &[],
+                        );
+                        continue;
+                    }
+
+                    match rx.drain(msginfo.sender, len) {
+                        Some(n) => sys_reply(
+                            msginfo.sender,
+                            ResponseCode::Success as u32,
+                            (n as u32).as_bytes(),
+                        ),
+                        None => sys_reply(
+                            msginfo.sender,
+                            ResponseCode::BadArg as u32,
+                            &[],
+                        ),
+                    }
+                }
                 _ => sys_reply(msginfo.sender, ResponseCode::BadOp as u32, &[]),
             }
         }
@@ -81,6 +81,10 @@ pub enum SpiError {
     ///
     /// This is almost certainly a programming error on the client side.
     BadDevice = 16,
+
+    /// The server is busy with another transfer.  (This is returned only by
+    /// servers that are SPI devices, which must wait for their controller.)
+    Busy = 17,
 }
 
 impl From<SpiError> for u32 {
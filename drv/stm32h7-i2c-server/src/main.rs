@@ -7,6 +7,7 @@
 #![no_std]
 #![no_main]
 
+use core::cell::Cell;
 use drv_i2c_api::*;
 use drv_stm32h7_gpio_api::{Gpio, OutputType, Pull, Speed};
 use drv_stm32h7_i2c::*;
@@ -107,14 +108,74 @@ fn configure_mux(
 
 ringbuf!(Option<ResponseCode>, 16, None);
 
+//
+// Our ring buffer only holds the most recent errors; we also count every
+// error (NACKs, bus resets, mux failures, etc.) by response code.
+//
+counters!(ResponseCode);
+
+///
+/// A bus, for the purposes of keeping statistics: a controller and port,
+/// and the mux segment, if any.
+///
+type Bus = (Controller, PortIndex, Option<(Mux, Segment)>);
+
+//
+// We keep statistics for each bus as we first use it; should we use more
+// buses than we have room for, the statistics of the excess are not kept.
+//
+type StatsMap = FixedMap<Bus, BusStats, 32>;
+
+fn record(stats: &mut StatsMap, bus: Bus, f: impl FnOnce(&mut BusStats)) {
+    if let Ok(s) = stats.get_or_insert(bus, BusStats::default()) {
+        f(s);
+    }
+}
+
+fn bump(count: &mut u32) {
+    *count = count.wrapping_add(1);
+}
+
 fn reset_if_needed(
     code: ResponseCode,
     controller: &I2cController,
     port: PortIndex,
     muxes: &[I2cMux],
     mux: Option<(Mux, Segment)>,
+    muxmap: &mut MuxMap,
+    stats: &mut StatsMap,
 ) {
     ringbuf_entry!(Some(code));
+    count!(code);
+
+    let bus = (controller.controller, port, mux);
+
+    record(stats, bus, |s| {
+        match code {
+            ResponseCode::NoDevice | ResponseCode::NoRegister => {
+                bump(&mut s.nacks);
+            }
+            ResponseCode::BusLocked | ResponseCode::BusLockedMux => {
+                bump(&mut s.timeouts);
+            }
+            ResponseCode::BusReset | ResponseCode::BusResetMux => {
+                bump(&mut s.arbitration_losses);
+            }
+            _ => {}
+        }
+
+        match code {
+            ResponseCode::MuxDisconnected
+            | ResponseCode::SegmentDisconnected
+            | ResponseCode::BadMuxAddress
+            | ResponseCode::BadMuxRegister
+            | ResponseCode::BusLockedMux
+            | ResponseCode::BusResetMux => {
+                bump(&mut s.mux_failures);
+            }
+            _ => {}
+        }
+    });
 
     match code {
         ResponseCode::BusLocked
@@ -130,19 +191,25 @@ fn reset_if_needed(
     let gpio = GPIO.get_task_id();
     let gpio = Gpio::from(gpio);
 
+    record(stats, bus, |s| bump(&mut s.resets));
+
     // First, bounce our I2C controller
     controller.reset();
 
-    // And now reset the mux, eating any errors.
-    let _ = find_mux(controller, port, muxes, mux, |mux, _, _| {
+    //
+    // And now reset the mux, eating any errors.  A reset may leave the mux
+    // with no segment enabled, so we also forget the segment we enabled.
+    //
+    let _ = find_mux(controller, port, muxes, mux, |mux, id, _| {
         ringbuf_entry!(None);
+        muxmap.remove(id);
         mux.driver.reset(&mux, &gpio)?;
         Ok(())
     });
 }
 
 type PortMap = FixedMap<Controller, PortIndex, 8>;
-type MuxMap = FixedMap<Mux, Segment, 4>;
+type MuxMap = SortedFixedMap<Mux, Segment, 4>;
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
@@ -155,6 +222,7 @@ fn main() -> ! {
     // This is our actual mutable state
     let mut portmap = PortMap::new();
     let mut muxmap = MuxMap::new();
+    let mut stats = StatsMap::new();
 
     // Turn the actual peripheral on so that we can interact with it.
     turn_on_i2c(&controllers);
@@ -173,13 +241,33 @@ fn main() -> ! {
         },
     };
 
-    configure_muxes(&muxes, &controllers, &pins, &mut portmap, &ctrl);
+    configure_muxes(
+        &muxes,
+        &controllers,
+        &pins,
+        &mut portmap,
+        &mut muxmap,
+        &mut stats,
+        &ctrl,
+    );
 
     loop {
         hl::recv_without_notification(&mut buffer, |op, msg| match op {
-            Op::WriteRead | Op::WriteReadBlock => {
+            Op::WriteRead
+            | Op::WriteReadBlock
+            | Op::WriteReadPec
+            | Op::WriteReadBlockPec
+            | Op::WriteBlock
+            | Op::WriteBlockPec
+            | Op::BlockProcessCall
+            | Op::BlockProcessCallPec => {
+                let nleases = match op {
+                    Op::BlockProcessCall | Op::BlockProcessCallPec => 3,
+                    _ => 2,
+                };
+
                 let (payload, caller) = msg
-                    .fixed_with_leases::<[u8; 4], usize>(2)
+                    .fixed_with_leases::<[u8; 4], usize>(nleases)
                     .ok_or(ResponseCode::BadArg)?;
 
                 let (addr, controller, port, mux) =
@@ -192,6 +280,10 @@ fn main() -> ! {
                 let controller = lookup_controller(&controllers, controller)?;
                 validate_port(&pins, controller.controller, port)?;
 
+                record(&mut stats, (controller.controller, port, mux), |s| {
+                    bump(&mut s.transactions)
+                });
+
                 configure_port(&mut portmap, controller, port, &pins);
 
                 match configure_mux(
@@ -204,7 +296,15 @@ fn main() -> ! {
                 ) {
                     Ok(_) => {}
                     Err(code) => {
-                        reset_if_needed(code, controller, port, &muxes, mux);
+                        reset_if_needed(
+                            code,
+                            controller,
+                            port,
+                            &muxes,
+                            mux,
+                            &mut muxmap,
+                            &mut stats,
+                        );
                         return Err(code);
                     }
                 }
@@ -216,10 +316,60 @@ fn main() -> ! {
                     return Err(ResponseCode::BadArg);
                 }
 
-                let rbuf = caller.borrow(1);
+                //
+                // For SMBus block writes, the register is followed by a byte
+                // count, and then by the block itself, which is in lease 1.
+                //
+                let block = match op {
+                    Op::WriteBlock
+                    | Op::WriteBlockPec
+                    | Op::BlockProcessCall
+                    | Op::BlockProcessCallPec => {
+                        let bbuf = caller.borrow(1);
+                        let binfo = bbuf.info().ok_or(ResponseCode::BadArg)?;
+
+                        if !binfo.attributes.contains(LeaseAttributes::READ)
+                            || binfo.len > 255
+                        {
+                            return Err(ResponseCode::BadArg);
+                        }
+
+                        Some((bbuf, binfo.len))
+                    }
+                    _ => None,
+                };
+
+                // (For block writes, which read nothing, this is the block.)
+                let rbuf = caller.borrow(nleases - 1);
                 let rinfo = rbuf.info().ok_or(ResponseCode::BadArg)?;
+                let pec = op.pec();
+
+                let rlen = match op {
+                    Op::WriteRead => ReadLength::Fixed(rinfo.len),
+                    Op::WriteReadPec if rinfo.len == 0 => ReadLength::Fixed(0),
+                    Op::WriteReadPec => ReadLength::Fixed(rinfo.len + 1),
+                    Op::WriteReadBlock | Op::BlockProcessCall => {
+                        ReadLength::Variable
+                    }
+                    Op::WriteReadBlockPec | Op::BlockProcessCallPec => {
+                        ReadLength::VariableWithPec
+                    }
+                    _ => ReadLength::Fixed(0),
+                };
+
+                //
+                // If we are only writing, the PEC (if any) follows what we
+                // write; otherwise, it follows what we read.
+                //
+                let wlen = winfo.len
+                    + block.as_ref().map_or(0, |(_, len)| len + 1)
+                    + if pec && rlen == ReadLength::Fixed(0) {
+                        1
+                    } else {
+                        0
+                    };
 
-                if winfo.len == 0 && rinfo.len == 0 {
+                if wlen == 0 && rlen == ReadLength::Fixed(0) {
                     // We must have either a write OR a read -- while perhaps
                     // valid to support both being zero as a way of testing an
                     // address for a NACK, it's not a mode that we (currently)
@@ -227,42 +377,291 @@ fn main() -> ! {
                     return Err(ResponseCode::BadArg);
                 }
 
-                if winfo.len > 255 || rinfo.len > 255 {
+                if wlen > 255 || rinfo.len > 255 {
                     // For now, we don't support writing or reading more than
                     // 255 bytes.
                     return Err(ResponseCode::BadArg);
                 }
 
-                let mut nread = 0;
+                if let ReadLength::Fixed(len) = rlen {
+                    if len > 255 {
+                        return Err(ResponseCode::BadArg);
+                    }
+                }
 
-                match controller.write_read(
-                    addr,
-                    winfo.len,
-                    |pos| wbuf.read_at(pos),
-                    if op == Op::WriteRead {
-                        ReadLength::Fixed(rinfo.len)
-                    } else {
-                        ReadLength::Variable
-                    },
-                    |pos, byte| {
-                        if pos + 1 > nread {
-                            nread = pos + 1;
+                //
+                // We calculate the PEC as we go, whether or not we need it.
+                //
+                let calculated = Cell::new(Pec::new());
+                let update =
+                    |byte| calculated.set(calculated.get().update(byte));
+
+                let getbyte = |pos: usize| {
+                    if pos == 0 {
+                        calculated
+                            .set(calculated.get().update_address(addr, false));
+                    }
+
+                    let byte = match (pos.checked_sub(winfo.len), &block) {
+                        (None, _) => wbuf.read_at(pos)?,
+                        (Some(0), Some((_, len))) => *len as u8,
+                        (Some(n), Some((bbuf, len))) if n <= *len => {
+                            bbuf.read_at(n - 1)?
                         }
+                        _ => {
+                            // All that remains to be written is the PEC.
+                            return Some(calculated.get().value());
+                        }
+                    };
 
-                        rbuf.write_at(pos, byte)
-                    },
-                    &ctrl,
-                ) {
+                    update(byte);
+                    Some(byte)
+                };
+
+                let mut nread = 0;
+
+                //
+                // For a read with a PEC, we need to know which byte is the
+                // PEC; for a block read, we don't know until we have the
+                // length (which precedes the block).
+                //
+                let mut pec_pos = match rlen {
+                    ReadLength::Fixed(len) if pec && len > 0 => Some(len - 1),
+                    _ => None,
+                };
+
+                let skip = if rlen == ReadLength::VariableWithPec {
+                    1
+                } else {
+                    0
+                };
+                let mut received = None;
+
+                let putbyte = |pos: usize, byte| {
+                    if pos == 0 {
+                        calculated
+                            .set(calculated.get().update_address(addr, true));
+                    }
+
+                    if Some(pos) == pec_pos {
+                        received = Some(byte);
+                        return Some(());
+                    }
+
+                    update(byte);
+
+                    if pos < skip {
+                        pec_pos = Some(usize::from(byte) + 1);
+                        return Some(());
+                    }
+
+                    if pos + 1 - skip > nread {
+                        nread = pos + 1 - skip;
+                    }
+
+                    rbuf.write_at(pos - skip, byte)
+                };
+
+                match controller
+                    .write_read(addr, wlen, getbyte, rlen, putbyte, &ctrl)
+                {
                     Err(code) => {
-                        reset_if_needed(code, controller, port, &muxes, mux);
+                        reset_if_needed(
+                            code,
+                            controller,
+                            port,
+                            &muxes,
+                            mux,
+                            &mut muxmap,
+                            &mut stats,
+                        );
                         Err(code)
                     }
                     Ok(_) => {
+                        if pec_pos.is_some()
+                            && received != Some(calculated.get().value())
+                        {
+                            let code = ResponseCode::PecMismatch;
+                            reset_if_needed(
+                                code,
+                                controller,
+                                port,
+                                &muxes,
+                                mux,
+                                &mut muxmap,
+                                &mut stats,
+                            );
+                            return Err(code);
+                        }
+
                         caller.reply(nread);
                         Ok(())
                     }
                 }
             }
+            Op::Scan => {
+                let (payload, caller) = msg
+                    .fixed_with_leases::<[u8; 4], usize>(1)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let (method, controller, port, mux) =
+                    Marshal::unmarshal(payload)?;
+
+                let method =
+                    ProbeMethod::from_u8(method).ok_or(ResponseCode::BadArg)?;
+
+                let bitmap = caller.borrow(0);
+                let info = bitmap.info().ok_or(ResponseCode::BadArg)?;
+
+                if !info.attributes.contains(LeaseAttributes::WRITE)
+                    || info.len < SCAN_BITMAP_SIZE
+                {
+                    return Err(ResponseCode::BadArg);
+                }
+
+                let controller = lookup_controller(&controllers, controller)?;
+                validate_port(&pins, controller.controller, port)?;
+
+                configure_port(&mut portmap, controller, port, &pins);
+
+                match configure_mux(
+                    &mut muxmap,
+                    controller,
+                    port,
+                    mux,
+                    &muxes,
+                    &ctrl,
+                ) {
+                    Ok(_) => {}
+                    Err(code) => {
+                        reset_if_needed(
+                            code,
+                            controller,
+                            port,
+                            &muxes,
+                            mux,
+                            &mut muxmap,
+                            &mut stats,
+                        );
+                        return Err(code);
+                    }
+                }
+
+                let mut found = [0u8; SCAN_BITMAP_SIZE];
+                let mut nfound = 0;
+
+                for addr in 0..128u8 {
+                    if ReservedAddress::from_u8(addr).is_some() {
+                        continue;
+                    }
+
+                    //
+                    // A NACK is what we expect from most addresses, so we
+                    // don't record it as an error; anything else ends the
+                    // scan.
+                    //
+                    match controller.probe(addr, method, &ctrl) {
+                        Ok(_) => {
+                            found[usize::from(addr / 8)] |= 1 << (addr % 8);
+                            nfound += 1;
+                        }
+                        Err(ResponseCode::NoDevice) => {}
+                        Err(code) => {
+                            reset_if_needed(
+                                code,
+                                controller,
+                                port,
+                                &muxes,
+                                mux,
+                                &mut muxmap,
+                                &mut stats,
+                            );
+                            return Err(code);
+                        }
+                    }
+                }
+
+                bitmap
+                    .write_fully_at(0, &found)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                caller.reply(nfound);
+                Ok(())
+            }
+            Op::Stats => {
+                let (payload, caller) = msg
+                    .fixed_with_leases::<[u8; 4], usize>(1)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let (clear, controller, port, mux) =
+                    Marshal::unmarshal(payload)?;
+
+                let controller = lookup_controller(&controllers, controller)?;
+                validate_port(&pins, controller.controller, port)?;
+                find_mux(controller, port, &muxes, mux, |_, _, _| Ok(()))?;
+
+                let bus = (controller.controller, port, mux);
+                let s = stats.get(bus).unwrap_or_default();
+
+                caller
+                    .borrow(0)
+                    .write_at(0, s)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                if clear != 0 {
+                    stats.remove(bus);
+                }
+
+                caller.reply(core::mem::size_of::<BusStats>());
+                Ok(())
+            }
+            Op::MuxInterrupts => {
+                let (payload, caller) =
+                    msg.fixed::<[u8; 4], u8>().ok_or(ResponseCode::BadArg)?;
+
+                let (_, controller, port, mux) = Marshal::unmarshal(payload)?;
+
+                if mux.is_none() {
+                    return Err(ResponseCode::BadMux);
+                }
+
+                let controller = lookup_controller(&controllers, controller)?;
+                validate_port(&pins, controller.controller, port)?;
+
+                configure_port(&mut portmap, controller, port, &pins);
+
+                let mut interrupts = 0;
+
+                match find_mux(controller, port, &muxes, mux, |mux, _, _| {
+                    interrupts =
+                        mux.driver.interrupts(mux, controller, &ctrl)?;
+                    Ok(())
+                }) {
+                    Ok(_) => {}
+                    Err(ResponseCode::NoMuxInterrupts) => {
+                        return Err(ResponseCode::NoMuxInterrupts);
+                    }
+                    Err(code) => {
+                        reset_if_needed(
+                            code,
+                            controller,
+                            port,
+                            &muxes,
+                            mux,
+                            &mut muxmap,
+                            &mut stats,
+                        );
+                        return Err(code);
+                    }
+                }
+
+                caller.reply(interrupts);
+                Ok(())
+            }
+            Op::DumpRingbufs => {
+                ringbuf_registry::serve(msg);
+                Ok(())
+            }
         });
     }
 }
@@ -369,6 +768,8 @@ fn configure_muxes(
     controllers: &[I2cController],
     pins: &[I2cPin],
     map: &mut PortMap,
+    muxmap: &mut MuxMap,
+    stats: &mut StatsMap,
     ctrl: &I2cControl,
 ) {
     let gpio = GPIO.get_task_id();
@@ -386,7 +787,9 @@ fn configure_muxes(
                 }
                 Err(code) => {
                     ringbuf_entry!(Some(code));
-                    reset_if_needed(code, controller, mux.port, muxes, None);
+                    reset_if_needed(
+                        code, controller, mux.port, muxes, None, muxmap, stats,
+                    );
                 }
             }
         }
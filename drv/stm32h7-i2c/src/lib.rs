@@ -21,10 +21,10 @@ pub type RegisterBlock = device::i2c3::RegisterBlock;
 #[cfg(any(feature = "h743", feature = "h753"))]
 pub type RegisterBlock = device::i2c1::RegisterBlock;
 
-pub mod ltc4306;
-pub mod max7358;
-pub mod pca9548;
+pub use i2c_mux::{ltc4306, max7358, pca9545, pca9548, pca9846, I2cKonamiCode};
 
+use core::cell::RefCell;
+use i2c_target::Targets;
 use ringbuf::*;
 use userlib::*;
 
@@ -53,14 +53,10 @@ pub struct I2cControl {
     pub wfi: fn(u32),
 }
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum I2cKonamiCode {
-    Read,
-    Write,
-}
-
 ///
-/// A trait to express an I2C mux driver.
+/// A trait to express an I2C mux driver.  This is implemented for each of
+/// the drivers in [`i2c_mux`], which do their in-band management through
+/// an [`I2cMuxBus`].
 ///
 pub trait I2cMuxDriver {
     /// Configure the mux, specifying the mux and controller, but also an
@@ -88,6 +84,27 @@ pub trait I2cMuxDriver {
         segment: drv_i2c_api::Segment,
         ctrl: &I2cControl,
     ) -> Result<(), drv_i2c_api::ResponseCode>;
+
+    /// Returns the segments of the specified mux that have their interrupt
+    /// line asserted, as a bitmask in which bit 0 is `Segment::S1`.  By
+    /// default, a mux has no interrupt lines to report.
+    fn interrupts(
+        &self,
+        _mux: &I2cMux,
+        _controller: &I2cController,
+        _ctrl: &I2cControl,
+    ) -> Result<u8, drv_i2c_api::ResponseCode> {
+        Err(drv_i2c_api::ResponseCode::NoMuxInterrupts)
+    }
+}
+
+///
+/// The bus on which a mux driver in [`i2c_mux`] makes its in-band management
+/// transfers: the controller to which the mux is attached.
+///
+pub struct I2cMuxBus<'a> {
+    pub controller: &'a I2cController<'a>,
+    pub ctrl: &'a I2cControl,
 }
 
 pub struct I2cMux<'a> {
@@ -108,6 +125,11 @@ pub enum ReadLength {
     Fixed(usize),
     /// Read size is variable: first byte contains length
     Variable,
+    /// Read size is variable, as with [`ReadLength::Variable`], but the
+    /// data is followed by an SMBus PEC byte.  So that the PEC can be
+    /// checked, both the length byte (as byte 0) and the PEC byte (after
+    /// the data) are passed to `putbyte`.
+    VariableWithPec,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -119,6 +141,7 @@ enum Trace {
     ReadWaitISR(u32),
     RxISR(u32),
     KonamiISR(u32),
+    ProbeISR(u32),
     Konami(I2cKonamiCode),
     ResetISR(u32),
     AddrISR(u32),
@@ -144,16 +167,23 @@ impl<'a> I2cMux<'_> {
     /// management into one that can be returned to a caller
     fn error_code(
         &self,
-        code: drv_i2c_api::ResponseCode,
+        err: i2c_mux::Error<drv_i2c_api::ResponseCode>,
     ) -> drv_i2c_api::ResponseCode {
         use drv_i2c_api::ResponseCode;
+        use i2c_mux::Error;
 
-        match code {
-            ResponseCode::NoDevice => ResponseCode::BadMuxAddress,
-            ResponseCode::NoRegister => ResponseCode::BadMuxRegister,
-            ResponseCode::BusLocked => ResponseCode::BusLockedMux,
-            ResponseCode::BusReset => ResponseCode::BusResetMux,
-            _ => code,
+        match err {
+            Error::Bus(ResponseCode::NoDevice) => ResponseCode::BadMuxAddress,
+            Error::Bus(ResponseCode::NoRegister) => {
+                ResponseCode::BadMuxRegister
+            }
+            Error::Bus(ResponseCode::BusLocked) => ResponseCode::BusLockedMux,
+            Error::Bus(ResponseCode::BusReset) => ResponseCode::BusResetMux,
+            Error::Bus(code) => code,
+            Error::SegmentNotFound => ResponseCode::SegmentNotFound,
+            Error::SegmentDisconnected => ResponseCode::SegmentDisconnected,
+            Error::MuxDisconnected => ResponseCode::MuxDisconnected,
+            Error::NoInterrupts => ResponseCode::NoMuxInterrupts,
         }
     }
 
@@ -559,6 +589,25 @@ impl<'a> I2cController<'a> {
                     continue;
                 }
 
+                if rlen == ReadLength::VariableWithPec {
+                    // We can't ask for the PEC byte after a length of 255.
+                    let remaining = byte
+                        .checked_add(1)
+                        .ok_or(drv_i2c_api::ResponseCode::BadArg)?;
+
+                    #[rustfmt::skip]
+                    i2c.cr2.modify(|_, w| { w
+                        .nbytes().bits(remaining)
+                        .reload().clear_bit()
+                    });
+
+                    putbyte(0, byte)
+                        .ok_or(drv_i2c_api::ResponseCode::BadArg)?;
+                    rlen = ReadLength::Fixed(usize::from(remaining) + 1);
+                    pos = 1;
+                    continue;
+                }
+
                 putbyte(pos, byte).ok_or(drv_i2c_api::ResponseCode::BadArg)?;
                 pos += 1;
             }
@@ -596,6 +645,74 @@ impl<'a> I2cController<'a> {
         Ok(())
     }
 
+    ///
+    /// Probes for a device at the specified address using the specified
+    /// method, returning `ResponseCode::NoDevice` if the address is NACK'd.
+    ///
+    pub fn probe(
+        &self,
+        addr: u8,
+        method: drv_i2c_api::ProbeMethod,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        if method == drv_i2c_api::ProbeMethod::ReadByte {
+            return self.write_read(
+                addr,
+                0,
+                |_| None,
+                ReadLength::Fixed(1),
+                |_, _| Some(()),
+                ctrl,
+            );
+        }
+
+        let i2c = self.registers;
+        let notification = self.notification;
+
+        self.wait_until_notbusy()?;
+
+        #[rustfmt::skip]
+        i2c.cr2.modify(|_, w| { w
+            .nbytes().bits(0u8)
+            .autoend().clear_bit()
+            .add10().clear_bit()
+            .sadd().bits((addr << 1).into())
+            .rd_wrn().clear_bit()
+            .start().set_bit()
+        });
+
+        loop {
+            let isr = i2c.isr.read();
+            ringbuf_entry!(Trace::ProbeISR(isr.bits()));
+
+            if isr.timeout().is_timeout() {
+                i2c.icr.write(|w| w.timoutcf().set_bit());
+                return Err(drv_i2c_api::ResponseCode::BusLocked);
+            }
+
+            if isr.nackf().is_nack() {
+                i2c.icr.write(|w| w.nackcf().set_bit());
+                return Err(drv_i2c_api::ResponseCode::NoDevice);
+            }
+
+            if isr.arlo().is_lost() {
+                i2c.icr.write(|w| w.arlocf().set_bit());
+                return Err(drv_i2c_api::ResponseCode::BusReset);
+            }
+
+            if isr.tc().is_complete() {
+                break;
+            }
+
+            (ctrl.wfi)(notification);
+            (ctrl.enable)(notification);
+        }
+
+        i2c.cr2.modify(|_, w| w.stop().set_bit());
+
+        Ok(())
+    }
+
     ///
     /// Regrettably, some devices insist on special sequences to be sent to
     /// unlock functionality -- effectively a Konami Code for an I2C device.
@@ -879,4 +996,102 @@ impl<'a> I2cController<'a> {
             }
         }
     }
+
+    ///
+    /// Operates as the targets in `targets`, each of which presents a
+    /// register map at its own address (see the `i2c-target` crate), NACK'ing
+    /// any other address.
+    ///
+    pub fn operate_as_register_target(
+        &self,
+        ctrl: &I2cControl,
+        targets: &mut Targets,
+    ) -> ! {
+        let targets = RefCell::new(targets);
+
+        self.operate_as_target(
+            ctrl,
+            |addr| targets.borrow_mut().initiate(addr),
+            |addr, byte| targets.borrow_mut().rx(addr, byte),
+            |addr| targets.borrow_mut().tx(addr),
+        )
+    }
+}
+
+impl i2c_mux::Bus for I2cMuxBus<'_> {
+    type Error = drv_i2c_api::ResponseCode;
+
+    fn transfer(
+        &self,
+        addr: u8,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        self.controller.write_read(
+            addr,
+            wbuf.len(),
+            |pos| wbuf.get(pos).copied(),
+            ReadLength::Fixed(rbuf.len()),
+            |pos, byte| {
+                *rbuf.get_mut(pos)? = byte;
+                Some(())
+            },
+            self.ctrl,
+        )
+    }
+
+    fn konami(
+        &self,
+        addr: u8,
+        ops: &[I2cKonamiCode],
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        self.controller.send_konami_code(addr, ops, self.ctrl)
+    }
+}
+
+impl<T: i2c_mux::Driver> I2cMuxDriver for T {
+    fn configure(
+        &self,
+        mux: &I2cMux,
+        controller: &I2cController,
+        gpio: &drv_stm32h7_gpio_api::Gpio,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.configure(gpio)?;
+
+        let bus = I2cMuxBus { controller, ctrl };
+        i2c_mux::Driver::configure(self, &bus, mux.address)
+            .map_err(|err| mux.error_code(err))
+    }
+
+    fn reset(
+        &self,
+        mux: &I2cMux,
+        gpio: &drv_stm32h7_gpio_api::Gpio,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.reset(gpio)
+    }
+
+    fn enable_segment(
+        &self,
+        mux: &I2cMux,
+        controller: &I2cController,
+        segment: drv_i2c_api::Segment,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        let bus = I2cMuxBus { controller, ctrl };
+        i2c_mux::Driver::enable_segment(self, &bus, mux.address, segment as u8)
+            .map_err(|err| mux.error_code(err))
+    }
+
+    fn interrupts(
+        &self,
+        mux: &I2cMux,
+        controller: &I2cController,
+        ctrl: &I2cControl,
+    ) -> Result<u8, drv_i2c_api::ResponseCode> {
+        let bus = I2cMuxBus { controller, ctrl };
+        i2c_mux::Driver::interrupts(self, &bus, mux.address)
+            .map_err(|err| mux.error_code(err))
+    }
 }
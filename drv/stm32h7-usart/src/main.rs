@@ -9,6 +9,14 @@
 //! ## `write` (1)
 //!
 //! Sends the contents of lease #0. Returns when completed.
+//!
+//! ## `read` (2)
+//!
+//! Moves bytes that have been received into lease #0, which must be
+//! writable, and returns the number of bytes moved as a `u32`. This doesn't
+//! block: if nothing has been received, it returns zero. Received bytes are
+//! buffered (up to [`RX_BUFFER_SIZE`]) until they are read; bytes that
+//! arrive when the buffer is full are dropped.
 
 #![no_std]
 #![no_main]
@@ -30,6 +38,7 @@ task_slot!(GPIO, gpio_driver);
 #[derive(Copy, Clone, Debug, FromPrimitive)]
 enum Operation {
     Write = 1,
+    Read = 2,
 }
 
 #[repr(u32)]
@@ -52,6 +61,49 @@ struct Transmit {
     pos: usize,
 }
 
+/// Size of the buffer for received bytes that have yet to be read.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Bytes that have been received, but not yet read.
+struct Receive {
+    buf: [u8; RX_BUFFER_SIZE],
+    /// Index of the oldest byte.
+    head: usize,
+    len: usize,
+}
+
+impl Receive {
+    fn push(&mut self, byte: u8) {
+        if self.len < self.buf.len() {
+            self.buf[(self.head + self.len) % self.buf.len()] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Moves as many bytes as will fit into `writer`.
+    fn drain(
+        &mut self,
+        writer: &mut hl::BorrowWriter<'_>,
+    ) -> Result<usize, hl::LeaseError> {
+        let n = self.len.min(writer.remaining());
+
+        // The bytes may wrap around the end of the buffer, in which case
+        // they come in two pieces.
+        let first = n.min(self.buf.len() - self.head);
+        writer.write_all(&self.buf[self.head..self.head + first])?;
+        writer.write_all(&self.buf[..n - first])?;
+
+        self.head = (self.head + n) % self.buf.len();
+        self.len -= n;
+        Ok(n)
+    }
+}
+
+struct State {
+    tx: Option<Transmit>,
+    rx: Receive,
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     // Turn the actual peripheral on so that we can interact with it.
@@ -80,18 +132,35 @@ fn main() -> ! {
     const CYCLES_PER_BIT: u32 = (CLOCK_HZ + (BAUDRATE / 2)) / BAUDRATE;
     usart.brr.write(|w| w.brr().bits(CYCLES_PER_BIT as u16));
 
-    // Enable the UART and transmitter.
-    usart.cr1.modify(|_, w| w.ue().enabled().te().enabled());
+    // Enable the UART, transmitter and receiver, and have the receiver
+    // interrupt us as each byte arrives.
+    usart.cr1.modify(|_, w| {
+        w.ue()
+            .enabled()
+            .te()
+            .enabled()
+            .re()
+            .enabled()
+            .rxneie()
+            .enabled()
+    });
 
     configure_pins();
 
-    // Turn on our interrupt. We haven't enabled any interrupt sources at the
-    // USART side yet, so this won't trigger notifications yet.
+    // Turn on our interrupt. The only interrupt source that we've enabled at
+    // the USART side is the receiver.
     sys_irq_control(1, true);
 
     // Field messages.
     let mask = 1;
-    let mut tx: Option<Transmit> = None;
+    let mut state = State {
+        tx: None,
+        rx: Receive {
+            buf: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        },
+    };
 
     loop {
         hl::recv(
@@ -100,9 +169,9 @@ fn main() -> ! {
             // Notification mask
             mask,
             // State to pass through to whichever closure below gets run
-            &mut tx,
+            &mut state,
             // Notification handler
-            |txref, bits| {
+            |state, bits| {
                 if bits & 1 != 0 {
                     // Handling an interrupt. To allow for spurious interrupts,
                     // check the individual conditions we care about, and
@@ -110,21 +179,33 @@ fn main() -> ! {
 
                     if usart.isr.read().txe().bit() {
                         // TX register empty. Do we need to send something?
-                        step_transmit(&usart, txref);
+                        step_transmit(&usart, &mut state.tx);
+                    }
+
+                    while usart.isr.read().rxne().bit() {
+                        // Reading the byte clears the RX-not-empty flag.
+                        state.rx.push(usart.rdr.read().rdr().bits() as u8);
+                    }
+
+                    if usart.isr.read().ore().bit() {
+                        // We failed to keep up with the receiver. Whatever we
+                        // missed is lost; clear the condition so that we can
+                        // receive again.
+                        usart.icr.write(|w| w.orecf().clear());
                     }
 
                     sys_irq_control(1, true);
                 }
             },
             // Message handler
-            |txref, op, msg| match op {
+            |state, op, msg| match op {
                 Operation::Write => {
                     // Validate lease count and buffer sizes first.
                     let ((), caller) =
                         msg.fixed_with_leases(1).ok_or(ResponseCode::BadArg)?;
 
                     // Deny incoming writes if we're already running one.
-                    if txref.is_some() {
+                    if state.tx.is_some() {
                         return Err(ResponseCode::Busy);
                     }
 
@@ -139,7 +220,7 @@ fn main() -> ! {
                     }
 
                     // Okay! Begin a transfer!
-                    *txref = Some(Transmit {
+                    state.tx = Some(Transmit {
                         caller,
                         pos: 0,
                         len: info.len,
@@ -151,6 +232,18 @@ fn main() -> ! {
                     // We'll do the rest as interrupts arrive.
                     Ok(())
                 }
+                Operation::Read => {
+                    let ((), caller) = msg
+                        .fixed_with_leases::<(), u32>(1)
+                        .ok_or(ResponseCode::BadArg)?;
+
+                    let n = hl::BorrowWriter::new(caller.borrow(0))
+                        .and_then(|mut writer| state.rx.drain(&mut writer))
+                        .map_err(|_| ResponseCode::BadArg)?;
+
+                    caller.reply(n as u32);
+                    Ok(())
+                }
             },
         );
     }
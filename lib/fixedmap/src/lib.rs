@@ -7,10 +7,22 @@
 //! This contains a very simple implementation of a fixed-sized map, with
 //! keys of type `K` and values of type `V`.  Keys and values are both stored
 //! by value: both must implement `Copy`, and keys must implement `PartialEq`.
-//! It is up to callers to assure that the map doesn't overflow; an attempt
-//! to [`FixedMap::insert`] when the map is full will result in a `panic!`.
+//! An attempt to [`FixedMap::insert`] a new key when the map is full will
+//! result in a `panic!`; callers that can't assure that the map won't
+//! overflow should use [`FixedMap::try_insert`] instead.
+//!
+//! Lookups in a [`FixedMap`] are linear scans, which is as fast as anything
+//! for a handful of entries.  For larger maps, [`SortedFixedMap`] keeps its
+//! entries sorted by key (which must implement `Ord`), and uses a binary
+//! search.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
+
+///
+/// Error returned when attempting to insert a new key into a full map.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MapFull;
 
 ///
 /// A fixed-size map of size `N`, mapping keys of type `K` to values of
@@ -18,6 +30,8 @@
 ///
 #[derive(Debug)]
 pub struct FixedMap<K: Copy + PartialEq, V: Copy, const N: usize> {
+    /// The entries, which are packed at the front: every `Some` precedes
+    /// every `None`.
     contents: [Option<(K, V)>; N],
 }
 
@@ -31,25 +45,237 @@ impl<K: Copy + PartialEq, V: Copy, const N: usize> FixedMap<K, V, { N }> {
         }
     }
 
+    /// Returns the index of `key`, if it is in the map.
+    fn find(&self, key: K) -> Option<usize> {
+        self.iter().position(|(&k, _)| k == key)
+    }
+
     ///
     /// Gets the value that corresponds to `key`, returning `None` if no
     /// such key is in the map.
     ///
     pub fn get(&self, key: K) -> Option<V> {
-        for i in 0..self.contents.len() {
-            match self.contents[i] {
-                None => {
-                    break;
-                }
-                Some((k, v)) => {
-                    if k == key {
-                        return Some(v);
-                    }
-                }
+        self.iter().find(|(&k, _)| k == key).map(|(_, &v)| v)
+    }
+
+    ///
+    /// Gets a mutable reference to the value that corresponds to `key`,
+    /// returning `None` if no such key is in the map.
+    ///
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.iter_mut().find(|(&k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Returns `true` if `key` is in the map.
+    pub fn contains_key(&self, key: K) -> bool {
+        self.find(key).is_some()
+    }
+
+    ///
+    /// Inserts the `value` into the map for the specified `key`.  If the
+    /// specified key already exists in the map, its value will be overwritten
+    /// with the specified value.  It is up to the caller to assure that there
+    /// is room in the map; if the map is full, this code will panic.
+    ///
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.try_insert(key, value).is_err() {
+            panic!("FixedMap overflow");
+        }
+    }
+
+    ///
+    /// Inserts the `value` into the map for the specified `key`, overwriting
+    /// any value already there.  If the key is not in the map and the map is
+    /// full, returns [`MapFull`] and leaves the map unchanged.
+    ///
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), MapFull> {
+        *self.get_or_insert(key, value)? = value;
+        Ok(())
+    }
+
+    ///
+    /// Gets a mutable reference to the value for `key`, first inserting
+    /// `value` if the key is not in the map.  Returns [`MapFull`] if the key
+    /// needs to be inserted, but the map is full.
+    ///
+    pub fn get_or_insert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<&mut V, MapFull> {
+        self.get_or_insert_with(key, || value)
+    }
+
+    ///
+    /// Like [`FixedMap::get_or_insert`], but calls `f` to produce the value
+    /// to insert (and only if it needs to be inserted).
+    ///
+    pub fn get_or_insert_with(
+        &mut self,
+        key: K,
+        f: impl FnOnce() -> V,
+    ) -> Result<&mut V, MapFull> {
+        let len = self.len();
+
+        let ndx = match self.find(key) {
+            Some(ndx) => ndx,
+            None if len < N => {
+                self.contents[len] = Some((key, f()));
+                len
             }
+            None => return Err(MapFull),
+        };
+
+        match &mut self.contents[ndx] {
+            Some((_, v)) => Ok(v),
+            None => unreachable!(),
         }
+    }
 
-        None
+    ///
+    /// Removes the specified key from the map.
+    ///
+    pub fn remove(&mut self, key: K) {
+        if let Some(found) = self.find(key) {
+            // Keep the entries packed by moving the last one into the hole.
+            let last = self.len() - 1;
+            self.contents[found] = self.contents[last];
+            self.contents[last] = None;
+        }
+    }
+
+    ///
+    /// Retains only the entries for which `f` returns `true`, removing the
+    /// rest.  The order of the remaining entries is preserved.
+    ///
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        let len = self.len();
+        let mut kept = 0;
+
+        for i in 0..len {
+            let keep = match &mut self.contents[i] {
+                Some((k, v)) => f(k, v),
+                None => unreachable!(),
+            };
+
+            if keep {
+                self.contents[kept] = self.contents[i];
+                kept += 1;
+            }
+        }
+
+        for entry in &mut self.contents[kept..len] {
+            *entry = None;
+        }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.contents.iter().take_while(|e| e.is_some()).count()
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        !matches!(self.contents.first(), Some(Some(_)))
+    }
+
+    /// Returns `true` if no more keys can be inserted into the map.
+    pub fn is_full(&self) -> bool {
+        !matches!(self.contents.last(), Some(None))
+    }
+
+    ///
+    /// Returns an iterator over the entries in the map.  The order is
+    /// unspecified.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.contents
+            .iter()
+            .filter_map(|e| e.as_ref().map(|(k, v)| (k, v)))
+    }
+
+    ///
+    /// Returns an iterator over the entries in the map, allowing the values
+    /// to be modified.  The order is unspecified.
+    ///
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.contents
+            .iter_mut()
+            .filter_map(|e| e.as_mut().map(|(k, v)| (&*k, v)))
+    }
+}
+
+impl<K: Copy + PartialEq, V: Copy, const N: usize> Default
+    for FixedMap<K, V, { N }>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// A fixed-size map of size `N`, mapping keys of type `K` to values of
+/// type `V`, in which entries are kept sorted by key.  Lookups are by binary
+/// search, making this preferable to [`FixedMap`] for larger maps -- though
+/// insertion and removal must move the entries that follow.
+///
+#[derive(Debug)]
+pub struct SortedFixedMap<K: Copy + Ord, V: Copy, const N: usize> {
+    /// The entries, sorted by key and packed at the front.
+    contents: [Option<(K, V)>; N],
+    len: usize,
+}
+
+impl<K: Copy + Ord, V: Copy, const N: usize> SortedFixedMap<K, V, { N }> {
+    ///
+    /// Create a new `SortedFixedMap`.
+    ///
+    pub fn new() -> Self {
+        Self {
+            contents: [None; N],
+            len: 0,
+        }
+    }
+
+    ///
+    /// Searches for `key`, returning `Ok` with its index if it's in the map,
+    /// or `Err` with the index at which it would be inserted if it isn't.
+    ///
+    fn search(&self, key: K) -> Result<usize, usize> {
+        self.contents[..self.len].binary_search_by(|e| match e {
+            Some((k, _)) => k.cmp(&key),
+            None => unreachable!(),
+        })
+    }
+
+    fn value_mut(&mut self, ndx: usize) -> &mut V {
+        match &mut self.contents[ndx] {
+            Some((_, v)) => v,
+            None => unreachable!(),
+        }
+    }
+
+    ///
+    /// Gets the value that corresponds to `key`, returning `None` if no
+    /// such key is in the map.
+    ///
+    pub fn get(&self, key: K) -> Option<V> {
+        let ndx = self.search(key).ok()?;
+        self.contents[ndx].map(|(_, v)| v)
+    }
+
+    ///
+    /// Gets a mutable reference to the value that corresponds to `key`,
+    /// returning `None` if no such key is in the map.
+    ///
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let ndx = self.search(key).ok()?;
+        Some(self.value_mut(ndx))
+    }
+
+    /// Returns `true` if `key` is in the map.
+    pub fn contains_key(&self, key: K) -> bool {
+        self.search(key).is_ok()
     }
 
     ///
@@ -59,60 +285,511 @@ impl<K: Copy + PartialEq, V: Copy, const N: usize> FixedMap<K, V, { N }> {
     /// is room in the map; if the map is full, this code will panic.
     ///
     pub fn insert(&mut self, key: K, value: V) {
-        for i in 0..self.contents.len() {
-            match self.contents[i] {
-                None => {
-                    self.contents[i] = Some((key, value));
-                    return;
-                }
+        if self.try_insert(key, value).is_err() {
+            panic!("SortedFixedMap overflow");
+        }
+    }
 
-                Some((k, _)) => {
-                    if k == key {
-                        self.contents[i] = Some((key, value));
-                        return;
-                    }
-                }
+    ///
+    /// Inserts the `value` into the map for the specified `key`, overwriting
+    /// any value already there.  If the key is not in the map and the map is
+    /// full, returns [`MapFull`] and leaves the map unchanged.
+    ///
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), MapFull> {
+        *self.get_or_insert(key, value)? = value;
+        Ok(())
+    }
+
+    ///
+    /// Gets a mutable reference to the value for `key`, first inserting
+    /// `value` if the key is not in the map.  Returns [`MapFull`] if the key
+    /// needs to be inserted, but the map is full.
+    ///
+    pub fn get_or_insert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<&mut V, MapFull> {
+        self.get_or_insert_with(key, || value)
+    }
+
+    ///
+    /// Like [`SortedFixedMap::get_or_insert`], but calls `f` to produce the
+    /// value to insert (and only if it needs to be inserted).
+    ///
+    pub fn get_or_insert_with(
+        &mut self,
+        key: K,
+        f: impl FnOnce() -> V,
+    ) -> Result<&mut V, MapFull> {
+        let ndx = match self.search(key) {
+            Ok(ndx) => ndx,
+            Err(_) if self.len == N => return Err(MapFull),
+            Err(ndx) => {
+                self.contents[ndx..=self.len].rotate_right(1);
+                self.contents[ndx] = Some((key, f()));
+                self.len += 1;
+                ndx
             }
-        }
+        };
 
-        panic!("FixedMap overflow");
+        Ok(self.value_mut(ndx))
     }
 
     ///
     /// Removes the specified key from the map.
     ///
     pub fn remove(&mut self, key: K) {
-        let mut found = None;
-        let mut swap = None;
+        if let Ok(ndx) = self.search(key) {
+            self.contents[ndx..self.len].rotate_left(1);
+            self.len -= 1;
+            self.contents[self.len] = None;
+        }
+    }
 
-        for i in 0..self.contents.len() {
-            match self.contents[i] {
-                None => {
-                    break;
-                }
-                Some((k, _)) => {
-                    if k == key {
-                        found = Some(i);
-                    } else {
-                        if found.is_some() {
-                            swap = Some(i);
-                        }
-                    }
-                }
+    ///
+    /// Retains only the entries for which `f` returns `true`, removing the
+    /// rest.
+    ///
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        let mut kept = 0;
+
+        for i in 0..self.len {
+            let keep = match &mut self.contents[i] {
+                Some((k, v)) => f(k, v),
+                None => unreachable!(),
+            };
+
+            if keep {
+                self.contents[kept] = self.contents[i];
+                kept += 1;
             }
         }
 
-        match (found, swap) {
-            (Some(found), Some(swap)) => {
-                self.contents[found] = self.contents[swap];
-                self.contents[swap] = None;
-            }
+        for entry in &mut self.contents[kept..self.len] {
+            *entry = None;
+        }
+
+        self.len = kept;
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if no more keys can be inserted into the map.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns an iterator over the entries in the map, in order of key.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.contents[..self.len]
+            .iter()
+            .filter_map(|e| e.as_ref().map(|(k, v)| (k, v)))
+    }
+
+    ///
+    /// Returns an iterator over the entries in the map, in order of key,
+    /// allowing the values to be modified.
+    ///
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.contents[..self.len]
+            .iter_mut()
+            .filter_map(|e| e.as_mut().map(|(k, v)| (&*k, v)))
+    }
+}
+
+impl<K: Copy + Ord, V: Copy, const N: usize> Default
+    for SortedFixedMap<K, V, { N }>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests basic insertion and lookup in a `FixedMap`.
+    #[test]
+    fn insert() {
+        let mut map = FixedMap::<u8, u32, 4>::new();
+
+        assert!(map.is_empty());
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.get(1), None);
+
+        map.insert(1, 10);
+        map.insert(2, 20);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(1), Some(10));
+        assert_eq!(map.get(2), Some(20));
+        assert_eq!(map.get(3), None);
+        assert!(map.contains_key(2));
+        assert!(!map.contains_key(3));
+
+        // Inserting an existing key overwrites its value.
+        map.insert(1, 11);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(1), Some(11));
+
+        *map.get_mut(2).unwrap() += 1;
+        assert_eq!(map.get(2), Some(21));
+        assert!(map.get_mut(3).is_none());
+    }
+
+    /// Tests that a full `FixedMap` refuses new keys, but not existing ones.
+    #[test]
+    fn overflow() {
+        let mut map = FixedMap::<u8, u32, 3>::new();
+
+        for k in 0..3 {
+            assert!(!map.is_full());
+            assert_eq!(map.try_insert(k, u32::from(k)), Ok(()));
+        }
+
+        assert!(map.is_full());
+        assert_eq!(map.try_insert(3, 3), Err(MapFull));
+        assert_eq!(map.get(3), None);
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.try_insert(1, 100), Ok(()));
+        assert_eq!(map.get(1), Some(100));
+
+        map.remove(0);
+        assert!(!map.is_full());
+        assert_eq!(map.try_insert(3, 3), Ok(()));
+    }
+
+    /// Tests removal from a `FixedMap`, including of keys that aren't there.
+    #[test]
+    fn remove() {
+        let mut map = FixedMap::<u8, u32, 4>::new();
+
+        for k in 0..4 {
+            map.insert(k, u32::from(k) * 10);
+        }
+
+        map.remove(1);
+        map.remove(7);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(1), None);
+        assert_eq!(map.get(0), Some(0));
+        assert_eq!(map.get(2), Some(20));
+        assert_eq!(map.get(3), Some(30));
+
+        for k in 0..4 {
+            map.remove(k);
+        }
+
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    /// Tests iteration over a `FixedMap`.
+    #[test]
+    fn iter() {
+        let mut map = FixedMap::<u8, u32, 8>::new();
+
+        for k in 0..5 {
+            map.insert(k, u32::from(k));
+        }
+
+        map.remove(2);
+
+        let mut seen = 0u32;
+
+        for (&k, &v) in map.iter() {
+            assert_eq!(u32::from(k), v);
+            assert_eq!(seen & (1 << k), 0);
+            seen |= 1 << k;
+        }
+
+        assert_eq!(seen, 0b11011);
+
+        for (_, v) in map.iter_mut() {
+            *v *= 2;
+        }
+
+        for k in [0, 1, 3, 4] {
+            assert_eq!(map.get(k), Some(u32::from(k) * 2));
+        }
+    }
+
+    /// Tests the get-or-insert API of a `FixedMap`.
+    #[test]
+    fn get_or_insert() {
+        let mut map = FixedMap::<u8, u32, 2>::new();
+
+        *map.get_or_insert(1, 0).unwrap() += 5;
+        *map.get_or_insert(1, 0).unwrap() += 5;
+        assert_eq!(map.get(1), Some(10));
+
+        // The closure is only called if the key needs to be inserted.
+        let v = map.get_or_insert_with(1, || panic!()).unwrap();
+        assert_eq!(*v, 10);
+
+        assert_eq!(map.get_or_insert_with(2, || 20).map(|v| *v), Ok(20));
+        assert_eq!(map.get_or_insert(3, 30), Err(MapFull));
+        assert_eq!(map.len(), 2);
+    }
+
+    /// Tests retaining a subset of the entries of a `FixedMap`.
+    #[test]
+    fn retain() {
+        let mut map = FixedMap::<u8, u32, 8>::new();
+
+        for k in 0..8 {
+            map.insert(k, u32::from(k));
+        }
+
+        map.retain(|&k, v| {
+            *v += 100;
+            k % 3 != 0
+        });
+
+        assert_eq!(map.len(), 5);
+
+        for k in 0..8 {
+            let expected = if k % 3 != 0 {
+                Some(u32::from(k) + 100)
+            } else {
+                None
+            };
 
-            (Some(found), None) => {
-                self.contents[found] = None;
+            assert_eq!(map.get(k), expected);
+        }
+
+        // The space freed up by retain is usable again.
+        for k in 10..13 {
+            map.insert(k, 0);
+        }
+
+        assert!(map.is_full());
+
+        map.retain(|_, _| false);
+        assert!(map.is_empty());
+    }
+
+    /// Tests basic insertion and lookup in a `SortedFixedMap`, and that its
+    /// entries stay sorted regardless of the order of insertion.
+    #[test]
+    fn sorted_insert() {
+        let mut map = SortedFixedMap::<u8, u32, 8>::new();
+
+        assert!(map.is_empty());
+        assert_eq!(map.get(1), None);
+
+        for &k in &[5, 1, 7, 3, 0, 6] {
+            map.insert(k, u32::from(k) * 10);
+        }
+
+        map.insert(3, 33);
+
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get(3), Some(33));
+        assert_eq!(map.get(7), Some(70));
+        assert_eq!(map.get(2), None);
+        assert_eq!(map.get(8), None);
+        assert!(map.contains_key(0));
+        assert!(!map.contains_key(4));
+
+        let mut last = None;
+
+        for (&k, _) in map.iter() {
+            assert!(last < Some(k));
+            last = Some(k);
+        }
+
+        for (_, v) in map.iter_mut() {
+            *v += 1;
+        }
+
+        *map.get_mut(0).unwrap() += 1;
+        assert_eq!(map.get(0), Some(2));
+        assert_eq!(map.get(5), Some(51));
+    }
+
+    /// Tests that a full `SortedFixedMap` refuses new keys, but not existing
+    /// ones.
+    #[test]
+    fn sorted_overflow() {
+        let mut map = SortedFixedMap::<u8, u32, 3>::new();
+
+        for &k in &[9, 3, 6] {
+            assert!(!map.is_full());
+            assert_eq!(map.try_insert(k, u32::from(k)), Ok(()));
+        }
+
+        assert!(map.is_full());
+        assert_eq!(map.try_insert(0, 0), Err(MapFull));
+        assert_eq!(map.try_insert(10, 0), Err(MapFull));
+        assert_eq!(map.try_insert(6, 60), Ok(()));
+        assert_eq!(map.get(6), Some(60));
+        assert_eq!(map.len(), 3);
+    }
+
+    /// Tests removal from a `SortedFixedMap`.
+    #[test]
+    fn sorted_remove() {
+        let mut map = SortedFixedMap::<u8, u32, 4>::new();
+
+        for k in 0..4 {
+            map.insert(k, u32::from(k));
+        }
+
+        map.remove(0);
+        map.remove(2);
+        map.remove(9);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(1), Some(1));
+        assert_eq!(map.get(3), Some(3));
+
+        let mut iter = map.iter();
+        assert_eq!(iter.next(), Some((&1, &1)));
+        assert_eq!(iter.next(), Some((&3, &3)));
+        assert_eq!(iter.next(), None);
+        drop(iter);
+
+        map.insert(2, 2);
+        map.insert(0, 0);
+        assert!(map.is_full());
+
+        for k in 0..4 {
+            map.remove(k);
+        }
+
+        assert!(map.is_empty());
+    }
+
+    /// Tests the get-or-insert API of a `SortedFixedMap`.
+    #[test]
+    fn sorted_get_or_insert() {
+        let mut map = SortedFixedMap::<u8, u32, 2>::new();
+
+        *map.get_or_insert(4, 0).unwrap() += 5;
+        *map.get_or_insert(4, 0).unwrap() += 5;
+        assert_eq!(map.get(4), Some(10));
+
+        let v = map.get_or_insert_with(4, || panic!()).unwrap();
+        assert_eq!(*v, 10);
+
+        assert_eq!(map.get_or_insert_with(2, || 20).map(|v| *v), Ok(20));
+        assert_eq!(map.get_or_insert(3, 30), Err(MapFull));
+        assert_eq!(map.iter().next(), Some((&2, &20)));
+    }
+
+    /// Tests retaining a subset of the entries of a `SortedFixedMap`.
+    #[test]
+    fn sorted_retain() {
+        let mut map = SortedFixedMap::<u8, u32, 8>::new();
+
+        for k in (0..8).rev() {
+            map.insert(k, u32::from(k));
+        }
+
+        map.retain(|&k, v| {
+            *v += 100;
+            k % 2 == 0
+        });
+
+        assert_eq!(map.len(), 4);
+
+        let mut iter = map.iter();
+
+        for k in [0, 2, 4, 6] {
+            assert_eq!(iter.next(), Some((&k, &(u32::from(k) + 100))));
+        }
+
+        assert_eq!(iter.next(), None);
+        drop(iter);
+
+        map.insert(5, 5);
+        assert_eq!(map.get(5), Some(5));
+        assert_eq!(map.get(6), Some(106));
+    }
+
+    /// Tests that a map with no room at all is both empty and full.
+    #[test]
+    fn zero_sized() {
+        let mut map = FixedMap::<u8, u32, 0>::new();
+        assert!(map.is_empty());
+        assert!(map.is_full());
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.try_insert(1, 1), Err(MapFull));
+        assert_eq!(map.get_or_insert(1, 1), Err(MapFull));
+        assert_eq!(map.get(1), None);
+        map.remove(1);
+        map.retain(|_, _| true);
+        assert_eq!(map.iter().count(), 0);
+
+        let mut map = SortedFixedMap::<u8, u32, 0>::new();
+        assert!(map.is_empty());
+        assert!(map.is_full());
+        assert_eq!(map.try_insert(1, 1), Err(MapFull));
+        assert_eq!(map.get(1), None);
+        map.remove(1);
+        map.retain(|_, _| true);
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    /// Tests that `FixedMap::insert` panics when the map is full.
+    #[test]
+    #[should_panic(expected = "FixedMap overflow")]
+    fn insert_overflow() {
+        let mut map = FixedMap::<u8, u32, 1>::new();
+        map.insert(0, 0);
+        map.insert(1, 1);
+    }
+
+    /// Tests that `FixedMap::retain` preserves the order of what it keeps.
+    #[test]
+    fn retain_order() {
+        let mut map = FixedMap::<u8, u32, 8>::new();
+
+        for &k in &[7, 2, 5, 0, 3, 6] {
+            map.insert(k, u32::from(k));
+        }
+
+        map.retain(|&k, _| k != 5 && k != 0);
+
+        let keys: Vec<u8> = map.iter().map(|(&k, _)| k).collect();
+        assert_eq!(keys, [7, 2, 3, 6]);
+    }
+
+    /// Tests that a `SortedFixedMap` stays sorted through interleaved
+    /// inserts and removes, checked against a sorted `Vec`.
+    #[test]
+    fn sorted_order() {
+        let mut map = SortedFixedMap::<u8, u32, 16>::new();
+        let mut model: Vec<u8> = vec![];
+        let mut k = 1u8;
+
+        for i in 0..200 {
+            k = k.wrapping_mul(37).wrapping_add(11) % 32;
+
+            if i % 3 == 2 {
+                map.remove(k);
+                model.retain(|&m| m != k);
+            } else if map.try_insert(k, u32::from(k)).is_ok() {
+                if let Err(ndx) = model.binary_search(&k) {
+                    model.insert(ndx, k);
+                }
+            } else {
+                assert!(map.is_full());
+                assert!(model.binary_search(&k).is_err());
             }
 
-            (_, _) => {}
+            let keys: Vec<u8> = map.iter().map(|(&k, _)| k).collect();
+            assert_eq!(keys, model);
         }
     }
 }
@@ -7,12 +7,45 @@
 //! This is mostly intended for compressing data with sections of very low
 //! entropy, such as FPGA bitstreams. It generally performs worse than lz4, but
 //! there don't appear to be any `no_std` lz4 crates out there, no matter what
-//! their READMEs claim.
+//! their READMEs claim -- so we have our own, in the [`lz4`] module, which
+//! decompresses using a bounded window.
+//!
+//! # Container format
+//!
+//! The raw RLE stream produced by [`compress`] has no framing, so a truncated
+//! or corrupted stream can't be told apart from a valid one. For anything
+//! that's going to be stored or shipped, use [`compress_framed`], which
+//! prefixes the stream with a [`Header`] (all fields little-endian):
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | Magic, [`MAGIC`]                        |
+//! | 4      | 2    | Format version, [`VERSION`]             |
+//! | 6      | 2    | Reserved, must be zero                  |
+//! | 8      | 4    | Length of the uncompressed data         |
+//! | 12     | 4    | CRC-32 (IEEE) of the uncompressed data  |
+//!
+//! A [`Decompressor`] created with [`Decompressor::framed`] expects this
+//! header, and checks the length and CRC incrementally as it streams --
+//! reporting a bad CRC on the call that produces the last byte, and a
+//! truncated stream from [`Decompressor::finish`]. Headerless streams are
+//! decompressed with [`Decompressor::raw`] (or `Decompressor::default()`).
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 use core::convert::TryFrom;
 
+pub mod lz4;
+
+/// Magic number at the start of a framed stream.
+pub const MAGIC: [u8; 4] = *b"GNRL";
+
+/// Version of the container format.
+pub const VERSION: u16 = 1;
+
+/// Size of the container header, in bytes.
+pub const HEADER_SIZE: usize = 16;
+
 /// Internal definition of how long the run count is. Tuning this might improve
 /// performance, though its current value seems optimal in practice.
 type RunType = u8;
@@ -60,6 +93,17 @@ pub fn compress<E>(
     Ok(())
 }
 
+/// Compresses data from `input` into a framed stream, handing the results to
+/// `out` as with [`compress`]. As the header describes the whole of the
+/// uncompressed data, `input` must be complete (and smaller than 4 GiB).
+pub fn compress_framed<E>(
+    input: &[u8],
+    mut out: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    out(&Header::for_data(input).to_bytes())?;
+    compress(input, out)
+}
+
 fn generate_run<E>(
     byte: u8,
     count: usize,
@@ -76,25 +120,211 @@ fn generate_run<E>(
     Ok(())
 }
 
+/// Things that can go wrong while decompressing a framed stream.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The stream does not start with [`MAGIC`].
+    BadMagic,
+    /// The stream is of a version (or has reserved bits) we don't understand.
+    BadVersion,
+    /// The stream ended before producing the length given in its header.
+    Truncated,
+    /// The stream continues beyond the length given in its header.
+    TooLong,
+    /// The decompressed data does not match the CRC in the header.
+    BadCrc,
+    /// An LZ4 match refers to data outside of the window.
+    BadOffset,
+}
+
+/// Header of a framed stream.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    /// Length of the uncompressed data.
+    pub length: u32,
+    /// CRC-32 of the uncompressed data.
+    pub crc: u32,
+}
+
+impl Header {
+    /// Creates the header for (uncompressed) `data`.
+    pub fn for_data(data: &[u8]) -> Self {
+        let mut crc = Crc32::new();
+        crc.update(data);
+
+        Self {
+            length: u32::try_from(data.len()).unwrap(),
+            crc: crc.finish(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.length.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; HEADER_SIZE]) -> Result<Self, Error> {
+        let word = |i: usize| {
+            u32::from_le_bytes([
+                bytes[i],
+                bytes[i + 1],
+                bytes[i + 2],
+                bytes[i + 3],
+            ])
+        };
+
+        if bytes[0..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+
+        if word(4) != u32::from(VERSION) {
+            return Err(Error::BadVersion);
+        }
+
+        Ok(Self {
+            length: word(8),
+            crc: word(12),
+        })
+    }
+}
+
+/// Incremental CRC-32, as used by (e.g.) Ethernet and zip: the reflected form
+/// of polynomial 0x04C11DB7, with an initial value and final XOR of all ones.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    const TABLE: [u32; 256] = Self::table();
+
+    const fn table() -> [u32; 256] {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                bit += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    }
+
+    pub fn new() -> Self {
+        Self(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            let index = (self.0 ^ u32::from(byte)) as u8;
+            self.0 = Self::TABLE[usize::from(index)] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// State that you're expected to hang on to while decompressing something.
-pub struct Decompressor(DState);
+pub struct Decompressor {
+    frame: Frame,
+    run: DState,
+}
 
 impl Decompressor {
+    /// Creates a decompressor for a raw (headerless) stream, as produced by
+    /// [`compress`]. Such a stream is not checked in any way.
+    pub fn raw() -> Self {
+        Self {
+            frame: Frame::Raw,
+            run: DState::Copying,
+        }
+    }
+
+    /// Creates a decompressor for a framed stream, as produced by
+    /// [`compress_framed`].
+    pub fn framed() -> Self {
+        Self {
+            frame: Frame::Header {
+                bytes: [0; HEADER_SIZE],
+                filled: 0,
+            },
+            run: DState::Copying,
+        }
+    }
+
+    /// Returns `true` if the decompressor has no output pending -- i.e., if
+    /// it isn't in the middle of a run.
     pub fn is_idle(&self) -> bool {
-        matches!(self.0, DState::Copying)
+        !matches!(self.run, DState::Repeating(..))
+    }
+
+    /// Checks that a stream was complete, once all of its input has been
+    /// handed to [`decompress`] and the decompressor is idle.
+    pub fn finish(&self) -> Result<(), Error> {
+        match self.frame {
+            Frame::Raw | Frame::Done if self.at_boundary() => Ok(()),
+            _ => Err(Error::Truncated),
+        }
+    }
+
+    /// Returns `true` if we're between runs (and escape sequences).
+    fn at_boundary(&self) -> bool {
+        matches!(self.run, DState::Copying)
     }
 }
 
 impl Default for Decompressor {
+    /// Creates a decompressor for a raw stream, as [`Decompressor::raw`].
     fn default() -> Self {
-        Self(DState::Copying)
+        Self::raw()
     }
 }
 
+enum Frame {
+    /// There is no header: we're just decompressing.
+    Raw,
+    /// We're accumulating the header, of which we have `filled` bytes.
+    Header {
+        bytes: [u8; HEADER_SIZE],
+        filled: usize,
+    },
+    /// We're decompressing the body, of which `remaining` bytes are yet to
+    /// be produced.
+    Body {
+        remaining: u32,
+        crc: Crc32,
+        expected: u32,
+    },
+    /// We have produced (and checked) all of the data.
+    Done,
+}
+
 enum DState {
     /// We're not in a run, we're just copying bytes to the output and watching
     /// for the escape byte.
     Copying,
+    /// We've seen the escape byte, but not the byte that follows it.
+    Escaped,
+    /// We've seen the escape byte and the byte to repeat, but not the count.
+    EscapedByte(u8),
     /// We're in a run, we are going to produce the given byte N times, where
     /// the count on the right is `N-1`.
     Repeating(u8, RunType),
@@ -115,11 +345,84 @@ enum DState {
 ///   complete decompressed form. (Otherwise, find or reuse an output buffer and
 ///   call `decompress(state, &mut &[], output)` until the decompressor becomes
 ///   idle.)
+///
+/// For a framed stream, this returns an error if the header is bad, if the
+/// stream continues past the length in its header, or -- on the call that
+/// produces the last byte -- if the CRC doesn't match. Once the input has been
+/// consumed, call [`Decompressor::finish`] to check that nothing is missing.
 pub fn decompress<'a>(
     state: &mut Decompressor,
     input: &mut &[u8],
     output: &'a mut [u8],
-) -> &'a [u8] {
+) -> Result<&'a [u8], Error> {
+    loop {
+        match &mut state.frame {
+            Frame::Raw => {
+                let n = decompress_run(&mut state.run, input, output);
+                return Ok(&output[..n]);
+            }
+            Frame::Header { bytes, filled } => {
+                let n = usize::min(HEADER_SIZE - *filled, input.len());
+                bytes[*filled..*filled + n].copy_from_slice(&input[..n]);
+                *input = &input[n..];
+                *filled += n;
+
+                if *filled < HEADER_SIZE {
+                    return Ok(&[]);
+                }
+
+                let header = Header::from_bytes(bytes)?;
+
+                state.frame = if header.length == 0 {
+                    Frame::Done
+                } else {
+                    Frame::Body {
+                        remaining: header.length,
+                        crc: Crc32::new(),
+                        expected: header.crc,
+                    }
+                };
+            }
+            Frame::Body {
+                remaining,
+                crc,
+                expected,
+            } => {
+                let limit = usize::min(output.len(), *remaining as usize);
+                let n =
+                    decompress_run(&mut state.run, input, &mut output[..limit]);
+
+                crc.update(&output[..n]);
+                *remaining -= n as u32;
+
+                if *remaining == 0 {
+                    if crc.finish() != *expected {
+                        return Err(Error::BadCrc);
+                    }
+
+                    state.frame = Frame::Done;
+                }
+
+                return Ok(&output[..n]);
+            }
+            Frame::Done => {
+                if !input.is_empty() || !state.at_boundary() {
+                    return Err(Error::TooLong);
+                }
+
+                return Ok(&[]);
+            }
+        }
+    }
+}
+
+/// Decompresses RLE data from `input` into `output`, returning the number of
+/// bytes written.
+fn decompress_run(
+    state: &mut DState,
+    input: &mut &[u8],
+    output: &mut [u8],
+) -> usize {
     fn take_byte(input: &mut &[u8]) -> Option<u8> {
         let (first, rest) = input.split_first()?;
         *input = rest;
@@ -128,26 +431,28 @@ pub fn decompress<'a>(
 
     let mut n = 0;
     while n < output.len() {
-        match &mut state.0 {
+        match state {
             DState::Repeating(byte, count) => {
                 output[n] = *byte;
                 n += 1;
                 if let Some(new_count) = count.checked_sub(1) {
                     *count = new_count;
                 } else {
-                    state.0 = DState::Copying;
+                    *state = DState::Copying;
                 }
             }
+            // An escape sequence may be split across chunks of input, so we
+            // keep track of how much of it we've seen.
+            DState::Escaped => match take_byte(input) {
+                Some(byte) => *state = DState::EscapedByte(byte),
+                None => break,
+            },
+            DState::EscapedByte(byte) => match take_byte(input) {
+                Some(count) => *state = DState::Repeating(*byte, count),
+                None => break,
+            },
             DState::Copying => match take_byte(input) {
-                Some(ESC) => {
-                    let actual_byte = take_byte(input);
-                    let count = take_byte(input);
-                    if let (Some(ab), Some(c)) = (actual_byte, count) {
-                        state.0 = DState::Repeating(ab, c);
-                    } else {
-                        break;
-                    }
-                }
+                Some(ESC) => *state = DState::Escaped,
                 Some(byte) => {
                     output[n] = byte;
                     n += 1;
@@ -157,5 +462,129 @@ pub fn decompress<'a>(
         }
     }
 
-    &output[..n]
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Produces some data with runs (including of the escape byte) and
+    /// non-runs.
+    fn data() -> Vec<u8> {
+        (0..192)
+            .map(|i| match i % 128 {
+                0..=39 => 0,
+                40..=47 => ESC,
+                _ => (i * 7 % 251) as u8,
+            })
+            .collect()
+    }
+
+    fn framed(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        compress_framed(data, |chunk| {
+            out.extend_from_slice(chunk);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        out
+    }
+
+    /// Decompresses `input` with `decompressor`, feeding it in chunks of
+    /// `chunk` bytes through a small output buffer.
+    fn check(
+        mut decompressor: Decompressor,
+        mut input: &[u8],
+        chunk: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut out = [0; 13];
+        let mut result = vec![];
+
+        while !input.is_empty() || !decompressor.is_idle() {
+            let mut piece = &input[..usize::min(chunk, input.len())];
+            let len = piece.len();
+            let produced = decompress(&mut decompressor, &mut piece, &mut out)?;
+            result.extend_from_slice(produced);
+            input = &input[len - piece.len()..];
+        }
+
+        decompressor.finish()?;
+        Ok(result)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = data();
+        let buf = framed(&data);
+
+        assert!(buf.len() < data.len());
+
+        for &chunk in &[1, 2, 3, 16, buf.len()] {
+            assert_eq!(
+                check(Decompressor::framed(), &buf, chunk),
+                Ok(data.clone())
+            );
+        }
+
+        // An empty container is fine, too.
+        let buf = framed(&[]);
+        assert_eq!(buf.len(), HEADER_SIZE);
+        assert_eq!(check(Decompressor::framed(), &buf, 5), Ok(vec![]));
+    }
+
+    #[test]
+    fn default_is_raw() {
+        let data = data();
+        let mut buf = vec![];
+        compress(&data, |chunk| {
+            buf.extend_from_slice(chunk);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+        assert_eq!(check(Decompressor::default(), &buf, 7), Ok(data.clone()));
+        assert_eq!(check(Decompressor::raw(), &buf, 7), Ok(data));
+
+        // A framed decompressor won't take a raw stream.
+        assert_eq!(
+            check(Decompressor::framed(), &buf, 7),
+            Err(Error::BadMagic)
+        );
+    }
+
+    #[test]
+    fn corruption() {
+        let data = data();
+        let mut buf = framed(&data);
+        let len = buf.len();
+
+        let mut corrupt = |offset: usize, bits: u8| {
+            buf[offset] ^= bits;
+            let result = check(Decompressor::framed(), &buf, 7);
+            buf[offset] ^= bits;
+            result
+        };
+
+        assert_eq!(corrupt(0, 0x01), Err(Error::BadMagic));
+        assert_eq!(corrupt(4, 0x02), Err(Error::BadVersion));
+        assert_eq!(corrupt(6, 0x80), Err(Error::BadVersion));
+        assert_eq!(corrupt(12, 0x10), Err(Error::BadCrc));
+
+        // Any single-bit error in the body must be caught, one way or
+        // another.
+        for offset in HEADER_SIZE..len {
+            assert!(corrupt(offset, 0x04).is_err());
+        }
+
+        for short in 0..len {
+            assert!(check(Decompressor::framed(), &buf[..short], 16).is_err());
+        }
+
+        buf.push(0);
+        assert_eq!(
+            check(Decompressor::framed(), &buf, 16),
+            Err(Error::TooLong)
+        );
+    }
 }
@@ -0,0 +1,482 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! LZ4 block compression, with a bounded window.
+//!
+//! This produces and consumes the [LZ4 block format][lz4], and so
+//! interoperates with other LZ4 implementations -- with one caveat: the
+//! decompressor keeps only a bounded window of its most recent output (in a
+//! buffer supplied by the caller, which may well be a `static`), rather than
+//! requiring the whole output to be addressable. Data to be decompressed
+//! here must have been compressed with a window no larger than that buffer,
+//! which [`compress`] takes as a parameter. (Data compressed by other LZ4
+//! implementations may use offsets of up to 64 KiB.)
+//!
+//! Where RLE handles little beyond runs of a single byte, LZ4 finds repeats
+//! of any data within its window, which makes it much better on things like
+//! task images -- and usually better on FPGA bitstreams, too.
+//!
+//! As with the RLE functions in the crate root, the decompressor streams:
+//! input and output can be chopped up however is convenient. Note that an
+//! LZ4 block carries neither its length nor a checksum: a block that has been
+//! truncated at a sequence boundary looks like a valid (shorter) block, so
+//! anything stored this way should have its length and integrity checked by
+//! other means.
+//!
+//! [lz4]: https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md
+
+use crate::Error;
+use core::convert::TryFrom;
+
+/// Shortest match that can be encoded.
+const MIN_MATCH: usize = 4;
+
+/// The format requires that the last 5 bytes of a block are literals...
+const LAST_LITERALS: usize = 5;
+
+/// ...and that the last match start at least 12 bytes before its end.
+const MF_LIMIT: usize = 12;
+
+/// Largest offset that can be encoded.
+pub const MAX_WINDOW: usize = 0xffff;
+
+/// Size of the compressor's hash table, in bits.
+const HASH_BITS: u32 = 12;
+
+/// Compresses `input` as a single LZ4 block, handing the results to `out` as
+/// small slices. Matches are restricted to the `window` bytes preceding
+/// them, so a [`Decompressor`] with a window of at least this size can
+/// decompress the result.
+///
+/// This is intended for use on the host (e.g., in a `build.rs`): it needs to
+/// see the whole input at once, and puts a 16 KiB hash table on the stack.
+pub fn compress<E>(
+    input: &[u8],
+    window: usize,
+    mut out: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let window = window.min(MAX_WINDOW);
+    let mut table = [u32::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+
+    let hash = |i: usize| {
+        let word = u32::from_le_bytes([
+            input[i],
+            input[i + 1],
+            input[i + 2],
+            input[i + 3],
+        ]);
+        (word.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+    };
+
+    while window > 0 && i + MF_LIMIT <= input.len() {
+        let h = hash(i);
+        let candidate = table[h] as usize;
+        table[h] = u32::try_from(i).unwrap();
+
+        let found = candidate < i
+            && i - candidate <= window
+            && input[candidate..candidate + MIN_MATCH]
+                == input[i..i + MIN_MATCH];
+
+        if !found {
+            i += 1;
+            continue;
+        }
+
+        let limit = input.len() - LAST_LITERALS;
+        let mut len = MIN_MATCH;
+
+        while i + len < limit && input[candidate + len] == input[i + len] {
+            len += 1;
+        }
+
+        sequence(&input[anchor..i], Some((i - candidate, len)), &mut out)?;
+
+        // Index the positions we've skipped over, so that later matches can
+        // refer to them.
+        for j in i + 1..(i + len).min(input.len() - MIN_MATCH) {
+            table[hash(j)] = u32::try_from(j).unwrap();
+        }
+
+        i += len;
+        anchor = i;
+    }
+
+    sequence(&input[anchor..], None, &mut out)
+}
+
+/// Emits a sequence: some literals, followed by a match (unless this is the
+/// last sequence in the block).
+fn sequence<E>(
+    literals: &[u8],
+    matched: Option<(usize, usize)>,
+    out: &mut impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let nliterals = literals.len();
+    let nmatch = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+
+    out(&[(nliterals.min(15) << 4 | nmatch.min(15)) as u8])?;
+
+    if nliterals >= 15 {
+        length(nliterals - 15, out)?;
+    }
+
+    out(literals)?;
+
+    if let Some((offset, _)) = matched {
+        out(&u16::try_from(offset).unwrap().to_le_bytes())?;
+
+        if nmatch >= 15 {
+            length(nmatch - 15, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Emits the remainder of a length that didn't fit in its token.
+fn length<E>(
+    mut len: usize,
+    out: &mut impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    while len >= 255 {
+        out(&[255])?;
+        len -= 255;
+    }
+
+    out(&[len as u8])
+}
+
+/// State that you're expected to hang on to while decompressing something,
+/// including the window of recent output.
+pub struct Decompressor<'w> {
+    window: &'w mut [u8],
+    /// Position in `window` at which the next byte will be written.
+    pos: usize,
+    /// Number of bytes produced, up to the size of the window.
+    valid: usize,
+    state: DState,
+}
+
+enum DState {
+    /// Expecting the token that starts a sequence.
+    Token,
+    /// Accumulating a literal length that didn't fit in the token.
+    LiteralLength { len: usize, token: u8 },
+    /// Copying literals from the input.
+    Literals { remaining: usize, token: u8 },
+    /// Expecting a match offset, of which we may have the low byte. The block
+    /// can end here (and only here), without a match.
+    Offset { low: Option<u8>, token: u8 },
+    /// Accumulating a match length that didn't fit in the token.
+    MatchLength { offset: usize, len: usize },
+    /// Copying a match from the window.
+    Match { offset: usize, remaining: usize },
+}
+
+impl<'w> Decompressor<'w> {
+    /// Creates a decompressor that uses `window` for its window, which must be
+    /// at least as large as that used to compress the data.
+    pub fn new(window: &'w mut [u8]) -> Self {
+        assert!(!window.is_empty());
+
+        Self {
+            window,
+            pos: 0,
+            valid: 0,
+            state: DState::Token,
+        }
+    }
+
+    /// Returns `true` if the decompressor has no output pending -- i.e., if
+    /// it isn't in the middle of a match.
+    pub fn is_idle(&self) -> bool {
+        !matches!(self.state, DState::Match { .. })
+    }
+
+    /// Checks that a block was complete, once all of its input has been
+    /// handed to [`decompress`] and the decompressor is idle.
+    pub fn finish(&self) -> Result<(), Error> {
+        match self.state {
+            DState::Offset { low: None, .. } => Ok(()),
+            _ => Err(Error::Truncated),
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.window[self.pos] = byte;
+        self.pos = (self.pos + 1) % self.window.len();
+        self.valid = usize::min(self.valid + 1, self.window.len());
+    }
+}
+
+/// Decompresses a chunk of an LZ4 block `input`, writing results to the start
+/// of `output`. Returns the prefix of `output` that was written.
+///
+/// As with [`crate::decompress`], `input` is updated to lop off the bytes that
+/// have been consumed, and decompression stops when we reach the end of
+/// either `input` or `output`. Keep calling this until `input.is_empty()`
+/// and `state.is_idle()`, and then call [`Decompressor::finish`] to check
+/// that the block was complete.
+///
+/// This returns [`Error::BadOffset`] if a match reaches back beyond the
+/// window, or beyond the start of the output.
+pub fn decompress<'a>(
+    state: &mut Decompressor<'_>,
+    input: &mut &[u8],
+    output: &'a mut [u8],
+) -> Result<&'a [u8], Error> {
+    fn take_byte(input: &mut &[u8]) -> Option<u8> {
+        let (first, rest) = input.split_first()?;
+        *input = rest;
+        Some(*first)
+    }
+
+    let mut n = 0;
+
+    loop {
+        match state.state {
+            DState::Literals {
+                remaining: 0,
+                token,
+            } => {
+                state.state = DState::Offset { low: None, token };
+            }
+            DState::Match { remaining: 0, .. } => {
+                state.state = DState::Token;
+            }
+            DState::Match { offset, remaining } => {
+                if n == output.len() {
+                    break;
+                }
+
+                let len = state.window.len();
+                let byte = state.window[(state.pos + len - offset) % len];
+                state.push(byte);
+                output[n] = byte;
+                n += 1;
+
+                state.state = DState::Match {
+                    offset,
+                    remaining: remaining - 1,
+                };
+            }
+            DState::Literals { remaining, token } => {
+                if n == output.len() {
+                    break;
+                }
+
+                let byte = match take_byte(input) {
+                    Some(byte) => byte,
+                    None => break,
+                };
+
+                state.push(byte);
+                output[n] = byte;
+                n += 1;
+
+                state.state = DState::Literals {
+                    remaining: remaining - 1,
+                    token,
+                };
+            }
+            DState::Token => {
+                let token = match take_byte(input) {
+                    Some(token) => token,
+                    None => break,
+                };
+
+                let len = usize::from(token >> 4);
+
+                state.state = if len == 15 {
+                    DState::LiteralLength { len, token }
+                } else {
+                    DState::Literals {
+                        remaining: len,
+                        token,
+                    }
+                };
+            }
+            DState::LiteralLength { len, token } => {
+                let byte = match take_byte(input) {
+                    Some(byte) => byte,
+                    None => break,
+                };
+
+                let len = len.saturating_add(usize::from(byte));
+
+                state.state = if byte == 255 {
+                    DState::LiteralLength { len, token }
+                } else {
+                    DState::Literals {
+                        remaining: len,
+                        token,
+                    }
+                };
+            }
+            DState::Offset { low, token } => {
+                let byte = match take_byte(input) {
+                    Some(byte) => byte,
+                    None => break,
+                };
+
+                let low = match low {
+                    Some(low) => low,
+                    None => {
+                        state.state = DState::Offset {
+                            low: Some(byte),
+                            token,
+                        };
+                        continue;
+                    }
+                };
+
+                let offset = usize::from(u16::from_le_bytes([low, byte]));
+
+                if offset == 0 || offset > state.valid {
+                    return Err(Error::BadOffset);
+                }
+
+                let len = usize::from(token & 0xf) + MIN_MATCH;
+
+                state.state = if len == 15 + MIN_MATCH {
+                    DState::MatchLength { offset, len }
+                } else {
+                    DState::Match {
+                        offset,
+                        remaining: len,
+                    }
+                };
+            }
+            DState::MatchLength { offset, len } => {
+                let byte = match take_byte(input) {
+                    Some(byte) => byte,
+                    None => break,
+                };
+
+                let len = len.saturating_add(usize::from(byte));
+
+                state.state = if byte == 255 {
+                    DState::MatchLength { offset, len }
+                } else {
+                    DState::Match {
+                        offset,
+                        remaining: len,
+                    }
+                };
+            }
+        }
+    }
+
+    Ok(&output[..n])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of each sample of representative data.
+    const SAMPLE_SIZE: usize = 4096;
+
+    /// Window used to compress and decompress our samples.
+    const WINDOW_SIZE: usize = 1024;
+
+    /// The start of an FPGA bitstream...
+    const BITSTREAM: &[u8] =
+        include_bytes!("../../../drv/gimlet-seq-server/fpga.bin");
+
+    /// ...and some text.
+    const TEXT: &[u8] = include_bytes!("../../../LICENSE.txt");
+
+    fn sample(data: &[u8]) -> &[u8] {
+        &data[..SAMPLE_SIZE.min(data.len())]
+    }
+
+    fn compress_lz4(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        compress(data, WINDOW_SIZE, |chunk| {
+            out.extend_from_slice(chunk);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        out
+    }
+
+    fn compress_rle(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        crate::compress_framed(data, |chunk| {
+            out.extend_from_slice(chunk);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        out
+    }
+
+    /// Tests that our samples survive compression and decompression through
+    /// a bounded window, regardless of chunking.
+    #[test]
+    fn round_trip() {
+        let mut window = [0; WINDOW_SIZE];
+
+        for data in &[sample(BITSTREAM), sample(TEXT)] {
+            let lz4 = compress_lz4(data);
+
+            for &chunk in &[1, 7, 64, lz4.len()] {
+                let mut decompressor = Decompressor::new(&mut window);
+                let mut input = &lz4[..];
+                let mut out = [0; 61];
+                let mut offset = 0;
+
+                while !input.is_empty() || !decompressor.is_idle() {
+                    let mut piece = &input[..usize::min(chunk, input.len())];
+                    let len = piece.len();
+                    let produced =
+                        decompress(&mut decompressor, &mut piece, &mut out)
+                            .unwrap();
+
+                    assert_eq!(
+                        produced,
+                        &data[offset..offset + produced.len()]
+                    );
+                    offset += produced.len();
+                    input = &input[len - piece.len()..];
+                }
+
+                assert_eq!(decompressor.finish(), Ok(()));
+                assert_eq!(offset, data.len());
+            }
+        }
+    }
+
+    /// Tests that a match reaching back before the start of the output is an
+    /// error.
+    #[test]
+    fn bad_offset() {
+        let mut window = [0; WINDOW_SIZE];
+        let mut decompressor = Decompressor::new(&mut window);
+        let mut input: &[u8] = &[0x10, b'a', 0x02, 0x00];
+        let mut out = [0; 4];
+        let result = decompress(&mut decompressor, &mut input, &mut out);
+        assert_eq!(result, Err(Error::BadOffset));
+    }
+
+    /// Compares LZ4 with RLE on our samples. Bitstreams are mostly runs, at
+    /// which RLE is hard to beat -- but LZ4 should keep up, and do much
+    /// better on text.
+    #[test]
+    fn vs_rle() {
+        let data = sample(BITSTREAM);
+        let (rle, lz4) = (compress_rle(data), compress_lz4(data));
+        assert!(rle.len() < data.len() / 2);
+        assert!(lz4.len() < data.len() / 2);
+        assert!(lz4.len() * 10 < rle.len() * 11);
+
+        let data = sample(TEXT);
+        let (rle, lz4) = (compress_rle(data), compress_lz4(data));
+        assert!(rle.len() < data.len());
+        assert!(lz4.len() * 4 < rle.len() * 3);
+    }
+}
@@ -0,0 +1,547 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serial transport for HIF
+//!
+//! Normally, the debugger drives hiffy by writing `HIFFY_TEXT` and
+//! `HIFFY_DATA` over SWD, incrementing `HIFFY_KICK`, and reading back
+//! `HIFFY_RSTACK`.  This crate defines a protocol that does the same over a
+//! serial line, for systems that don't have a probe attached.
+//!
+//! # Framing
+//!
+//! Each message is a frame consisting of a kind byte, a payload of at most
+//! [`MAX_PAYLOAD`] bytes, and the CRC-16 of the kind and payload (see
+//! [`Crc16`]), little-endian.  The frame is COBS-encoded, so it contains no
+//! zero bytes, and is followed by a zero byte as a delimiter.  A receiver
+//! that loses sync -- or that starts listening mid-frame -- need only wait
+//! for the next delimiter, and a sender can send a lone delimiter to flush
+//! out any partial frame at the receiver.
+//!
+//! # Protocol
+//!
+//! The host sends a [`Request`], and hiffy replies with a [`Response`].
+//! Offsets and lengths are little-endian `u32`s:
+//!
+//! | Request        | Payload                    | Response            |
+//! |----------------|----------------------------|---------------------|
+//! | `WriteText`    | offset, then bytes         | `Ack`               |
+//! | `WriteData`    | offset, then bytes         | `Ack`               |
+//! | `Kick`         | (none)                     | `Status`            |
+//! | `ReadRstack`   | offset, length             | `Rstack`            |
+//! | `Status`       | (none)                     | `Status`            |
+//!
+//! `Kick` executes the program in `HIFFY_TEXT`, and replies once it has
+//! completed.  The `Status` payload is the count of successful requests and
+//! the count of failures (as `u32`s, as in `HIFFY_REQUESTS` and
+//! `HIFFY_ERRORS`), followed by the raw contents of `HIFFY_FAILURE`, to be
+//! decoded using the debug information in the archive just as it would be
+//! when read over SWD.  A request that can't be honored gets a `Nack`, with
+//! a [`NackReason`] as its payload.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::convert::TryInto;
+
+/// Largest payload that a frame may carry.
+pub const MAX_PAYLOAD: usize = 256;
+
+/// Largest a frame can be on the wire, including its delimiter.
+pub const MAX_FRAME: usize = encoded_len(1 + MAX_PAYLOAD + 2) + 1;
+
+/// Largest number of bytes that COBS encoding of `len` bytes can produce.
+const fn encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// Messages sent by the host.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Request {
+    WriteText = 1,
+    WriteData = 2,
+    Kick = 3,
+    ReadRstack = 4,
+    Status = 5,
+}
+
+impl Request {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            1 => Some(Request::WriteText),
+            2 => Some(Request::WriteData),
+            3 => Some(Request::Kick),
+            4 => Some(Request::ReadRstack),
+            5 => Some(Request::Status),
+            _ => None,
+        }
+    }
+}
+
+/// Messages sent by hiffy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Response {
+    Ack = 0x81,
+    Nack = 0x82,
+    Rstack = 0x83,
+    Status = 0x84,
+}
+
+impl Response {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0x81 => Some(Response::Ack),
+            0x82 => Some(Response::Nack),
+            0x83 => Some(Response::Rstack),
+            0x84 => Some(Response::Status),
+            _ => None,
+        }
+    }
+}
+
+/// Why a request was refused.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum NackReason {
+    /// The frame was malformed, too long, or failed its CRC.
+    BadFrame = 1,
+    /// The request kind is unknown, or its payload is the wrong size.
+    BadRequest = 2,
+    /// The request would access beyond the end of the target buffer.
+    OutOfBounds = 3,
+}
+
+/// Errors in encoding or decoding a frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameError {
+    /// The payload exceeds [`MAX_PAYLOAD`], or the output buffer is too
+    /// small for the encoded frame.
+    TooLong,
+    /// The frame is not valid COBS, or is too short to hold a kind and CRC.
+    BadEncoding,
+    /// The frame's CRC doesn't match its contents.
+    BadCrc,
+}
+
+/// A decoded frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub kind: u8,
+    pub payload: &'a [u8],
+}
+
+/// Incremental CRC-16/CCITT-FALSE: polynomial 0x1021, not reflected, with
+/// an initial value of all ones and no final XOR.
+#[derive(Copy, Clone, Debug)]
+pub struct Crc16(u16);
+
+impl Crc16 {
+    pub fn new() -> Self {
+        Self(0xffff)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= u16::from(byte) << 8;
+
+            for _ in 0..8 {
+                self.0 = if self.0 & 0x8000 != 0 {
+                    (self.0 << 1) ^ 0x1021
+                } else {
+                    self.0 << 1
+                };
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u16 {
+        self.0
+    }
+}
+
+impl Default for Crc16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// COBS encoder writing into a fixed buffer.
+struct CobsWriter<'a> {
+    out: &'a mut [u8],
+    /// Where the code byte for the current block goes.
+    code_ndx: usize,
+    /// Code for the current block: one more than its length.
+    code: u8,
+    pos: usize,
+}
+
+impl<'a> CobsWriter<'a> {
+    fn new(out: &'a mut [u8]) -> Result<Self, FrameError> {
+        if out.is_empty() {
+            return Err(FrameError::TooLong);
+        }
+
+        Ok(Self {
+            out,
+            code_ndx: 0,
+            code: 1,
+            pos: 1,
+        })
+    }
+
+    fn end_block(&mut self) -> Result<(), FrameError> {
+        self.out[self.code_ndx] = self.code;
+        self.code_ndx = self.pos;
+        self.code = 1;
+        self.skip()
+    }
+
+    fn skip(&mut self) -> Result<(), FrameError> {
+        if self.pos == self.out.len() {
+            return Err(FrameError::TooLong);
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), FrameError> {
+        if byte == 0 {
+            return self.end_block();
+        }
+
+        *self.out.get_mut(self.pos).ok_or(FrameError::TooLong)? = byte;
+        self.pos += 1;
+        self.code += 1;
+
+        if self.code == 0xff {
+            self.end_block()?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends the frame, adding the delimiter, and returns its length.
+    fn finish(self) -> Result<usize, FrameError> {
+        self.out[self.code_ndx] = self.code;
+        *self.out.get_mut(self.pos).ok_or(FrameError::TooLong)? = 0;
+        Ok(self.pos + 1)
+    }
+}
+
+/// Encodes a frame of `kind` carrying `payload` into `out`, returning the
+/// number of bytes of `out` used, which includes the delimiter.  `out` need
+/// be no larger than [`MAX_FRAME`].
+pub fn encode(
+    kind: u8,
+    payload: &[u8],
+    out: &mut [u8],
+) -> Result<usize, FrameError> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(FrameError::TooLong);
+    }
+
+    let mut crc = Crc16::new();
+    crc.update(&[kind]);
+    crc.update(payload);
+    let crc = crc.finish().to_le_bytes();
+
+    let mut writer = CobsWriter::new(out)?;
+
+    let bytes = core::iter::once(kind)
+        .chain(payload.iter().copied())
+        .chain(crc.iter().copied());
+
+    for byte in bytes {
+        writer.push(byte)?;
+    }
+
+    writer.finish()
+}
+
+/// Decodes (in place) the COBS-encoded contents of `buf`, which doesn't
+/// include the delimiter, returning the decoded length.
+fn cobs_decode(buf: &mut [u8]) -> Result<usize, FrameError> {
+    let mut r = 0;
+    let mut w = 0;
+
+    while r < buf.len() {
+        let code = buf[r] as usize;
+        r += 1;
+
+        if code == 0 || r + code - 1 > buf.len() {
+            return Err(FrameError::BadEncoding);
+        }
+
+        buf.copy_within(r..r + code - 1, w);
+        r += code - 1;
+        w += code - 1;
+
+        // A block that is less than full stands for its contents followed
+        // by a zero -- except at the very end of the frame.
+        if code != 0xff && r < buf.len() {
+            buf[w] = 0;
+            w += 1;
+        }
+    }
+
+    Ok(w)
+}
+
+/// Checks the CRC of a decoded frame, and splits out its kind and payload.
+fn parse(buf: &[u8]) -> Result<Frame<'_>, FrameError> {
+    if buf.len() < 3 {
+        return Err(FrameError::BadEncoding);
+    }
+
+    let (contents, crc) = buf.split_at(buf.len() - 2);
+    let mut expected = Crc16::new();
+    expected.update(contents);
+
+    if u16::from_le_bytes(crc.try_into().unwrap()) != expected.finish() {
+        return Err(FrameError::BadCrc);
+    }
+
+    Ok(Frame {
+        kind: contents[0],
+        payload: &contents[1..],
+    })
+}
+
+/// Decodes frames from a stream of bytes, as they arrive.
+pub struct Decoder {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    /// Set if the frame in progress has outgrown `buf`.
+    overflowed: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Consumes one byte from the stream.  When `byte` ends a frame, this
+    /// returns the frame (or why it couldn't be decoded); otherwise, or if
+    /// the frame is empty, it returns `None`.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame<'_>, FrameError>> {
+        if byte != 0 {
+            if self.len == self.buf.len() {
+                self.overflowed = true;
+            } else {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+
+            return None;
+        }
+
+        let len = core::mem::replace(&mut self.len, 0);
+
+        if core::mem::replace(&mut self.overflowed, false) {
+            return Some(Err(FrameError::TooLong));
+        }
+
+        if len == 0 {
+            return None;
+        }
+
+        let buf = &mut self.buf[..len];
+
+        Some(cobs_decode(buf).and_then(move |n| parse(&buf[..n])))
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits a little-endian `u32` off the front of `payload`.
+pub fn split_u32(payload: &[u8]) -> Option<(u32, &[u8])> {
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let (word, rest) = payload.split_at(4);
+    Some((u32::from_le_bytes(word.try_into().unwrap()), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = [0; MAX_FRAME];
+        let len = encode(kind, payload, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    /// Feeds `stream` to a decoder, returning every frame that it produces.
+    fn decode(stream: &[u8]) -> Vec<Result<(u8, Vec<u8>), FrameError>> {
+        let mut decoder = Decoder::new();
+
+        stream
+            .iter()
+            .filter_map(|&b| {
+                decoder
+                    .push(b)
+                    .map(|r| r.map(|f| (f.kind, f.payload.to_vec())))
+            })
+            .collect()
+    }
+
+    /// Payloads that exercise COBS: zeros in various places, and runs of
+    /// non-zero bytes on either side of the 254-byte block limit.
+    fn payloads() -> Vec<Vec<u8>> {
+        vec![
+            vec![],
+            vec![0],
+            vec![0, 0, 0],
+            b"hello".to_vec(),
+            vec![1, 0, 2, 0, 0, 3],
+            vec![0xff; 252],
+            vec![0xff; 253],
+            vec![0xff; 254],
+            vec![0; MAX_PAYLOAD],
+            (0..MAX_PAYLOAD).map(|i| (i * 7 % 256) as u8).collect(),
+            (0..MAX_PAYLOAD).map(|i| (i % 255 + 1) as u8).collect(),
+        ]
+    }
+
+    #[test]
+    fn crc() {
+        let mut crc = Crc16::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0x29b1);
+    }
+
+    #[test]
+    fn round_trip() {
+        for payload in payloads() {
+            for &kind in &[Request::WriteText as u8, Response::Status as u8] {
+                let frame = encoded(kind, &payload);
+
+                // Only the delimiter is zero.
+                let zero = frame.iter().position(|&b| b == 0);
+                assert_eq!(zero, Some(frame.len() - 1));
+
+                assert_eq!(decode(&frame), [Ok((kind, payload.clone()))]);
+            }
+        }
+    }
+
+    #[test]
+    fn stream() {
+        let mut stream = vec![0, 0];
+        stream.extend(encoded(Request::Kick as u8, &[]));
+        stream.push(0);
+        stream.extend(encoded(Request::WriteData as u8, &[1, 0, 2]));
+
+        // Empty frames (lone delimiters) are ignored.
+        assert_eq!(
+            decode(&stream),
+            [
+                Ok((Request::Kick as u8, vec![])),
+                Ok((Request::WriteData as u8, vec![1, 0, 2])),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_crc() {
+        let mut frame = encoded(Request::WriteText as u8, b"hello");
+
+        // The first block holds the kind and payload, so flipping a bit of
+        // the payload leaves the encoding intact.
+        frame[2] ^= 0x01;
+        assert_eq!(decode(&frame), [Err(FrameError::BadCrc)]);
+    }
+
+    #[test]
+    fn truncated() {
+        for payload in payloads() {
+            let frame = encoded(Request::WriteData as u8, &payload);
+
+            for len in 1..frame.len() - 1 {
+                let mut stream = frame[..len].to_vec();
+                stream.push(0);
+
+                let frames = decode(&stream);
+                assert_eq!(frames.len(), 1);
+                assert!(frames[0].is_err(), "{:?} at {}", payload, len);
+
+                // A subsequent frame decodes fine.
+                stream.extend(&frame);
+                assert_eq!(
+                    decode(&stream)[1],
+                    Ok((Request::WriteData as u8, payload.clone()))
+                );
+            }
+        }
+
+        // A frame too short to hold a kind and CRC is refused outright.
+        assert_eq!(decode(&[2, 5, 0]), [Err(FrameError::BadEncoding)]);
+
+        // As is a block that runs past the end of the frame.
+        assert_eq!(decode(&[9, 5, 1, 1, 0]), [Err(FrameError::BadEncoding)]);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut stream = vec![0x55; MAX_FRAME + 10];
+        stream.push(0);
+        stream.extend(encoded(Request::Status as u8, &[]));
+
+        assert_eq!(
+            decode(&stream),
+            [
+                Err(FrameError::TooLong),
+                Ok((Request::Status as u8, vec![]))
+            ]
+        );
+
+        // The largest possible frame fits.
+        let payload = vec![0xff; MAX_PAYLOAD];
+        assert_eq!(
+            decode(&encoded(Response::Rstack as u8, &payload)),
+            [Ok((Response::Rstack as u8, payload))]
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut out = [0; MAX_FRAME + 1];
+
+        assert_eq!(
+            encode(1, &[0; MAX_PAYLOAD + 1], &mut out),
+            Err(FrameError::TooLong)
+        );
+
+        for payload in payloads() {
+            let len = encode(1, &payload, &mut out).unwrap();
+            assert!(len <= MAX_FRAME);
+
+            for short in 0..len {
+                assert_eq!(
+                    encode(1, &payload, &mut out[..short]),
+                    Err(FrameError::TooLong)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_u32(&[1, 2, 3, 4, 5]), Some((0x0403_0201, &[5][..])));
+        assert_eq!(split_u32(&[1, 2, 3, 4]), Some((0x0403_0201, &[][..])));
+        assert_eq!(split_u32(&[1, 2, 3]), None);
+    }
+}
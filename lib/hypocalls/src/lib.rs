@@ -8,6 +8,53 @@
 
 pub use lpc55_romapi::FlashStatus;
 
+use num_derive::FromPrimitive;
+
+/// Image slot from which we booted. This (and `BootReason`) must match the
+/// definitions in `stage0`.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum BootSlot {
+    A = 0,
+    B = 1,
+}
+
+/// Why the bootloader booted the slot that it did.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum BootReason {
+    /// No slot was requested; the default was booted.
+    Default = 0,
+    /// The requested slot was booted.
+    Requested = 1,
+    /// The preferred slot didn't hold a valid image, so the other was
+    /// booted.
+    Fallback = 2,
+}
+
+/// The header of an image: the start of its vector table, with image
+/// information in some of the reserved entries.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct ImageHeader {
+    pub sp: u32,
+    pub pc: u32,
+    pub vector_table: [u32; 6],
+    pub image_length: u32,
+    pub image_type: u32,
+    pub header_offset: u32,
+}
+
+#[cfg(not(feature = "standalone"))]
+fn flash_status(result: u32) -> FlashStatus {
+    use num_traits::cast::FromPrimitive;
+
+    match FlashStatus::from_u32(result) {
+        Some(a) => a,
+        None => FlashStatus::Unknown,
+    }
+}
+
 /// Write the buffer to the specified region number.
 ///
 /// Once we've established our regions this should be changed to an enum
@@ -15,8 +62,6 @@ pub use lpc55_romapi::FlashStatus;
 #[cfg(not(feature = "standalone"))]
 #[inline(never)]
 pub fn hypo_write_to_flash(region: u32, buf: &[u8]) -> FlashStatus {
-    use num_traits::cast::FromPrimitive;
-
     let result = unsafe {
         core::mem::transmute::<
             _,
@@ -28,16 +73,175 @@ pub fn hypo_write_to_flash(region: u32, buf: &[u8]) -> FlashStatus {
         )
     };
 
-    let result = match FlashStatus::from_u32(result) {
-        Some(a) => a,
-        None => FlashStatus::Unknown,
+    flash_status(result)
+}
+
+/// Erase the first `len` bytes of the specified region number, which must
+/// be a multiple of the flash page size (512 bytes).
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_erase_flash(region: u32, len: u32) -> FlashStatus {
+    let result = unsafe {
+        core::mem::transmute::<_, unsafe extern "C" fn(u32, u32) -> u32>(
+            __bootloader_fn_table.erase_flash,
+        )(region, len)
+    };
+
+    flash_status(result)
+}
+
+/// Read the header of the image that was booted.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_get_image_header() -> Result<ImageHeader, FlashStatus> {
+    let mut header = ImageHeader::default();
+
+    let result = unsafe {
+        core::mem::transmute::<_, unsafe extern "C" fn(*mut u32, u32) -> u32>(
+            __bootloader_fn_table.get_image_header,
+        )(
+            &mut header as *mut ImageHeader as *mut u32,
+            core::mem::size_of::<ImageHeader>() as u32,
+        )
+    };
+
+    match flash_status(result) {
+        FlashStatus::Success => Ok(header),
+        e => Err(e),
+    }
+}
+
+/// Read the version (that is, the build number) of the image that was
+/// booted.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_get_image_version() -> Result<u32, FlashStatus> {
+    let mut version = 0;
+
+    let result = unsafe {
+        core::mem::transmute::<_, unsafe extern "C" fn(*mut u32) -> u32>(
+            __bootloader_fn_table.get_image_version,
+        )(&mut version)
+    };
+
+    match flash_status(result) {
+        FlashStatus::Success => Ok(version),
+        e => Err(e),
+    }
+}
+
+/// Determine which image slot was booted, and why.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_get_boot_info() -> Result<(BootSlot, BootReason), FlashStatus> {
+    use num_traits::cast::FromPrimitive;
+
+    let mut slot = 0;
+    let mut reason = 0;
+
+    let result = unsafe {
+        let get_boot_info: unsafe extern "C" fn(*mut u32, *mut u32) -> u32 =
+            core::mem::transmute(__bootloader_fn_table.get_boot_info);
+
+        get_boot_info(&mut slot, &mut reason)
     };
 
-    return result;
+    match flash_status(result) {
+        FlashStatus::Success => {
+            match (BootSlot::from_u32(slot), BootReason::from_u32(reason)) {
+                (Some(slot), Some(reason)) => Ok((slot, reason)),
+                _ => Err(FlashStatus::Unknown),
+            }
+        }
+        e => Err(e),
+    }
+}
+
+#[cfg(not(feature = "standalone"))]
+fn read_pfr(hypocall: usize, offset: u32, buf: &mut [u32]) -> FlashStatus {
+    let len = core::mem::size_of_val(buf) as u32;
+
+    let result = unsafe {
+        let read: unsafe extern "C" fn(u32, *mut u32, u32) -> u32 =
+            core::mem::transmute(hypocall);
+
+        read(offset, buf.as_mut_ptr(), len)
+    };
+
+    flash_status(result)
+}
+
+/// Read from the CMPA (the customer manufacturing configuration page),
+/// starting at `offset` bytes in, enough to fill `buf`.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_read_cmpa(offset: u32, buf: &mut [u32]) -> FlashStatus {
+    read_pfr(__bootloader_fn_table.read_cmpa, offset, buf)
+}
+
+/// Read from the active CFPA (the customer field programmable page),
+/// starting at `offset` bytes in, enough to fill `buf`.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_read_cfpa(offset: u32, buf: &mut [u32]) -> FlashStatus {
+    read_pfr(__bootloader_fn_table.read_cfpa, offset, buf)
+}
+
+/// Ask that the slot other than the one that was booted be booted after the
+/// next reset. This applies to the next boot only, and fails with
+/// `InvalidArg` if the other slot didn't hold a valid image when we booted.
+/// The request is written to flash, so the calling task needs access to the
+/// flash controller.
+#[cfg(not(feature = "standalone"))]
+#[inline(never)]
+pub fn hypo_boot_other_slot_next() -> FlashStatus {
+    let result = unsafe {
+        core::mem::transmute::<_, unsafe extern "C" fn() -> u32>(
+            __bootloader_fn_table.boot_other_slot_next,
+        )()
+    };
+
+    flash_status(result)
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_write_to_flash(_region: u32, _buf: &[u8]) -> FlashStatus {
+    return FlashStatus::Success;
 }
 
 #[cfg(feature = "standalone")]
-pub fn hypo_write_to_flash(_addr: u32, _buf: &[u8], _size: u32) -> FlashStatus {
+pub fn hypo_erase_flash(_region: u32, _len: u32) -> FlashStatus {
     return FlashStatus::Success;
 }
+
+#[cfg(feature = "standalone")]
+pub fn hypo_get_image_header() -> Result<ImageHeader, FlashStatus> {
+    Ok(ImageHeader::default())
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_get_image_version() -> Result<u32, FlashStatus> {
+    Ok(0)
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_get_boot_info() -> Result<(BootSlot, BootReason), FlashStatus> {
+    Ok((BootSlot::A, BootReason::Default))
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_read_cmpa(_offset: u32, _buf: &mut [u32]) -> FlashStatus {
+    return FlashStatus::Success;
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_read_cfpa(_offset: u32, _buf: &mut [u32]) -> FlashStatus {
+    return FlashStatus::Success;
+}
+
+#[cfg(feature = "standalone")]
+pub fn hypo_boot_other_slot_next() -> FlashStatus {
+    return FlashStatus::Success;
+}
+
 include!(concat!(env!("OUT_DIR"), "/hypo.rs"));
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! I2C mux drivers
+//!
+//! This crate contains the register-level logic of each I2C mux that we
+//! support: how the mux is configured, how a segment is enabled, and (for
+//! those muxes that can) how pending interrupts are read.  Each driver
+//! implements [`Driver`], and makes its in-band management transfers
+//! through a [`Bus`] -- which is implemented by the I2C controller driver
+//! (for the STM32H7, `drv-stm32h7-i2c`), and by a mock mux in tests.  This
+//! crate knows nothing of the hardware (or of the mux's enable pin, which
+//! is the controller driver's concern), allowing the drivers to be tested
+//! on the host.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod ltc4306;
+pub mod max7358;
+pub mod onereg;
+pub mod pca9545;
+pub mod pca9548;
+pub mod pca9846;
+
+#[cfg(test)]
+mod mock;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum I2cKonamiCode {
+    Read,
+    Write,
+}
+
+///
+/// An error from a mux driver: either one from the bus, or one in which
+/// the mux itself is found wanting.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error<E> {
+    /// The transfer to the mux failed.
+    Bus(E),
+    /// The mux has no such segment.
+    SegmentNotFound,
+    /// The mux reports that the segment failed to connect.
+    SegmentDisconnected,
+    /// The mux reports that it is not connected.
+    MuxDisconnected,
+    /// The mux has no interrupt lines to report.
+    NoInterrupts,
+}
+
+///
+/// The in-band management transfers that a mux driver makes to its mux.
+///
+pub trait Bus {
+    type Error;
+
+    /// Writes `wbuf` to the device at `addr` and then, if `rbuf` isn't
+    /// empty, reads `rbuf` from it.
+    fn transfer(
+        &self,
+        addr: u8,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Sends the specified "Konami Code" (a sequence of zero-byte reads and
+    /// writes) to the device at `addr`.
+    fn konami(
+        &self,
+        addr: u8,
+        ops: &[I2cKonamiCode],
+    ) -> Result<(), Self::Error>;
+}
+
+///
+/// A trait to express an I2C mux driver.  Segments are numbered as they are
+/// in `drv_i2c_api::Segment`, from 1.
+///
+pub trait Driver {
+    /// Configure the mux at `addr`, once it has been enabled.  By default,
+    /// a mux needs no configuration.
+    fn configure<B: Bus>(
+        &self,
+        _bus: &B,
+        _addr: u8,
+    ) -> Result<(), Error<B::Error>> {
+        Ok(())
+    }
+
+    /// Enable the specified segment (and only it) on the mux at `addr`
+    fn enable_segment<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>>;
+
+    /// Returns the segments of the mux at `addr` that have their interrupt
+    /// line asserted, as a bitmask in which bit 0 is segment 1.  By
+    /// default, a mux has no interrupt lines to report.
+    fn interrupts<B: Bus>(
+        &self,
+        _bus: &B,
+        _addr: u8,
+    ) -> Result<u8, Error<B::Error>> {
+        Err(Error::NoInterrupts)
+    }
+}
@@ -6,9 +6,8 @@
 
 use crate::*;
 use bitfield::bitfield;
-use drv_i2c_api::{ResponseCode, Segment};
-use ringbuf::*;
-use userlib::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 
 pub struct Ltc4306;
 
@@ -37,6 +36,7 @@ bitfield! {
 }
 
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum Timeout {
     TimeoutDisabled = 0b00,
     Timeout30ms = 0b01,
@@ -81,108 +81,124 @@ bitfield! {
     bus4_active, _: 0;
 }
 
-ringbuf!(u8, 16, 0);
-
-fn read_reg_u8(
-    mux: &I2cMux,
-    controller: &I2cController,
+fn read_reg_u8<B: Bus>(
+    bus: &B,
+    addr: u8,
     reg: u8,
-    ctrl: &I2cControl,
-) -> Result<u8, ResponseCode> {
-    let mut rval = 0u8;
-    let wlen = 1;
-
-    match controller.write_read(
-        mux.address,
-        wlen,
-        |_| Some(reg),
-        ReadLength::Fixed(1),
-        |_, byte| {
-            rval = byte;
-            Some(())
-        },
-        ctrl,
-    ) {
-        Err(code) => Err(mux.error_code(code)),
-        _ => Ok(rval),
-    }
+) -> Result<u8, Error<B::Error>> {
+    let mut rval = [0u8];
+    bus.transfer(addr, &[reg], &mut rval).map_err(Error::Bus)?;
+    Ok(rval[0])
 }
 
-fn write_reg_u8(
-    mux: &I2cMux,
-    controller: &I2cController,
+fn write_reg_u8<B: Bus>(
+    bus: &B,
+    addr: u8,
     reg: u8,
     val: u8,
-    ctrl: &I2cControl,
-) -> Result<(), ResponseCode> {
-    match controller.write_read(
-        mux.address,
-        2,
-        |pos| Some(if pos == 0 { reg } else { val }),
-        ReadLength::Fixed(0),
-        |_, _| Some(()),
-        ctrl,
-    ) {
-        Err(code) => Err(mux.error_code(code)),
-        _ => Ok(()),
-    }
+) -> Result<(), Error<B::Error>> {
+    bus.transfer(addr, &[reg, val], &mut []).map_err(Error::Bus)
 }
 
-impl I2cMuxDriver for Ltc4306 {
-    fn configure(
-        &self,
-        mux: &I2cMux,
-        _controller: &I2cController,
-        gpio: &drv_stm32h7_gpio_api::Gpio,
-        _ctrl: &I2cControl,
-    ) -> Result<(), drv_i2c_api::ResponseCode> {
-        mux.configure(gpio)
-    }
-
-    fn enable_segment(
+impl Driver for Ltc4306 {
+    fn enable_segment<B: Bus>(
         &self,
-        mux: &I2cMux,
-        controller: &I2cController,
-        segment: Segment,
-        ctrl: &I2cControl,
-    ) -> Result<(), ResponseCode> {
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>> {
         let mut reg3 = Register3(0);
 
         match segment {
-            Segment::S1 => {
+            1 => {
                 reg3.set_bus1_connected(true);
             }
-            Segment::S2 => {
+            2 => {
                 reg3.set_bus2_connected(true);
             }
-            Segment::S3 => {
+            3 => {
                 reg3.set_bus3_connected(true);
             }
-            Segment::S4 => {
+            4 => {
                 reg3.set_bus4_connected(true);
             }
             _ => {
-                return Err(ResponseCode::SegmentNotFound);
+                return Err(Error::SegmentNotFound);
             }
         }
 
-        write_reg_u8(mux, controller, 3, reg3.0, ctrl)?;
-        let reg0 = Register0(read_reg_u8(mux, controller, 0, ctrl)?);
+        write_reg_u8(bus, addr, 3, reg3.0)?;
+        let reg0 = Register0(read_reg_u8(bus, addr, 0)?);
 
         if !reg0.not_failed() {
-            Err(ResponseCode::SegmentDisconnected)
+            Err(Error::SegmentDisconnected)
         } else if !reg0.connected() {
-            Err(ResponseCode::MuxDisconnected)
+            Err(Error::MuxDisconnected)
         } else {
             Ok(())
         }
     }
+}
 
-    fn reset(
-        &self,
-        mux: &I2cMux,
-        gpio: &drv_stm32h7_gpio_api::Gpio,
-    ) -> Result<(), drv_i2c_api::ResponseCode> {
-        mux.reset(gpio)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    /// Register 0 of a part that is connected, and whose connection
+    /// hasn't failed.
+    const CONNECTED: u8 = 0b1000_0100;
+
+    fn ltc4306() -> MockMux {
+        let mux = MockMux::new(Addressing::Pointer).readonly(0, 0xff);
+        mux.set_reg(0, CONNECTED);
+        mux
+    }
+
+    #[test]
+    fn segments() {
+        let mux = ltc4306();
+
+        for segment in 1..=4 {
+            Ltc4306.enable_segment(&mux, ADDR, segment).unwrap();
+            assert_eq!(mux.reg(3), 0b1000_0000 >> (segment - 1));
+        }
+
+        assert_eq!(
+            Ltc4306.enable_segment(&mux, ADDR, 5),
+            Err(Error::SegmentNotFound)
+        );
+    }
+
+    #[test]
+    fn status() {
+        let mux = ltc4306();
+
+        // The connection failed.
+        mux.set_reg(0, 0b1000_0000);
+        assert_eq!(
+            Ltc4306.enable_segment(&mux, ADDR, 1),
+            Err(Error::SegmentDisconnected)
+        );
+
+        // Not connected
+        mux.set_reg(0, 0b0000_0100);
+        assert_eq!(
+            Ltc4306.enable_segment(&mux, ADDR, 1),
+            Err(Error::MuxDisconnected)
+        );
+
+        mux.set_reg(0, CONNECTED);
+        assert_eq!(Ltc4306.enable_segment(&mux, ADDR, 1), Ok(()));
+    }
+
+    #[test]
+    fn no_device() {
+        let mux = ltc4306();
+
+        assert_eq!(
+            Ltc4306.enable_segment(&mux, ADDR + 1, 1),
+            Err(Error::Bus(BusError::NoDevice))
+        );
     }
 }
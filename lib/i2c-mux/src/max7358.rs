@@ -0,0 +1,259 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the MAX7358 I2C mux
+
+use crate::*;
+use bitfield::bitfield;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+pub struct Max7358;
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+enum Register {
+    SwitchControl = 0x00,
+    Configuration = 0x01,
+    FlushOutSequence = 0x02,
+    LockupIndication = 0x03,
+    TrafficPriorToLockupByte0 = 0x04,
+    TrafficPriorToLockupByte1 = 0x05,
+    StuckHighFault = 0x06,
+}
+
+impl From<u8> for Register {
+    fn from(value: u8) -> Self {
+        Register::from_u8(value).unwrap()
+    }
+}
+
+impl From<Register> for u8 {
+    fn from(value: Register) -> Self {
+        value as u8
+    }
+}
+
+bitfield! {
+    #[derive(Copy, Clone, PartialEq)]
+    pub struct SwitchControl(u8);
+    channel7_selected, set_channel7_selected: 7;
+    channel6_selected, set_channel6_selected: 6;
+    channel5_selected, set_channel5_selected: 5;
+    channel4_selected, set_channel4_selected: 4;
+    channel3_selected, set_channel3_selected: 3;
+    channel2_selected, set_channel2_selected: 2;
+    channel1_selected, set_channel1_selected: 1;
+    channel0_selected, set_channel0_selected: 0;
+}
+
+bitfield! {
+    #[derive(Copy, Clone, PartialEq)]
+    pub struct Configuration(u8);
+    preconnect_test_enabled, set_preconnect_test_enabled: 7;
+    basic_mode_enabled, set_basic_mode_enabled: 6;
+    bus_lockup_disabled, set_bus_lockup_disabled: 5;
+    disconnect_locked_only, set_disconnect_locked_only: 4;
+    lockup_cleared_on_read, set_lockup_cleared_on_read: 3;
+    rst_delay_released, set_rst_delay_released: 2;
+    flushout_enabled, set_flushout_enabled: 1;
+    interrupt_enabled, set_interrupt_enabled: 0;
+}
+
+fn read_regs<B: Bus>(
+    bus: &B,
+    addr: u8,
+    rbuf: &mut [u8],
+) -> Result<(), Error<B::Error>> {
+    bus.transfer(addr, &[], rbuf).map_err(Error::Bus)
+}
+
+fn write_reg<B: Bus>(
+    bus: &B,
+    addr: u8,
+    reg: Register,
+    val: u8,
+) -> Result<(), Error<B::Error>> {
+    let mut wbuf = [0u8; 3];
+
+    //
+    // When doing a write to this bonkers part, unless it's SwitchControl
+    // (which is in position 0), we must always write the other two --
+    // which necessitates us reading them first.  (Fortunately, we expect
+    // writes to SwitchControl to be by far the most frequent!)
+    //
+    let index = reg as usize;
+
+    if index > 0 {
+        read_regs(bus, addr, &mut wbuf[0..index])?;
+    }
+
+    wbuf[index] = val;
+
+    bus.transfer(addr, &wbuf[..index + 1], &mut [])
+        .map_err(Error::Bus)
+}
+
+impl Driver for Max7358 {
+    fn configure<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+    ) -> Result<(), Error<B::Error>> {
+        //
+        // The MAX7358 has a really, really regrettable idea:  it has a
+        // "special" (their words) sequence sent to expose enhanced
+        // functionality.  The sequence consists of I2C operations that one
+        // would never see from a functional initiator:  a zero-byte write
+        // followed by a zero-byte read, followed by a zero-byte write, followed
+        // by a zero-byte read.  (Because this evokes storied cheat sequences in
+        // video games, we choose to call this a "Konami Code.") This is bad
+        // enough, but it actually gets worse: this doesn't seem to always work
+        // correctly.  In particular, there seem to be modes in which the device
+        // confuses the zero-byte read that is the second operation for an
+        // *actual* read -- and tries to return the contents of register 0
+        // (which is its defined behavior on a read).  This is not (at all) what
+        // the initiator-side is expecting, and, because register 0 is the
+        // SwitchControl register which is itself zeroed on reset, this
+        // condition results in SDA appearing to be being held low -- and the
+        // controller (rightfully) indicates that arbitration is lost.  When
+        // this condition has been seen (namely, on hard power on), it is
+        // resolved as soon as the initiator emits enough SCL iterations (i.e.,
+        // controller restarts) for SDA to be let go: a subsequent issuing of
+        // the sequence is handled properly in the cases that we've seen.
+        // However, we have also found that issuing a (proper) read ahead of
+        // issuing the Konami Code appears to put the part in a better frame of
+        // mind -- so we choose to do this, with the hope that it will prevent
+        // the caller from needing to reset the controller entirely several
+        // times over.
+        //
+        let mut scratch = [0u8; 1];
+        read_regs(bus, addr, &mut scratch[0..1])?;
+
+        bus.konami(
+            addr,
+            &[
+                I2cKonamiCode::Write,
+                I2cKonamiCode::Read,
+                I2cKonamiCode::Write,
+                I2cKonamiCode::Read,
+            ],
+        )
+        .map_err(Error::Bus)?;
+
+        let reg = SwitchControl(0);
+        write_reg(bus, addr, Register::SwitchControl, reg.0)
+    }
+
+    fn enable_segment<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>> {
+        let mut reg = SwitchControl(0);
+
+        match segment {
+            1 => {
+                reg.set_channel0_selected(true);
+            }
+            2 => {
+                reg.set_channel1_selected(true);
+            }
+            3 => {
+                reg.set_channel2_selected(true);
+            }
+            4 => {
+                reg.set_channel3_selected(true);
+            }
+            5 => {
+                reg.set_channel4_selected(true);
+            }
+            6 => {
+                reg.set_channel5_selected(true);
+            }
+            7 => {
+                reg.set_channel6_selected(true);
+            }
+            8 => {
+                reg.set_channel7_selected(true);
+            }
+            _ => {
+                return Err(Error::SegmentNotFound);
+            }
+        }
+
+        write_reg(bus, addr, Register::SwitchControl, reg.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn configure_sequence() {
+        let mux = MockMux::new(Addressing::Sequential);
+        mux.set_reg(0, 0b0001_0000);
+
+        Max7358.configure(&mux, ADDR).unwrap();
+
+        assert_eq!(
+            *mux.konami.borrow(),
+            [[
+                I2cKonamiCode::Write,
+                I2cKonamiCode::Read,
+                I2cKonamiCode::Write,
+                I2cKonamiCode::Read,
+            ]]
+        );
+
+        // Configuring leaves every channel deselected.
+        assert_eq!(mux.reg(0), 0);
+    }
+
+    #[test]
+    fn segments() {
+        let mux = MockMux::new(Addressing::Sequential);
+
+        for segment in 1..=8 {
+            Max7358.enable_segment(&mux, ADDR, segment).unwrap();
+            assert_eq!(mux.reg(0), 1 << (segment - 1));
+        }
+
+        assert_eq!(
+            Max7358.enable_segment(&mux, ADDR, 9),
+            Err(Error::SegmentNotFound)
+        );
+
+        // Switch control is written alone.
+        assert!(mux.writes.borrow().iter().all(|w| w.len() == 1));
+    }
+
+    #[test]
+    fn write_preserves() {
+        let mux = MockMux::new(Addressing::Sequential);
+        mux.set_reg(0, 0b0000_0100);
+        mux.set_reg(1, 0b0100_0000);
+
+        write_reg(&mux, ADDR, Register::FlushOutSequence, 0x12).unwrap();
+
+        // The registers before it are rewritten as they were.
+        assert_eq!(mux.writes.borrow()[0], [0b0000_0100, 0b0100_0000, 0x12]);
+        assert_eq!(mux.reg(0), 0b0000_0100);
+        assert_eq!(mux.reg(1), 0b0100_0000);
+        assert_eq!(mux.reg(2), 0x12);
+    }
+
+    #[test]
+    fn no_device() {
+        let mux = MockMux::new(Addressing::Sequential);
+
+        assert_eq!(
+            Max7358.configure(&mux, ADDR + 1),
+            Err(Error::Bus(BusError::NoDevice))
+        );
+        assert!(mux.konami.borrow().is_empty());
+    }
+}
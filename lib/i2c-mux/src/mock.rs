@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A mock mux, for testing the mux drivers
+//!
+//! The mock has a bank of registers, and responds to transfers to its
+//! address by reading and writing them as the part that it stands in for
+//! would; it also keeps a log of what was written to it.
+
+use crate::*;
+use std::cell::{Cell, RefCell};
+use std::vec::Vec;
+
+/// The address of the mock mux.
+pub const ADDR: u8 = 0x70;
+
+/// The errors that the mock's bus can report.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BusError {
+    NoDevice,
+    NoRegister,
+}
+
+/// How a write selects the registers that it writes, and a read those that
+/// it reads.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Addressing {
+    /// There is but one register, which every byte is written to and read
+    /// from.
+    Single,
+    /// The first byte written is the number of a register; what follows is
+    /// written to it and the registers after it, and reads are from it and
+    /// the registers after it.
+    Pointer,
+    /// Writes are to register 0 and the registers after it, and reads are
+    /// from register 0 and the registers after it.
+    Sequential,
+}
+
+pub struct MockMux {
+    addressing: Addressing,
+    regs: RefCell<[u8; 8]>,
+    /// Bits of each register that writes don't change.
+    readonly: [u8; 8],
+    pointer: Cell<usize>,
+    /// Each write, in the order made.
+    pub writes: RefCell<Vec<Vec<u8>>>,
+    /// Each Konami Code sequence, in the order sent.
+    pub konami: RefCell<Vec<Vec<I2cKonamiCode>>>,
+}
+
+impl MockMux {
+    pub fn new(addressing: Addressing) -> Self {
+        Self {
+            addressing,
+            regs: RefCell::new([0; 8]),
+            readonly: [0; 8],
+            pointer: Cell::new(0),
+            writes: RefCell::new(Vec::new()),
+            konami: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Marks the bits in `mask` of register `reg` as read-only.
+    pub fn readonly(mut self, reg: usize, mask: u8) -> Self {
+        self.readonly[reg] = mask;
+        self
+    }
+
+    pub fn reg(&self, reg: usize) -> u8 {
+        self.regs.borrow()[reg]
+    }
+
+    /// Sets a register as the part would, read-only bits and all.
+    pub fn set_reg(&self, reg: usize, val: u8) {
+        self.regs.borrow_mut()[reg] = val;
+    }
+
+    fn write(&self, reg: usize, val: u8) {
+        let mut regs = self.regs.borrow_mut();
+        let readonly = self.readonly[reg];
+        regs[reg] = (regs[reg] & readonly) | (val & !readonly);
+    }
+}
+
+impl Bus for MockMux {
+    type Error = BusError;
+
+    fn transfer(
+        &self,
+        addr: u8,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<(), BusError> {
+        if addr != ADDR {
+            return Err(BusError::NoDevice);
+        }
+
+        if !wbuf.is_empty() {
+            self.writes.borrow_mut().push(wbuf.to_vec());
+        }
+
+        let data = match (self.addressing, wbuf.split_first()) {
+            (Addressing::Pointer, Some((&reg, data))) => {
+                if reg as usize >= self.readonly.len() {
+                    return Err(BusError::NoRegister);
+                }
+
+                self.pointer.set(reg as usize);
+                data
+            }
+            (Addressing::Sequential, _) => {
+                self.pointer.set(0);
+                wbuf
+            }
+            _ => wbuf,
+        };
+
+        for &byte in data {
+            match self.addressing {
+                Addressing::Single => self.write(0, byte),
+                _ => {
+                    self.write(self.pointer.get(), byte);
+                    self.pointer.set(self.pointer.get() + 1);
+                }
+            }
+        }
+
+        if self.addressing == Addressing::Sequential {
+            self.pointer.set(0);
+        }
+
+        for byte in rbuf.iter_mut() {
+            match self.addressing {
+                Addressing::Single => *byte = self.reg(0),
+                _ => {
+                    *byte = self.reg(self.pointer.get());
+                    self.pointer.set(self.pointer.get() + 1);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn konami(&self, addr: u8, ops: &[I2cKonamiCode]) -> Result<(), BusError> {
+        if addr != ADDR {
+            return Err(BusError::NoDevice);
+        }
+
+        self.konami.borrow_mut().push(ops.to_vec());
+        Ok(())
+    }
+}
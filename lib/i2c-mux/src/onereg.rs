@@ -0,0 +1,40 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Logic shared by muxes with but one register
+//!
+//! The PCA9548, PCA9545 and PCA9846 each have a single control register,
+//! in which bit `n` enables channel `n` (that is, segment `n + 1`).  Any
+//! write to the part is a write of this register, and any read of the part
+//! is a read of it.  Beyond the number of channels, the parts differ only
+//! in what else they do with the bits above their channels.
+
+use crate::*;
+
+/// Returns the value of the control register of a mux with `channels`
+/// channels that enables only `segment`.
+pub fn control<E>(segment: u8, channels: u8) -> Result<u8, Error<E>> {
+    match segment.checked_sub(1) {
+        Some(channel) if channel < channels => Ok(1 << channel),
+        _ => Err(Error::SegmentNotFound),
+    }
+}
+
+/// Enables only `segment` of the mux with `channels` channels at `addr`.
+pub fn enable_segment<B: Bus>(
+    bus: &B,
+    addr: u8,
+    segment: u8,
+    channels: u8,
+) -> Result<(), Error<B::Error>> {
+    bus.transfer(addr, &[control(segment, channels)?], &mut [])
+        .map_err(Error::Bus)
+}
+
+/// Reads the control register of the mux at `addr`.
+pub fn read_control<B: Bus>(bus: &B, addr: u8) -> Result<u8, Error<B::Error>> {
+    let mut reg = [0u8];
+    bus.transfer(addr, &[], &mut reg).map_err(Error::Bus)?;
+    Ok(reg[0])
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the PCA9545 I2C mux
+//!
+//! The PCA9545 is a four channel mux that also passes through an interrupt
+//! line from each of its segments, the state of which can be read from the
+//! upper four bits of its control register.  Its (active low) reset line,
+//! if connected, is the mux's `enable` pin.
+
+use crate::*;
+
+pub struct Pca9545;
+
+/// The PCA9545 has four channels; see [`onereg`].
+const CHANNELS: u8 = 4;
+
+impl Driver for Pca9545 {
+    fn enable_segment<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>> {
+        //
+        // The interrupt bits are read-only, so we can write the whole
+        // register without regard to them.
+        //
+        onereg::enable_segment(bus, addr, segment, CHANNELS)
+    }
+
+    ///
+    /// Reads the control register to determine which segments have their
+    /// interrupt line asserted.  Note that the interrupt lines are reported
+    /// whether or not the segment is enabled.
+    ///
+    fn interrupts<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+    ) -> Result<u8, Error<B::Error>> {
+        Ok(onereg::read_control(bus, addr)? >> CHANNELS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    fn pca9545() -> MockMux {
+        MockMux::new(Addressing::Single).readonly(0, 0xf0)
+    }
+
+    #[test]
+    fn segments() {
+        let mux = pca9545();
+
+        for segment in 1..=4 {
+            Pca9545.enable_segment(&mux, ADDR, segment).unwrap();
+            assert_eq!(mux.reg(0), 1 << (segment - 1));
+        }
+
+        for &segment in &[0, 5, 8] {
+            assert_eq!(
+                Pca9545.enable_segment(&mux, ADDR, segment),
+                Err(Error::SegmentNotFound)
+            );
+        }
+
+        // Only the four good segments were written.
+        assert_eq!(mux.writes.borrow().len(), 4);
+    }
+
+    #[test]
+    fn interrupts_pending() {
+        let mux = pca9545();
+
+        // INT0 and INT2 asserted, with segment 2 enabled
+        mux.set_reg(0, 0b0101_0010);
+        assert_eq!(Pca9545.interrupts(&mux, ADDR), Ok(0b0101));
+
+        // Enabling a segment leaves the interrupts as they are.
+        Pca9545.enable_segment(&mux, ADDR, 4).unwrap();
+        assert_eq!(mux.reg(0), 0b0101_1000);
+        assert_eq!(Pca9545.interrupts(&mux, ADDR), Ok(0b0101));
+
+        mux.set_reg(0, 0b1000_0000);
+        assert_eq!(Pca9545.interrupts(&mux, ADDR), Ok(0b1000));
+
+        mux.set_reg(0, 0b0000_1111);
+        assert_eq!(Pca9545.interrupts(&mux, ADDR), Ok(0));
+    }
+
+    #[test]
+    fn no_device() {
+        let mux = pca9545();
+
+        assert_eq!(
+            Pca9545.interrupts(&mux, ADDR + 1),
+            Err(Error::Bus(BusError::NoDevice))
+        );
+        assert_eq!(
+            Pca9545.enable_segment(&mux, ADDR + 1, 1),
+            Err(Error::Bus(BusError::NoDevice))
+        );
+        assert_eq!(mux.reg(0), 0);
+    }
+}
@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the PCA9548 I2C mux
+
+use crate::*;
+
+pub struct Pca9548;
+
+/// The PCA9548 has eight channels; see [`onereg`].
+const CHANNELS: u8 = 8;
+
+impl Driver for Pca9548 {
+    fn enable_segment<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>> {
+        onereg::enable_segment(bus, addr, segment, CHANNELS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn segments() {
+        let mux = MockMux::new(Addressing::Single);
+
+        for segment in 1..=8 {
+            Pca9548.enable_segment(&mux, ADDR, segment).unwrap();
+            assert_eq!(mux.reg(0), 1 << (segment - 1));
+        }
+
+        assert_eq!(
+            Pca9548.enable_segment(&mux, ADDR, 9),
+            Err(Error::SegmentNotFound)
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the PCA9846 I2C mux
+//!
+//! The PCA9846 is a four channel mux with an (active low) reset line, which,
+//! if connected, is the mux's `enable` pin.  Resetting the mux disconnects
+//! all of its segments.
+
+use crate::*;
+
+pub struct Pca9846;
+
+/// The PCA9846 has four channels; see [`onereg`].
+const CHANNELS: u8 = 4;
+
+impl Driver for Pca9846 {
+    fn enable_segment<B: Bus>(
+        &self,
+        bus: &B,
+        addr: u8,
+        segment: u8,
+    ) -> Result<(), Error<B::Error>> {
+        onereg::enable_segment(bus, addr, segment, CHANNELS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn segments() {
+        let mux = MockMux::new(Addressing::Single);
+
+        for segment in 1..=4 {
+            Pca9846.enable_segment(&mux, ADDR, segment).unwrap();
+            assert_eq!(mux.reg(0), 1 << (segment - 1));
+        }
+    }
+
+    #[test]
+    fn no_segment() {
+        let mux = MockMux::new(Addressing::Single);
+        mux.set_reg(0, 0b0000_0010);
+
+        assert_eq!(
+            Pca9846.enable_segment(&mux, ADDR, 5),
+            Err(Error::SegmentNotFound)
+        );
+
+        // Nothing should have been written.
+        assert!(mux.writes.borrow().is_empty());
+        assert_eq!(mux.reg(0), 0b0000_0010);
+    }
+
+    #[test]
+    fn no_interrupts() {
+        let mux = MockMux::new(Addressing::Single);
+
+        assert_eq!(Pca9846.interrupts(&mux, ADDR), Err(Error::NoInterrupts));
+    }
+}
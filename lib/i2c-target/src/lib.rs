@@ -0,0 +1,326 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! I2C target framework
+//!
+//! Many I2C targets present their contents as a map of byte-wide registers
+//! behind a register pointer: the first byte of a write sets the pointer,
+//! any further bytes written are stored starting at the pointer, and a read
+//! returns bytes starting at the pointer, with the pointer incrementing
+//! (and wrapping from 0xff to 0x00) after every byte read or written.  This
+//! is the convention of SMBus devices, and of small EEPROMs like the 24C02.
+//!
+//! A task that wants to act as one or more such targets implements
+//! [`RegisterMap`] for each, and hands them (as [`Target`]s, each with its
+//! address) to [`Targets`], which routes the traffic on the bus to them and
+//! keeps their pointers.  This crate knows nothing of the hardware: the
+//! driver calls into [`Targets`] as it is addressed, as it receives a byte,
+//! and as it needs a byte to transmit (for the STM32H7, see
+//! `I2cController::operate_as_register_target`).
+
+#![cfg_attr(not(test), no_std)]
+
+///
+/// A map of byte-wide registers, addressed by a register pointer.
+///
+pub trait RegisterMap {
+    ///
+    /// Returns the value of register `reg`, or `None` if it can't be read.
+    /// (As a target can't NACK a read once it has ACK'd its address, the
+    /// initiator will see filler in place of an unreadable register.)
+    ///
+    fn read(&mut self, reg: u8) -> Option<u8>;
+
+    /// Writes `val` to register `reg`.
+    fn write(&mut self, reg: u8, val: u8);
+
+    ///
+    /// Called at the start of every transaction addressed to the target;
+    /// returning false NACKs the transaction.
+    ///
+    fn initiate(&mut self) -> bool {
+        true
+    }
+}
+
+///
+/// A register map backed by read-only memory, e.g. the contents of an
+/// emulated FRU EEPROM.  Writes are ignored, as are reads beyond its end.
+///
+pub struct Rom<'a>(pub &'a [u8]);
+
+impl RegisterMap for Rom<'_> {
+    fn read(&mut self, reg: u8) -> Option<u8> {
+        self.0.get(reg as usize).copied()
+    }
+
+    fn write(&mut self, _reg: u8, _val: u8) {}
+}
+
+///
+/// A target at a single address, with its register map and pointer.
+///
+pub struct Target<'a> {
+    address: u8,
+    map: &'a mut dyn RegisterMap,
+    pointer: u8,
+    /// True if the next byte written is to set the pointer
+    expecting_pointer: bool,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(address: u8, map: &'a mut dyn RegisterMap) -> Self {
+        Self {
+            address,
+            map,
+            pointer: 0,
+            expecting_pointer: true,
+        }
+    }
+
+    /// Returns the target's register pointer.
+    pub fn pointer(&self) -> u8 {
+        self.pointer
+    }
+
+    fn initiate(&mut self) -> bool {
+        //
+        // A write always starts with the pointer; a read (including one
+        // after a repeated start) continues from wherever the pointer is.
+        //
+        self.expecting_pointer = true;
+        self.map.initiate()
+    }
+
+    fn rx(&mut self, byte: u8) {
+        if self.expecting_pointer {
+            self.pointer = byte;
+            self.expecting_pointer = false;
+        } else {
+            self.map.write(self.pointer, byte);
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+
+    fn tx(&mut self) -> Option<u8> {
+        let rval = self.map.read(self.pointer);
+        self.pointer = self.pointer.wrapping_add(1);
+        rval
+    }
+}
+
+///
+/// The targets that a task presents on a bus, with an interface matching
+/// that of the driver: [`Targets::initiate`] when addressed, [`Targets::rx`]
+/// for each byte received, and [`Targets::tx`] for each byte to transmit.
+/// An address that isn't that of one of the targets is NACK'd.
+///
+pub struct Targets<'a, 'b> {
+    targets: &'b mut [Target<'a>],
+}
+
+impl<'a, 'b> Targets<'a, 'b> {
+    ///
+    /// Returns a new [`Targets`].  Each target must have a distinct address;
+    /// if two share one, the second will never be addressed.
+    ///
+    pub fn new(targets: &'b mut [Target<'a>]) -> Self {
+        Self { targets }
+    }
+
+    fn lookup(&mut self, addr: u8) -> Option<&mut Target<'a>> {
+        self.targets.iter_mut().find(|t| t.address == addr)
+    }
+
+    /// Called when `addr` is addressed; returns false to NACK.
+    pub fn initiate(&mut self, addr: u8) -> bool {
+        match self.lookup(addr) {
+            Some(target) => target.initiate(),
+            None => false,
+        }
+    }
+
+    /// Called with each byte written to `addr`.
+    pub fn rx(&mut self, addr: u8, byte: u8) {
+        if let Some(target) = self.lookup(addr) {
+            target.rx(byte);
+        }
+    }
+
+    /// Called for each byte read from `addr`; `None` results in filler.
+    pub fn tx(&mut self, addr: u8) -> Option<u8> {
+        self.lookup(addr)?.tx()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A register map of 256 registers, all of which can be read and
+    /// written.
+    struct Ram {
+        regs: [u8; 256],
+        ready: bool,
+    }
+
+    impl Ram {
+        fn new() -> Self {
+            let mut regs = [0; 256];
+
+            for (i, reg) in regs.iter_mut().enumerate() {
+                *reg = i as u8 ^ 0xa5;
+            }
+
+            Self { regs, ready: true }
+        }
+    }
+
+    impl RegisterMap for Ram {
+        fn read(&mut self, reg: u8) -> Option<u8> {
+            Some(self.regs[reg as usize])
+        }
+
+        fn write(&mut self, reg: u8, val: u8) {
+            self.regs[reg as usize] = val;
+        }
+
+        fn initiate(&mut self) -> bool {
+            self.ready
+        }
+    }
+
+    const ADDR: u8 = 0x50;
+
+    /// Writes `bytes` to `addr` as a transaction of its own.
+    fn write(targets: &mut Targets, addr: u8, bytes: &[u8]) {
+        assert!(targets.initiate(addr));
+
+        for &byte in bytes {
+            targets.rx(addr, byte);
+        }
+    }
+
+    /// Reads `n` bytes from `addr` as a transaction of its own (or after a
+    /// repeated start).
+    fn read(targets: &mut Targets, addr: u8, n: usize) -> Vec<Option<u8>> {
+        assert!(targets.initiate(addr));
+        (0..n).map(|_| targets.tx(addr)).collect()
+    }
+
+    #[test]
+    fn pointer() {
+        let mut ram = Ram::new();
+        let mut target = [Target::new(ADDR, &mut ram)];
+        let mut targets = Targets::new(&mut target);
+
+        write(&mut targets, ADDR, &[0x10]);
+        assert_eq!(target[0].pointer(), 0x10);
+
+        // Setting the pointer writes nothing.
+        assert_eq!(ram.regs[0x10], 0x10 ^ 0xa5);
+    }
+
+    #[test]
+    fn auto_increment() {
+        let mut ram = Ram::new();
+        let mut target = [Target::new(ADDR, &mut ram)];
+        let mut targets = Targets::new(&mut target);
+
+        // Writes wrap from 0xff to 0x00...
+        write(&mut targets, ADDR, &[0xfe, 1, 2, 3]);
+
+        // ...as do reads.
+        write(&mut targets, ADDR, &[0xfe]);
+        assert_eq!(
+            read(&mut targets, ADDR, 4),
+            [Some(1), Some(2), Some(3), Some(0x01 ^ 0xa5)]
+        );
+
+        assert_eq!(target[0].pointer(), 0x02);
+
+        assert_eq!(ram.regs[0xfe], 1);
+        assert_eq!(ram.regs[0xff], 2);
+        assert_eq!(ram.regs[0x00], 3);
+    }
+
+    #[test]
+    fn repeated_start() {
+        let mut ram = Ram::new();
+        let mut target = [Target::new(ADDR, &mut ram)];
+        let mut targets = Targets::new(&mut target);
+
+        //
+        // A random read: the pointer is written, and then (after a
+        // repeated start) read from.
+        //
+        write(&mut targets, ADDR, &[0x40]);
+        assert_eq!(
+            read(&mut targets, ADDR, 2),
+            [Some(0x40 ^ 0xa5), Some(0x41 ^ 0xa5)]
+        );
+
+        //
+        // A current address read continues from where the last transaction
+        // left the pointer.
+        //
+        assert_eq!(read(&mut targets, ADDR, 1), [Some(0x42 ^ 0xa5)]);
+
+        //
+        // And a write that follows starts with the pointer, rather than
+        // writing at it.
+        //
+        write(&mut targets, ADDR, &[0x80, 0xee]);
+        assert_eq!(target[0].pointer(), 0x81);
+
+        assert_eq!(ram.regs[0x43], 0x43 ^ 0xa5);
+        assert_eq!(ram.regs[0x80], 0xee);
+    }
+
+    #[test]
+    fn nack() {
+        let mut ram = Ram::new();
+        let mut rom = Rom(&[1, 2, 3]);
+        let mut target =
+            [Target::new(ADDR, &mut ram), Target::new(0x51, &mut rom)];
+        let mut targets = Targets::new(&mut target);
+
+        // An address that isn't one of ours is NACK'd, and otherwise ignored.
+        assert!(!targets.initiate(0x52));
+        targets.rx(0x52, 0x10);
+        assert_eq!(targets.tx(0x52), None);
+
+        assert_eq!(target[0].pointer(), 0);
+        assert_eq!(target[1].pointer(), 0);
+    }
+
+    #[test]
+    fn not_ready() {
+        let mut ram = Ram::new();
+        ram.ready = false;
+
+        let mut target = [Target::new(ADDR, &mut ram)];
+        let mut targets = Targets::new(&mut target);
+
+        // A map that isn't ready NACKs its own address.
+        assert!(!targets.initiate(ADDR));
+    }
+
+    #[test]
+    fn rom() {
+        let contents = [0x11, 0x22, 0x33];
+        let mut rom = Rom(&contents);
+        let mut target = [Target::new(ADDR, &mut rom)];
+        let mut targets = Targets::new(&mut target);
+
+        write(&mut targets, ADDR, &[1]);
+        assert_eq!(read(&mut targets, ADDR, 3), [Some(0x22), Some(0x33), None]);
+
+        // Writes are ignored, but still move the pointer.
+        write(&mut targets, ADDR, &[0, 0xff, 0xff]);
+        write(&mut targets, ADDR, &[0]);
+        assert_eq!(read(&mut targets, ADDR, 1), [Some(0x11)]);
+        assert_eq!(read(&mut targets, ADDR, 1), [Some(0x22)]);
+    }
+}
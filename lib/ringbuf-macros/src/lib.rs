@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Derive macro for the `ringbuf::Count` trait
+//!
+//! For an enum `E`, `#[derive(Count)]` generates a struct `ECounters` with a
+//! `u32` counter for each variant of `E` -- named for the variant, so that the
+//! counters are self-describing in the debug info -- and an implementation of
+//! `ringbuf::Count` that bumps the counter corresponding to a value's variant
+//! (ignoring any data that the variant carries).  See the `ringbuf` crate for
+//! how this is used.
+//!
+//! The generated code refers to `ringbuf` only via its hidden `__private`
+//! module.  If the crate is known by another name (say, because it was renamed
+//! in `Cargo.toml`, or is used via a re-export), give its path with
+//! `#[count(crate = "path::to::ringbuf")]`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Path,
+};
+
+#[proc_macro_derive(Count, attributes(count))]
+pub fn derive_count(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match generate(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Determines the path of the `ringbuf` crate from any `#[count(crate =
+/// "...")]` attribute, defaulting to `::ringbuf`.
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+    let mut path: Path = syn::parse_quote!(::ringbuf);
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("count")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected #[count(crate = \"...\")]",
+                ));
+            }
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv))
+                    if nv.path.is_ident("crate") =>
+                {
+                    match &nv.lit {
+                        Lit::Str(s) => path = s.parse()?,
+                        lit => {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                "expected a string",
+                            ));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "unknown count attribute",
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+fn generate(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Count can only be derived for enums",
+            ));
+        }
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Count cannot be derived for generic enums",
+        ));
+    }
+
+    let krate = crate_path(input)?;
+    let private = quote! { #krate::__private };
+    let name = &input.ident;
+    let vis = &input.vis;
+    let counters = format_ident!("{}Counters", name);
+    let variants = data.variants.iter().map(|v| &v.ident).collect::<Vec<_>>();
+
+    let arms = data.variants.iter().map(|v| {
+        let variant = &v.ident;
+
+        let pattern = match v.fields {
+            Fields::Unit => quote! { #name::#variant },
+            Fields::Unnamed(_) => quote! { #name::#variant(..) },
+            Fields::Named(_) => quote! { #name::#variant { .. } },
+        };
+
+        quote! { #pattern => &counters.#variant }
+    });
+
+    Ok(quote! {
+        /// Per-variant event counters, as generated by `#[derive(Count)]`.
+        #[allow(non_snake_case)]
+        #[derive(Debug)]
+        #vis struct #counters {
+            #( pub #variants: #private::AtomicU32, )*
+        }
+
+        impl #private::Count for #name {
+            type Counters = #counters;
+
+            #[allow(clippy::declare_interior_mutable_const)]
+            const NEW_COUNTERS: #counters = #counters {
+                #( #variants: #private::AtomicU32::new(0), )*
+            };
+
+            fn count(&self, counters: &#counters) {
+                let counter = match self {
+                    #( #arms, )*
+                };
+
+                #private::increment(counter);
+            }
+        }
+    })
+}
@@ -62,6 +62,86 @@
 //! ringbuf_entry!((temp, Some(Register::TempMSB)));
 //! ```
 //!
+//! ## Timestamped ring buffers
+//!
+//! By default, a ring buffer entry records where it was generated, but not
+//! when.  To be able to correlate ring buffers across tasks (or to measure the
+//! time between events), a ring buffer can be declared `timestamped`:
+//!
+//! ```
+//! ringbuf!(timestamped Trace, 16, Trace::None);
+//! ringbuf!(timestamped MY_RINGBUF, u32, 16, 0);
+//! ```
+//!
+//! Entries are added to such a ring buffer with [`ringbuf_entry!`] as usual,
+//! but each entry additionally records a [`Timestamp`] for both the first and
+//! the most recent occurrence of its payload (entries with identical payloads
+//! being coalesced as ever).  A timestamp is the kernel's timer
+//! (`sys_get_timer().now`).  (The Cortex-M cycle counter would be finer, but
+//! the DWT is accessible only to privileged code.)
+//!
+//! Getting the kernel's timer costs a system call, which is why timestamps
+//! are opt-in.  A timestamped ring buffer has the same structure (and field
+//! names) as any other, with the addition of the `first_time` and
+//! `last_time` fields in each entry, so it can be processed by Humility or
+//! GDB in the same way.
+//!
+//! ## Counters
+//!
+//! For rare-but-important events, a ring buffer may lose history too quickly
+//! to be useful.  For these, an enum can instead be given a table of event
+//! counters -- one saturating `u32` per variant -- by deriving [`Count`] and
+//! declaring the table with [`counters!`]:
+//!
+//! ```
+//! #[derive(Copy, Clone, Count)]
+//! enum Event {
+//!     BusReset,
+//!     Nack(u8),
+//! }
+//!
+//! counters!(Event);
+//! ```
+//!
+//! An event is then counted with [`count!`], which costs a single increment:
+//!
+//! ```
+//! count!(Event::Nack(addr));
+//! ```
+//!
+//! As with ring buffers, the table can be named (`counters!(MY_COUNTERS,
+//! Event)` and `count!(MY_COUNTERS, Event::BusReset)`); if it isn't, it
+//! defaults to `__COUNTERS`.  By convention, the name of a table of counters
+//! ends in `COUNTERS`, just as that of a ring buffer ends in `RINGBUF`, so
+//! that tools can find them.  As the table is a structure with a field for
+//! each variant, named for the variant, it is self-describing:
+//!
+//! ```console
+//! (gdb) print drv_stm32h7_i2c_server::__COUNTERS
+//! $1 = drv_stm32h7_i2c_server::EventCounters {
+//!   BusReset: core::sync::atomic::AtomicU32 {v: core::cell::UnsafeCell<u32> {value: 2}},
+//!   Nack: core::sync::atomic::AtomicU32 {v: core::cell::UnsafeCell<u32> {value: 17}}
+//! }
+//! ```
+//!
+//! Like ring buffers, counters can be compiled out with the "disabled"
+//! feature.
+//!
+//! ## Extracting ring buffers without a debugger
+//!
+//! With the "registry" feature, every ring buffer declared with [`ringbuf!`]
+//! is registered in a table collected by the linker (see
+//! `userlib::ringbuf_registry`), allowing a task to serialize all of its ring
+//! buffers into a stable binary format.  A server that enables the feature
+//! can then do so in response to the `userlib::ringbuf_registry::DUMP_OP`
+//! operation -- allowing (say) a management task to collect ring buffers from
+//! a system in the field, and ship them off over whatever transport it has.
+//! As the registry costs flash, tasks that don't serve dumps should leave the
+//! feature off.  On the host, `cargo xtask
+//! ringbuf` checks a dump against the task's ELF and prints its entries, with
+//! their payloads as raw bytes; interpreting a payload requires the type of
+//! the ring buffer's entries, from the task's debug info.
+//!
 //! ## Inspecting a ring buffer via Humility
 //!
 //! Humility has built-in support for dumping a ring buffer, and will (by
@@ -142,6 +222,26 @@
 /// macros is guaranteed to be able to find them.
 pub use userlib::util::StaticCell;
 
+/// Re-export the ring buffer registry, which is used by code generated by the
+/// macros to register each ring buffer.
+pub use userlib::ringbuf_registry::{
+    DumpError, DumpWriter, RingbufRegistration,
+};
+
+/// Derives [`Count`] for an enum, generating a table of counters named for
+/// its variants.
+pub use ringbuf_macros::Count;
+
+/// Items used by code generated by `#[derive(Count)]`, which refers to them
+/// via this module rather than naming them directly.  Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{increment, Count};
+    pub use core::sync::atomic::AtomicU32;
+}
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
 /// Declares a ringbuffer in the current module or context.
 ///
 /// `ringbuf!(NAME, Type, N, expr)` makes a ringbuffer named `NAME`,
@@ -156,9 +256,34 @@ pub use userlib::util::StaticCell;
 ///
 /// To support the common case of having one quickly-installed ringbuffer per
 /// module, if you omit the name, it will default to `__RINGBUF`.
+///
+/// Prefixing the arguments with `timestamped` (e.g., `ringbuf!(timestamped
+/// NAME, Type, N, expr)`) makes a ringbuffer whose entries are timestamped, of
+/// type `StaticCell<TimestampedRingbuf<T, N>>`.
 #[cfg(not(feature = "disabled"))]
 #[macro_export]
 macro_rules! ringbuf {
+    (timestamped $name:ident, $t:ty, $n:expr, $init:expr) => {
+        #[used]
+        static $name: $crate::StaticCell<
+            $crate::TimestampedRingbuf<$t, $n>,
+        > = $crate::StaticCell::new($crate::TimestampedRingbuf {
+            last: None,
+            buffer: [$crate::TimestampedRingbufEntry {
+                line: 0,
+                generation: 0,
+                count: 0,
+                payload: $init,
+                first_time: $crate::Timestamp::ZERO,
+                last_time: $crate::Timestamp::ZERO,
+            }; $n],
+        });
+
+        $crate::__register_ringbuf!($name);
+    };
+    (timestamped $t:ty, $n:expr, $init:expr) => {
+        $crate::ringbuf!(timestamped __RINGBUF, $t, $n, $init);
+    };
     ($name:ident, $t:ty, $n:expr, $init:expr) => {
         #[used]
         static $name: $crate::StaticCell<$crate::Ringbuf<$t, $n>> =
@@ -171,15 +296,56 @@ macro_rules! ringbuf {
                     payload: $init,
                 }; $n],
             });
+
+        $crate::__register_ringbuf!($name);
     };
     ($t:ty, $n:expr, $init:expr) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init);
     };
 }
 
+/// Registers a ringbuffer declared by `ringbuf!` in the task's ringbuffer
+/// registry, under its full path.
+#[cfg(feature = "registry")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_ringbuf {
+    ($name:ident) => {
+        const _: () = {
+            fn dump(
+                writer: &mut $crate::DumpWriter<'_>,
+            ) -> Result<(), $crate::DumpError> {
+                $crate::Dump::dump(
+                    &*$crate::StaticCell::borrow_mut(&$name),
+                    &$name as *const _ as usize,
+                    writer,
+                )
+            }
+
+            #[used]
+            #[link_section = ".ringbuf_registry"]
+            static REGISTRATION: $crate::RingbufRegistration =
+                $crate::RingbufRegistration {
+                    name: concat!(module_path!(), "::", stringify!($name)),
+                    dump,
+                };
+        };
+    };
+}
+
+/// Without the "registry" feature, ringbuffers aren't registered.
+#[cfg(not(feature = "registry"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_ringbuf {
+    ($name:ident) => {};
+}
+
 #[cfg(feature = "disabled")]
 #[macro_export]
 macro_rules! ringbuf {
+    (timestamped $name:ident, $t:ty, $n:expr, $init:expr) => {};
+    (timestamped $t:ty, $n:expr, $init:expr) => {};
     ($name:ident, $t:ty, $n:expr, $init:expr) => {};
     ($t:ty, $n:expr, $init:expr) => {};
 }
@@ -202,8 +368,8 @@ macro_rules! ringbuf_entry {
         let (p, buf) = ($payload, &$buf);
         // Invoke these functions using slightly weird syntax to avoid
         // accidentally calling a _different_ routine called borrow_mut or
-        // entry.
-        $crate::Ringbuf::entry(
+        // record_entry.
+        $crate::RecordEntry::record_entry(
             &mut *$crate::StaticCell::borrow_mut(buf),
             line!() as u16,
             p,
@@ -221,6 +387,53 @@ macro_rules! ringbuf_entry {
     ($payload:expr) => {};
 }
 
+///
+/// Implemented by ring buffers that can have entries added to them by
+/// [`ringbuf_entry!`].
+///
+pub trait RecordEntry<T: Copy + PartialEq> {
+    fn record_entry(&mut self, line: u16, payload: T);
+}
+
+///
+/// Determines the index at which to write the entry following the one at
+/// `last` in a ring buffer of `len` entries.
+///
+fn next_index(last: Option<usize>, len: usize) -> usize {
+    match last {
+        None => 0,
+        Some(last) if last + 1 >= len => 0,
+        Some(last) => last + 1,
+    }
+}
+
+///
+/// Implemented by ring buffers that can be serialized into a dump (see
+/// `userlib::ringbuf_registry` for the format).
+///
+pub trait Dump {
+    /// Serializes the ring buffer, which lives at `address`.
+    fn dump(
+        &self,
+        address: usize,
+        writer: &mut DumpWriter<'_>,
+    ) -> Result<(), DumpError>;
+}
+
+///
+/// Returns the in-memory representation of a payload.  (Any padding within
+/// the payload is of indeterminate value; a host decoding a dump will ignore
+/// it, as the payload type's debug info tells it where the fields are.)
+///
+fn payload_bytes<T>(payload: &T) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            payload as *const T as *const u8,
+            core::mem::size_of::<T>(),
+        )
+    }
+}
+
 ///
 /// The structure of a single [`Ringbuf`] entry, carrying a payload of arbitrary
 /// type.  When a ring buffer entry is generated with an identical payload to
@@ -261,11 +474,7 @@ impl<T: Copy + PartialEq, const N: usize> Ringbuf<T, { N }> {
                     }
                 }
 
-                if last + 1 >= self.buffer.len() {
-                    0
-                } else {
-                    last + 1
-                }
+                next_index(Some(last), self.buffer.len())
             }
         };
 
@@ -278,3 +487,240 @@ impl<T: Copy + PartialEq, const N: usize> Ringbuf<T, { N }> {
         self.last = Some(ndx);
     }
 }
+
+impl<T: Copy + PartialEq, const N: usize> RecordEntry<T> for Ringbuf<T, { N }> {
+    fn record_entry(&mut self, line: u16, payload: T) {
+        self.entry(line, payload)
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> Dump for Ringbuf<T, { N }> {
+    fn dump(
+        &self,
+        address: usize,
+        writer: &mut DumpWriter<'_>,
+    ) -> Result<(), DumpError> {
+        writer.ringbuf(
+            address,
+            false,
+            N,
+            core::mem::size_of::<T>(),
+            self.last,
+        )?;
+
+        for ent in self.buffer.iter() {
+            writer.entry(
+                ent.line,
+                ent.generation,
+                ent.count,
+                None,
+                payload_bytes(&ent.payload),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// The time at which a [`TimestampedRingbuf`] entry was generated.
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timestamp {
+    /// The kernel's timer, in ticks.
+    pub ticks: u64,
+}
+
+impl Timestamp {
+    pub const ZERO: Self = Self { ticks: 0 };
+
+    pub fn now() -> Self {
+        Self {
+            ticks: userlib::sys_get_timer().now,
+        }
+    }
+}
+
+///
+/// The structure of a single [`TimestampedRingbuf`] entry: a [`RingbufEntry`]
+/// that also records the time of the first and the most recent occurrence of
+/// its payload.
+///
+#[derive(Debug, Copy, Clone)]
+pub struct TimestampedRingbufEntry<T: Copy + PartialEq> {
+    pub line: u16,
+    pub generation: u16,
+    pub count: u32,
+    pub payload: T,
+    pub first_time: Timestamp,
+    pub last_time: Timestamp,
+}
+
+///
+/// A ring buffer of parametrized type and size, with timestamped entries.  As
+/// with [`Ringbuf`], see the [`ringbuf!`] macro rather than instantiating
+/// this directly.
+///
+#[derive(Debug)]
+pub struct TimestampedRingbuf<T: Copy + PartialEq, const N: usize> {
+    pub last: Option<usize>,
+    pub buffer: [TimestampedRingbufEntry<T>; N],
+}
+
+impl<T: Copy + PartialEq, const N: usize> TimestampedRingbuf<T, { N }> {
+    pub fn entry(&mut self, line: u16, payload: T) {
+        let now = Timestamp::now();
+
+        if let Some(last) = self.last {
+            let ent = &mut self.buffer[last];
+
+            if ent.line == line && ent.payload == payload {
+                // As with an untimestamped ring buffer, only reuse this
+                // entry if we don't overflow the count.
+                if let Some(new_count) = ent.count.checked_add(1) {
+                    ent.count = new_count;
+                    ent.last_time = now;
+                    return;
+                }
+            }
+        }
+
+        let ndx = next_index(self.last, self.buffer.len());
+
+        let ent = &mut self.buffer[ndx];
+        ent.line = line;
+        ent.payload = payload;
+        ent.count = 1;
+        ent.generation = ent.generation.wrapping_add(1);
+        ent.first_time = now;
+        ent.last_time = now;
+
+        self.last = Some(ndx);
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> RecordEntry<T>
+    for TimestampedRingbuf<T, { N }>
+{
+    fn record_entry(&mut self, line: u16, payload: T) {
+        self.entry(line, payload)
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> Dump
+    for TimestampedRingbuf<T, { N }>
+{
+    fn dump(
+        &self,
+        address: usize,
+        writer: &mut DumpWriter<'_>,
+    ) -> Result<(), DumpError> {
+        writer.ringbuf(
+            address,
+            true,
+            N,
+            core::mem::size_of::<T>(),
+            self.last,
+        )?;
+
+        for ent in self.buffer.iter() {
+            let times = [ent.first_time.ticks, ent.last_time.ticks];
+
+            writer.entry(
+                ent.line,
+                ent.generation,
+                ent.count,
+                Some(times),
+                payload_bytes(&ent.payload),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Implemented (typically via `#[derive(Count)]`) by enums whose variants can
+/// be counted with [`count!`].  `Counters` is a table with a counter for each
+/// variant; see the [`counters!`] macro for declaring one.
+///
+pub trait Count {
+    type Counters: 'static;
+
+    /// An initial (all zero) counter table.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW_COUNTERS: Self::Counters;
+
+    /// Increments the counter corresponding to the variant of `self`.
+    fn count(&self, counters: &Self::Counters);
+}
+
+///
+/// Increments `counter`, saturating at `u32::MAX`.  Because tasks are single
+/// threaded, this needn't be (and isn't) an atomic read-modify-write, which
+/// isn't available on all targets.
+///
+#[inline(always)]
+pub fn increment(counter: &AtomicU32) {
+    let count = counter.load(Ordering::Relaxed);
+    counter.store(count.saturating_add(1), Ordering::Relaxed);
+}
+
+/// Declares a table of event counters in the current module or context.
+///
+/// `counters!(NAME, Type)` makes a table named `NAME`, with a counter for each
+/// variant of `Type` (which must implement [`Count`], typically by deriving
+/// it).
+///
+/// The resulting table will be static, so `NAME` should be uppercase.  By
+/// convention, the name should end in `COUNTERS`.
+///
+/// If you omit the name, it will default to `__COUNTERS`.
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! counters {
+    ($name:ident, $t:ty) => {
+        #[used]
+        static $name: <$t as $crate::Count>::Counters =
+            <$t as $crate::Count>::NEW_COUNTERS;
+    };
+    ($t:ty) => {
+        $crate::counters!(__COUNTERS, $t);
+    };
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! counters {
+    ($name:ident, $t:ty) => {};
+    ($t:ty) => {};
+}
+
+/// Counts an event in a named table of counters (which should have been
+/// declared with the `counters!` macro).
+///
+/// `count!(NAME, expr)` will increment the counter in `NAME` corresponding to
+/// the variant of `expr`.
+///
+/// If you declared your counters without a name, you can also use this
+/// without a name, and it will default to `__COUNTERS`.
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! count {
+    ($counters:expr, $event:expr) => {{
+        // As in ringbuf_entry!, evaluate the event and the counters without
+        // letting them see each other's bindings.
+        let (e, counters) = ($event, &$counters);
+        $crate::Count::count(&e, counters);
+    }};
+    ($event:expr) => {
+        $crate::count!(__COUNTERS, $event);
+    };
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! count {
+    ($counters:expr, $event:expr) => {};
+    ($event:expr) => {};
+}
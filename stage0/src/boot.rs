@@ -0,0 +1,196 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Image slot selection
+//!
+//! By default we boot the image in slot A. A task may ask (via hypocall)
+//! that the other slot be booted next time, in which case the request is
+//! consumed by the next boot whether or not it can be honored: if the
+//! requested slot holds no valid image, we fall back to the other.
+//!
+//! Hypocalls run in the context of the calling task, which can read our
+//! flash (it is mapped as `extratext`) but not our RAM -- so the slot that
+//! we booted, why, and any request for the next boot are all kept in a boot
+//! record in the last page of our flash, outside of the signed image. We
+//! rewrite the record at boot only when its contents change (that is, when
+//! a request has been made, or a different image has been booted), to spare
+//! the flash.
+
+use crate::image_header::{self, ImageHeader, HEADER_WORDS};
+use lpc55_romapi::FlashStatus;
+
+// These are part of the hypocall ABI, and must match `lib/hypocalls`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum BootSlot {
+    A = 0,
+    B = 1,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum BootReason {
+    /// No slot was requested; we booted the default.
+    Default = 0,
+    /// We booted the slot that was requested.
+    Requested = 1,
+    /// The preferred slot didn't hold a valid image.
+    Fallback = 2,
+}
+
+impl BootSlot {
+    fn from_u32(slot: u32) -> Option<Self> {
+        match slot {
+            0 => Some(BootSlot::A),
+            1 => Some(BootSlot::B),
+            _ => None,
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            BootSlot::A => BootSlot::B,
+            BootSlot::B => BootSlot::A,
+        }
+    }
+}
+
+/// Size of the boot record page, which must match that reserved for it by
+/// `xtask`.
+const BOOT_RECORD_SIZE: u32 = 512;
+
+/// Marks a valid boot record ("BRC1").
+const BOOT_RECORD_MAGIC: u32 = 0x3143_5242;
+
+/// Marks the absence of a request in a boot record.
+const NO_REQUEST: u32 = u32::MAX;
+
+extern "C" {
+    static address_of_boot_record: u32;
+}
+
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct BootRecord {
+    /// `BOOT_RECORD_MAGIC`, if the record is valid.
+    magic: u32,
+    /// The slot requested for the next boot, or `NO_REQUEST`.
+    request: u32,
+    /// The slot that we booted, as a `BootSlot`...
+    pub slot: u32,
+    /// ...and why, as a `BootReason`.
+    pub reason: u32,
+    /// Non-zero if the slot that we didn't boot held a valid image.
+    other_valid: u32,
+    /// The build number of the image that we booted...
+    pub version: u32,
+    /// ...and a copy of its header.
+    pub header: [u32; HEADER_WORDS],
+}
+
+fn record_address() -> u32 {
+    unsafe { address_of_boot_record }
+}
+
+/// Returns the boot record, if there's a valid one.
+pub fn record() -> Option<BootRecord> {
+    let addr = record_address();
+
+    // Reading flash that hasn't been programmed will fault, so check first.
+    if !lpc55_romapi::validate_programmed(addr, BOOT_RECORD_SIZE) {
+        return None;
+    }
+
+    let record = unsafe { core::ptr::read_volatile(addr as *const BootRecord) };
+
+    if record.magic != BOOT_RECORD_MAGIC {
+        return None;
+    }
+
+    Some(record)
+}
+
+fn write_record(record: &BootRecord) -> Result<(), FlashStatus> {
+    let addr = record_address();
+    let mut page = [0u32; BOOT_RECORD_SIZE as usize / 4];
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            record as *const BootRecord as *const u32,
+            page.as_mut_ptr(),
+            core::mem::size_of::<BootRecord>() / 4,
+        );
+
+        lpc55_romapi::flash_erase(addr, BOOT_RECORD_SIZE)?;
+        lpc55_romapi::flash_write(addr, page.as_mut_ptr(), BOOT_RECORD_SIZE)
+    }
+}
+
+fn slot_image(slot: BootSlot) -> Option<&'static ImageHeader> {
+    match slot {
+        BootSlot::A => image_header::get_image_a(),
+        BootSlot::B => image_header::get_image_b(),
+    }
+}
+
+/// Picks the image to boot, and records our choice.
+pub fn select() -> Option<&'static ImageHeader> {
+    let stored = record();
+    let requested = stored.and_then(|r| BootSlot::from_u32(r.request));
+    let preferred = requested.unwrap_or(BootSlot::A);
+
+    let (slot, reason, image) = match slot_image(preferred) {
+        Some(image) => (
+            preferred,
+            match requested {
+                Some(_) => BootReason::Requested,
+                None => BootReason::Default,
+            },
+            image,
+        ),
+        None => {
+            let slot = preferred.other();
+            (slot, BootReason::Fallback, slot_image(slot)?)
+        }
+    };
+
+    let booted = BootRecord {
+        magic: BOOT_RECORD_MAGIC,
+        request: NO_REQUEST,
+        slot: slot as u32,
+        reason: reason as u32,
+        other_valid: slot_image(slot.other()).is_some() as u32,
+        version: image.get_version(),
+        header: image.to_words(),
+    };
+
+    //
+    // If we fail to write the record, the hypocalls that need it will fail
+    // (or, if the erase failed too, report what they did last time) -- but
+    // that's no reason not to boot.
+    //
+    if stored != Some(booted) {
+        let _ = write_record(&booted);
+    }
+
+    Some(image)
+}
+
+/// Requests that the slot other than the one we booted be booted next time.
+/// This fails if that slot didn't hold a valid image when we booted.
+pub fn request_other() -> Result<BootSlot, FlashStatus> {
+    let mut record = record().ok_or(FlashStatus::InvalidArg)?;
+    let slot = BootSlot::from_u32(record.slot)
+        .ok_or(FlashStatus::InvalidArg)?
+        .other();
+
+    if record.other_valid == 0 {
+        return Err(FlashStatus::InvalidArg);
+    }
+
+    record.request = slot as u32;
+    write_record(&record)?;
+
+    Ok(slot)
+}
@@ -0,0 +1,182 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks on hypocall arguments
+//!
+//! These are kept apart from the hypocalls themselves (and from anything
+//! that touches the hardware), so that they can be tested on the host.
+
+use core::ops::Range;
+use lpc55_romapi::FlashStatus;
+
+/// Smallest unit of flash that can be erased or written.
+pub const FLASH_PAGE_SIZE: u32 = 512;
+
+/// Size of the CFPA and the CMPA.
+pub const PFR_PAGE_SIZE: u32 = 512;
+
+/// Checks a request to erase or write `len` bytes of region `which`,
+/// returning the address at which to start.
+pub fn check_region(
+    which: u32,
+    len: u32,
+    region: Range<u32>,
+) -> Result<u32, FlashStatus> {
+    if which != 0 || len == 0 {
+        return Err(FlashStatus::InvalidArg);
+    }
+
+    if len % FLASH_PAGE_SIZE != 0 {
+        return Err(FlashStatus::AlignmentError);
+    }
+
+    if len > region.end.saturating_sub(region.start) {
+        return Err(FlashStatus::SizeError);
+    }
+
+    Ok(region.start)
+}
+
+/// Checks a buffer of `len` bytes at `buffer` into which we are to write.
+pub fn check_buffer(buffer: *mut u32, len: u32) -> Result<(), FlashStatus> {
+    if buffer.is_null() || len == 0 {
+        return Err(FlashStatus::InvalidArg);
+    }
+
+    if buffer as usize % 4 != 0 || len % 4 != 0 {
+        return Err(FlashStatus::AlignmentError);
+    }
+
+    Ok(())
+}
+
+/// Checks a read of `len` bytes at `offset` within the CMPA or CFPA.
+pub fn check_pfr_read(
+    offset: u32,
+    buffer: *mut u32,
+    len: u32,
+) -> Result<(), FlashStatus> {
+    check_buffer(buffer, len)?;
+
+    if offset % 4 != 0 {
+        return Err(FlashStatus::AlignmentError);
+    }
+
+    match offset.checked_add(len) {
+        Some(end) if end <= PFR_PAGE_SIZE => Ok(()),
+        _ => Err(FlashStatus::SizeError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: Range<u32> = 0x9_0000..0x9_8000;
+
+    fn buffer(addr: usize) -> *mut u32 {
+        addr as *mut u32
+    }
+
+    #[test]
+    fn region() {
+        assert_eq!(check_region(0, 512, REGION), Ok(0x9_0000));
+        assert_eq!(check_region(0, 0x8000, REGION), Ok(0x9_0000));
+
+        // Only region 0 exists, and empty requests are refused.
+        assert_eq!(check_region(1, 512, REGION), Err(FlashStatus::InvalidArg));
+        assert_eq!(check_region(0, 0, REGION), Err(FlashStatus::InvalidArg));
+
+        // Requests must be whole pages...
+        assert_eq!(
+            check_region(0, 100, REGION),
+            Err(FlashStatus::AlignmentError)
+        );
+        assert_eq!(
+            check_region(0, 513, REGION),
+            Err(FlashStatus::AlignmentError)
+        );
+
+        // ...that fit in the region.
+        assert_eq!(
+            check_region(0, 0x8200, REGION),
+            Err(FlashStatus::SizeError)
+        );
+        assert_eq!(
+            check_region(0, u32::MAX - 511, REGION),
+            Err(FlashStatus::SizeError)
+        );
+
+        // An empty (or inverted) region can't take anything.
+        assert_eq!(
+            check_region(0, 512, 0x9_0000..0x9_0000),
+            Err(FlashStatus::SizeError)
+        );
+        assert_eq!(
+            check_region(0, 512, 0x9_8000..0x9_0000),
+            Err(FlashStatus::SizeError)
+        );
+    }
+
+    #[test]
+    fn buffer_checks() {
+        assert_eq!(check_buffer(buffer(0x2000_4000), 4), Ok(()));
+        assert_eq!(check_buffer(buffer(0x2000_4000), 512), Ok(()));
+
+        assert_eq!(
+            check_buffer(core::ptr::null_mut(), 4),
+            Err(FlashStatus::InvalidArg)
+        );
+        assert_eq!(
+            check_buffer(buffer(0x2000_4000), 0),
+            Err(FlashStatus::InvalidArg)
+        );
+
+        // Both the buffer and its length must be word aligned.
+        assert_eq!(
+            check_buffer(buffer(0x2000_4002), 4),
+            Err(FlashStatus::AlignmentError)
+        );
+        assert_eq!(
+            check_buffer(buffer(0x2000_4000), 6),
+            Err(FlashStatus::AlignmentError)
+        );
+    }
+
+    #[test]
+    fn pfr_read() {
+        let buf = buffer(0x2000_4000);
+
+        assert_eq!(check_pfr_read(0, buf, 16), Ok(()));
+        assert_eq!(check_pfr_read(0, buf, PFR_PAGE_SIZE), Ok(()));
+        assert_eq!(check_pfr_read(PFR_PAGE_SIZE - 4, buf, 4), Ok(()));
+
+        // The buffer is checked first.
+        assert_eq!(
+            check_pfr_read(0, core::ptr::null_mut(), 16),
+            Err(FlashStatus::InvalidArg)
+        );
+        assert_eq!(check_pfr_read(0, buf, 0), Err(FlashStatus::InvalidArg));
+
+        assert_eq!(
+            check_pfr_read(2, buf, 16),
+            Err(FlashStatus::AlignmentError)
+        );
+
+        // Reads must stay within the page, including when the end of the
+        // read would overflow.
+        assert_eq!(
+            check_pfr_read(PFR_PAGE_SIZE - 8, buf, 16),
+            Err(FlashStatus::SizeError)
+        );
+        assert_eq!(
+            check_pfr_read(PFR_PAGE_SIZE, buf, 4),
+            Err(FlashStatus::SizeError)
+        );
+        assert_eq!(
+            check_pfr_read(u32::MAX - 3, buf, 16),
+            Err(FlashStatus::SizeError)
+        );
+    }
+}
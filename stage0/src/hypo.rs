@@ -3,9 +3,58 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Hypovisor calls
+//!
+//! Each hypocall is a function here (named with a leading `__`) reached via
+//! a veneer in the `.flash_hypo` section, the address of which is exported
+//! to tasks (see `sharedsyms` in app.toml). Hypocalls run in the context of
+//! the calling task, so can reach only what it can: our flash (but not our
+//! RAM), the ROM, and the flash controller. Every argument comes from a
+//! task, so is checked before use; the checks live in the `check` module.
 
+use crate::boot;
+use crate::check::{check_buffer, check_pfr_read, check_region};
+use crate::image_header::ImageHeader;
+use core::ops::Range;
 use lpc55_romapi::FlashStatus;
 
+/// Start of the protected flash region (CFPA, CMPA and key store), which
+/// follows the last page of flash that we can use.
+const PFR_START: u32 = 0x9_de00;
+
+/// Size of the region set aside for testing flash writes (one sector).
+const TEST_REGION_SIZE: u32 = 0x8000;
+
+extern "C" {
+    static address_of_test_region: u32;
+}
+
+/// Returns the region of flash that tasks may erase and write: the sector
+/// that follows the last image, or as much of it as precedes the protected
+/// flash region.
+fn test_region() -> Range<u32> {
+    let start = unsafe { address_of_test_region as *const u32 as u32 };
+    let end = start.saturating_add(TEST_REGION_SIZE);
+    start..u32::min(end, PFR_START)
+}
+
+/// Checks a read of the first `len` bytes of an image header.
+fn check_header_read(buffer: *mut u32, len: u32) -> Result<(), FlashStatus> {
+    check_buffer(buffer, len)?;
+
+    if len as usize > core::mem::size_of::<ImageHeader>() {
+        return Err(FlashStatus::SizeError);
+    }
+
+    Ok(())
+}
+
+fn status(result: Result<(), FlashStatus>) -> FlashStatus {
+    match result {
+        Ok(()) => FlashStatus::Success,
+        Err(e) => e,
+    }
+}
+
 // FlashStatus is represented as a u32 so it's safe to return directly.
 // We convert on the receiving end for safety
 // #[cmse_nonsecure_entry] We want this eventually
@@ -15,60 +64,139 @@ pub unsafe extern "C" fn __write_to_flash(
     buffer: *mut u32,
     len: u32,
 ) -> FlashStatus {
-    extern "C" {
-        static address_of_test_region: u32;
-    }
-
-    if len == 0 {
-        return FlashStatus::InvalidArg;
-    }
-
     // We expect this to be called from non-secure (running on 28) and
     // non-privileged mode (called from hubris task). The tt instructions
     // are mostly useless for doing any kind of checking on the buffer
     // address passed in. The failure mode is going to be a fault.
+    status(
+        check_region(which, len, test_region()).and_then(|flash_addr| {
+            lpc55_romapi::flash_erase(flash_addr, len)?;
+            lpc55_romapi::flash_write(flash_addr, buffer, len)
+        }),
+    )
+}
 
-    if which == 0 {
-        let flash_addr = address_of_test_region as *const u32 as u32;
+#[no_mangle]
+pub unsafe extern "C" fn __erase_flash(which: u32, len: u32) -> FlashStatus {
+    status(
+        check_region(which, len, test_region())
+            .and_then(|flash_addr| lpc55_romapi::flash_erase(flash_addr, len)),
+    )
+}
 
-        if let Err(result) = lpc55_romapi::flash_erase(flash_addr, len) {
-            return result;
-        }
+/// Copies the first `len` bytes of the header of the image that we booted.
+/// The caller can't read the image itself, so this comes from the copy in
+/// the boot record.
+#[no_mangle]
+pub unsafe extern "C" fn __get_image_header(
+    buffer: *mut u32,
+    len: u32,
+) -> FlashStatus {
+    status(check_header_read(buffer, len).and_then(|_| {
+        let record = boot::record().ok_or(FlashStatus::InvalidArg)?;
 
-        if let Err(result) = lpc55_romapi::flash_write(flash_addr, buffer, len)
-        {
-            return result;
-        }
+        core::ptr::copy_nonoverlapping(
+            record.header.as_ptr(),
+            buffer,
+            len as usize / 4,
+        );
 
-        return FlashStatus::Success;
-    }
+        Ok(())
+    }))
+}
 
-    return FlashStatus::InvalidArg;
+#[no_mangle]
+pub unsafe extern "C" fn __get_image_version(version: *mut u32) -> FlashStatus {
+    status(check_buffer(version, 4).and_then(|_| {
+        *version = boot::record().ok_or(FlashStatus::InvalidArg)?.version;
+        Ok(())
+    }))
 }
 
-#[link_section = ".flash_hypo"]
-#[naked]
 #[no_mangle]
-pub unsafe extern "C" fn write_to_flash(
-    _addr: u32,
-    _buffer: *mut u32,
-    _len: u32,
-) -> u32 {
-    // ARM really wants another function to branch to based on
-    // their secure docs. We also don't have full compiler support yet
-    // so for now just keep it simple and have a single function with
-    // the sg instruction
-    //
-    // The sg is a nop when not using TrustZone. This will need to be
-    // a bxns when we get full TrustZone support
-    asm!(
-        "
-        sg
-        push {{lr}}
-        bl __write_to_flash
-        pop {{lr}}
-        bx lr
-        ",
-        options(noreturn)
-    );
+pub unsafe extern "C" fn __get_boot_info(
+    slot: *mut u32,
+    reason: *mut u32,
+) -> FlashStatus {
+    status(
+        check_buffer(slot, 4)
+            .and_then(|_| check_buffer(reason, 4))
+            .and_then(|_| {
+                let record = boot::record().ok_or(FlashStatus::InvalidArg)?;
+                *slot = record.slot;
+                *reason = record.reason;
+                Ok(())
+            }),
+    )
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn __read_cmpa(
+    offset: u32,
+    buffer: *mut u32,
+    len: u32,
+) -> FlashStatus {
+    status(check_pfr_read(offset, buffer, len).and_then(|_| {
+        let data = core::slice::from_raw_parts_mut(buffer, len as usize / 4);
+        lpc55_romapi::get_cmpa_data(data, offset, len)
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __read_cfpa(
+    offset: u32,
+    buffer: *mut u32,
+    len: u32,
+) -> FlashStatus {
+    status(check_pfr_read(offset, buffer, len).and_then(|_| {
+        let data = core::slice::from_raw_parts_mut(buffer, len as usize / 4);
+        lpc55_romapi::get_cfpa_data(data, offset, len)
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __boot_other_slot_next() -> FlashStatus {
+    status(boot::request_other().map(|_| ()))
+}
+
+// ARM really wants another function to branch to based on
+// their secure docs. We also don't have full compiler support yet
+// so for now just keep it simple and have a single function with
+// the sg instruction
+//
+// The sg is a nop when not using TrustZone. This will need to be
+// a bxns when we get full TrustZone support
+macro_rules! hypocall {
+    ($name:ident($($arg:ident: $ty:ty),*) => $target:ident) => {
+        #[link_section = ".flash_hypo"]
+        #[naked]
+        #[no_mangle]
+        pub unsafe extern "C" fn $name($($arg: $ty),*) -> u32 {
+            asm!(
+                "
+                sg
+                push {{lr}}
+                bl {target}
+                pop {{lr}}
+                bx lr
+                ",
+                target = sym $target,
+                options(noreturn)
+            );
+        }
+    };
+}
+
+hypocall!(write_to_flash(_which: u32, _buffer: *mut u32, _len: u32)
+    => __write_to_flash);
+hypocall!(erase_flash(_which: u32, _len: u32) => __erase_flash);
+hypocall!(get_image_header(_buffer: *mut u32, _len: u32)
+    => __get_image_header);
+hypocall!(get_image_version(_version: *mut u32) => __get_image_version);
+hypocall!(get_boot_info(_slot: *mut u32, _reason: *mut u32)
+    => __get_boot_info);
+hypocall!(read_cmpa(_offset: u32, _buffer: *mut u32, _len: u32)
+    => __read_cmpa);
+hypocall!(read_cfpa(_offset: u32, _buffer: *mut u32, _len: u32)
+    => __read_cfpa);
+hypocall!(boot_other_slot_next() => __boot_other_slot_next);
@@ -4,8 +4,12 @@
 
 extern "C" {
     static IMAGEA: ImageHeader;
+    static address_of_imageb_flash: u32;
 }
 
+/// Size of an image header, in words.
+pub const HEADER_WORDS: usize = core::mem::size_of::<ImageHeader>() / 4;
+
 // TODO grab this from lpc55_support or another crate eventually
 #[derive(Debug)]
 #[repr(C)]
@@ -25,27 +29,40 @@ struct CertHeader {
 }
 
 pub fn get_image_a() -> Option<&'static ImageHeader> {
-    // Taking the reference to our supposed imagea
-    let imagea = unsafe { &IMAGEA };
+    check_image(unsafe { &IMAGEA })
+}
+
+/// Returns image B, if the app lays one out (in which case its address is
+/// non-zero) and it's valid.
+pub fn get_image_b() -> Option<&'static ImageHeader> {
+    let addr = unsafe { address_of_imageb_flash };
+
+    if addr == 0 {
+        return None;
+    }
+
+    check_image(unsafe { &*(addr as *const ImageHeader) })
+}
 
+fn check_image(image: &'static ImageHeader) -> Option<&'static ImageHeader> {
     // Step 1: check if the flash for this image is actually programmed
-    if !imagea.validate() {
+    if !image.validate() {
         return None;
     }
 
     // We've validated that the image range should be safe
-    let img_start = imagea.get_img_start();
+    let img_start = image.get_img_start();
 
-    let table_start = imagea.get_table_start();
+    let table_start = image.get_table_start();
 
     // Step 2: Check that the table pointed to by this image is actually
     // within our image range that we checked before
-    if !imagea.check_bounds(table_start) {
+    if !image.check_bounds(table_start) {
         return None;
     }
 
     // The table is within bounds so accessing it will not cause a fault
-    let table = unsafe { &*imagea.get_cert_table() };
+    let table = unsafe { &*image.get_cert_table() };
 
     // our 'signature' is the letters cert. If this isn't valid the rest
     // of the data is probably not valid either.
@@ -56,13 +73,13 @@ pub fn get_image_a() -> Option<&'static ImageHeader> {
     let key_start = &table.key as *const u32 as u32;
 
     // validate that our key is fully programmed
-    if !imagea.check_bounds(key_start + table.key_size) {
+    if !image.check_bounds(key_start + table.key_size) {
         return None;
     }
 
     let sig_addr = img_start + table.total_image_len;
 
-    if !imagea.check_bounds(sig_addr) {
+    if !image.check_bounds(sig_addr) {
         return None;
     }
 
@@ -70,16 +87,16 @@ pub fn get_image_a() -> Option<&'static ImageHeader> {
     let sig_size = unsafe { core::ptr::read_volatile(sig_addr as *const u32) };
 
     // Check the signature
-    if !imagea.check_bounds(sig_addr + 4) {
+    if !image.check_bounds(sig_addr + 4) {
         return None;
     }
 
-    if !imagea.check_bounds(sig_addr + 4 + sig_size) {
+    if !image.check_bounds(sig_addr + 4 + sig_size) {
         return None;
     }
 
     // Check what is supposed to be the full image length
-    if !imagea.check_bounds(img_start + table.total_image_len) {
+    if !image.check_bounds(img_start + table.total_image_len) {
         return None;
     }
 
@@ -87,7 +104,7 @@ pub fn get_image_a() -> Option<&'static ImageHeader> {
     // - Accessing the full image range
     // - Accessing the key range
     // - Accessing the signature range
-    Some(imagea)
+    Some(image)
 }
 
 // The careful observer will note that yes this is just the
@@ -153,4 +170,27 @@ impl ImageHeader {
     pub fn get_sp(&self) -> u32 {
         self.sp
     }
+
+    /// Returns the build number from the image's certificate table. This
+    /// is only safe to call on an image that has been returned by
+    /// `get_image_a` or `get_image_b`, which check that the table is in
+    /// bounds.
+    pub fn get_version(&self) -> u32 {
+        unsafe { (*self.get_cert_table()).build_number }
+    }
+
+    /// Returns a copy of the header, as words.
+    pub fn to_words(&self) -> [u32; HEADER_WORDS] {
+        let mut words = [0; HEADER_WORDS];
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self as *const Self as *const u32,
+                words.as_mut_ptr(),
+                HEADER_WORDS,
+            );
+        }
+
+        words
+    }
 }
@@ -6,17 +6,28 @@
 #![feature(asm)]
 #![feature(naked_functions)]
 #![feature(array_methods)]
-#![no_main]
-#![no_std]
+// The checks on hypocall arguments can be tested on the host; nothing else
+// is built there.
+#![cfg_attr(not(test), no_main)]
+#![cfg_attr(not(test), no_std)]
 
+#[cfg(not(test))]
 extern crate panic_halt;
+#[cfg(not(test))]
 use cortex_m::peripheral::Peripherals;
+#[cfg(not(test))]
 use cortex_m_rt::entry;
 
+#[cfg(not(test))]
+mod boot;
+mod check;
+#[cfg(not(test))]
 mod hypo;
+#[cfg(not(test))]
 mod image_header;
 
 /// Initial entry point for handling a memory management fault.
+#[cfg(not(test))]
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn MemoryManagement() {
@@ -24,6 +35,7 @@ pub unsafe extern "C" fn MemoryManagement() {
 }
 
 /// Initial entry point for handling a bus fault.
+#[cfg(not(test))]
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn BusFault() {
@@ -31,21 +43,24 @@ pub unsafe extern "C" fn BusFault() {
 }
 
 /// Initial entry point for handling a usage fault.
+#[cfg(not(test))]
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn UsageFault() {
     loop {}
 }
 
+#[cfg(not(test))]
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn SecureFault() {
     loop {}
 }
 
+#[cfg(not(test))]
 #[entry]
 fn main() -> ! {
-    let imagea = image_header::get_image_a().unwrap();
+    let imagea = boot::select().unwrap();
 
     let entry_pt = imagea.get_pc();
     let stack = imagea.get_sp();
@@ -95,6 +95,12 @@ impl From<u8> for Generation {
     }
 }
 
+impl From<Generation> for u8 {
+    fn from(g: Generation) -> Self {
+        g.0
+    }
+}
+
 /// Indicates priority of a task.
 ///
 /// Priorities are small numbers starting from zero. Numerically lower
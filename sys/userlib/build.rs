@@ -23,15 +23,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     println!("cargo:rerun-if-env-changed=HUBRIS_TASKS");
     let mut task_enum = vec![];
+    let mut task_names_table = vec![];
     let task_count;
     if let Ok(task_names) = env::var("HUBRIS_TASKS") {
         println!("HUBRIS_TASKS = {}", task_names);
         for (i, name) in task_names.split(",").enumerate() {
             task_enum.push(format!("    {} = {},", name, i));
+            task_names_table.push(format!("    {:?},", name));
         }
         task_count = task_names.split(",").count();
     } else {
         task_enum.push("    anonymous = 0,".to_string());
+        task_names_table.push("    \"anonymous\",".to_string());
         task_count = 1;
     }
     let mut task_file = File::create(out.join("tasks.rs")).unwrap();
@@ -44,5 +47,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     writeln!(task_file, "pub const NUM_TASKS: usize = {};", task_count)
         .unwrap();
 
+    // The names of all tasks, indexed by task index. This is the same
+    // information as the `Task` enum above, but in a form that can be searched
+    // at runtime; see `task_slot::lookup_task`.
+    writeln!(task_file, "pub static TASK_NAMES: [&str; NUM_TASKS] = [")
+        .unwrap();
+    for line in task_names_table {
+        writeln!(task_file, "{}", line).unwrap();
+    }
+    writeln!(task_file, "];").unwrap();
+
+    // The linker script of every task includes this fragment, which keeps
+    // the registry of ring buffers (see `ringbuf_registry`) only in tasks
+    // that have opted in to it; elsewhere, it's empty.
+    let mut registry = File::create(out.join("ringbuf-registry.x")).unwrap();
+    if env::var_os("CARGO_FEATURE_RINGBUF_REGISTRY").is_some() {
+        writeln!(registry, "KEEP(*(.ringbuf_registry .ringbuf_registry.*));")
+            .unwrap();
+    }
+    println!("cargo:rustc-link-search={}", out.display());
+
     Ok(())
 }
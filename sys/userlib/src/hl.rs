@@ -259,6 +259,10 @@ impl<'a> Message<'a> {
     pub fn lease_count(&self) -> usize {
         self.lease_count
     }
+
+    pub fn sender(&self) -> TaskId {
+        self.sender
+    }
 }
 
 /// A typed handle to a task, used to send a single reply of type `R`.
@@ -443,6 +447,324 @@ pub struct BorrowInfo {
     pub len: usize,
 }
 
+/// Errors that can arise when streaming data through a `BorrowReader`,
+/// `BorrowWriter`, or `copy_between`.
+///
+/// Unlike the `Option`-returning operations on `Borrow`, these distinguish
+/// between the ways a transfer can go wrong, so that servers can report
+/// something more useful than "bad lease" if they choose to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LeaseError {
+    /// The borrow doesn't exist, or the lender has defected or been killed.
+    Defected,
+    /// The borrow exists, but doesn't grant the access (read or write) that
+    /// the operation requires.
+    BadAttributes,
+    /// The operation would run past the end of the borrow. No data has been
+    /// transferred.
+    EndOfLease,
+    /// The kernel transferred fewer bytes than requested, even though the
+    /// borrow claimed to be large enough. This indicates that the lender has
+    /// changed underneath us, and should be treated like `Defected`.
+    ShortTransfer,
+}
+
+impl Borrow<'_> {
+    /// Checks that this borrow exists and grants at least `atts`, returning
+    /// its length on success.
+    fn check(&self, atts: abi::LeaseAttributes) -> Result<usize, LeaseError> {
+        let info = self.info().ok_or(LeaseError::Defected)?;
+        if info.attributes.contains(atts) {
+            Ok(info.len)
+        } else {
+            Err(LeaseError::BadAttributes)
+        }
+    }
+
+    /// Like `read_fully_at`, but reports what went wrong.
+    fn try_read_fully_at(
+        &self,
+        offset: usize,
+        dest: &mut [u8],
+    ) -> Result<(), LeaseError> {
+        let (rc, n) = sys_borrow_read(self.id, self.index, offset, dest);
+        if rc != 0 {
+            Err(LeaseError::Defected)
+        } else if n != dest.len() {
+            Err(LeaseError::ShortTransfer)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like `write_fully_at`, but reports what went wrong.
+    fn try_write_fully_at(
+        &self,
+        offset: usize,
+        src: &[u8],
+    ) -> Result<(), LeaseError> {
+        let (rc, n) = sys_borrow_write(self.id, self.index, offset, src);
+        if rc != 0 {
+            Err(LeaseError::Defected)
+        } else if n != src.len() {
+            Err(LeaseError::ShortTransfer)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A cursor that reads sequentially from a readable `Borrow`, tracking its
+/// position.
+///
+/// The length of the borrow is fetched once, when the reader is created, and
+/// all reads are checked against it before asking the kernel to do anything.
+/// This matters: asking the kernel to read at an offset past the end of a
+/// lease is a usage error that faults the *borrower*, i.e. us.
+pub struct BorrowReader<'caller> {
+    borrow: Borrow<'caller>,
+    len: usize,
+    pos: usize,
+}
+
+impl<'caller> BorrowReader<'caller> {
+    /// Creates a reader positioned at the start of `borrow`.
+    ///
+    /// Fails if the borrow doesn't exist or isn't readable.
+    pub fn new(borrow: Borrow<'caller>) -> Result<Self, LeaseError> {
+        let len = borrow.check(abi::LeaseAttributes::READ)?;
+        Ok(Self {
+            borrow,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Total length of the underlying borrow, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Current offset into the borrow.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    /// Checks whether everything in the borrow has been read.
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Reads up to `dest.len()` bytes into `dest`, returning the number of
+    /// bytes actually read. This returns `Ok(0)` once the end of the borrow has
+    /// been reached.
+    pub fn read(&mut self, dest: &mut [u8]) -> Result<usize, LeaseError> {
+        let n = dest.len().min(self.remaining());
+        self.read_exact(&mut dest[..n])?;
+        Ok(n)
+    }
+
+    /// Reads exactly `dest.len()` bytes into `dest`.
+    ///
+    /// If fewer than `dest.len()` bytes remain, this returns
+    /// `LeaseError::EndOfLease` and does not advance.
+    pub fn read_exact(&mut self, dest: &mut [u8]) -> Result<(), LeaseError> {
+        if dest.len() > self.remaining() {
+            return Err(LeaseError::EndOfLease);
+        }
+        if !dest.is_empty() {
+            self.borrow.try_read_fully_at(self.pos, dest)?;
+            self.pos += dest.len();
+        }
+        Ok(())
+    }
+
+    /// Reads one item of type `T`. As with `Borrow::read_at`, no alignment
+    /// requirements are placed on the client side.
+    pub fn read_value<T>(&mut self) -> Result<T, LeaseError>
+    where
+        T: Default + FromBytes + AsBytes,
+    {
+        let mut dest = T::default();
+        self.read_exact(dest.as_bytes_mut())?;
+        Ok(dest)
+    }
+
+    /// Advances the position by `n` bytes without reading them.
+    pub fn skip(&mut self, n: usize) -> Result<(), LeaseError> {
+        if n > self.remaining() {
+            return Err(LeaseError::EndOfLease);
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    /// Reads the rest of the borrow through `buf` one chunk at a time, handing
+    /// each chunk to `body` along with its offset within the borrow.
+    ///
+    /// `buf` is typically a small buffer on the caller's stack; each chunk is
+    /// at most `buf.len()` bytes. If `body` fails, iteration stops and its
+    /// error is returned; the position is left after the failed chunk.
+    ///
+    /// # Panics
+    ///
+    /// If `buf` is empty and there is data left to read.
+    pub fn for_each_chunk<E>(
+        &mut self,
+        buf: &mut [u8],
+        mut body: impl FnMut(usize, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<LeaseError>,
+    {
+        while !self.is_empty() {
+            assert!(!buf.is_empty());
+            let offset = self.pos;
+            let n = self.read(buf)?;
+            body(offset, &buf[..n])?;
+        }
+        Ok(())
+    }
+}
+
+/// A cursor that writes sequentially into a writable `Borrow`, tracking its
+/// position.
+///
+/// As with `BorrowReader`, the length of the borrow is fetched once and all
+/// writes are bounds-checked against it before entering the kernel.
+pub struct BorrowWriter<'caller> {
+    borrow: Borrow<'caller>,
+    len: usize,
+    pos: usize,
+}
+
+impl<'caller> BorrowWriter<'caller> {
+    /// Creates a writer positioned at the start of `borrow`.
+    ///
+    /// Fails if the borrow doesn't exist or isn't writable.
+    pub fn new(borrow: Borrow<'caller>) -> Result<Self, LeaseError> {
+        let len = borrow.check(abi::LeaseAttributes::WRITE)?;
+        Ok(Self {
+            borrow,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Total length of the underlying borrow, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Current offset into the borrow.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Number of bytes of space left in the borrow.
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    /// Checks whether the borrow has been completely filled.
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Writes as much of `src` as will fit, returning the number of bytes
+    /// written. This returns `Ok(0)` once the borrow is full.
+    pub fn write(&mut self, src: &[u8]) -> Result<usize, LeaseError> {
+        let n = src.len().min(self.remaining());
+        self.write_all(&src[..n])?;
+        Ok(n)
+    }
+
+    /// Writes all of `src`.
+    ///
+    /// If fewer than `src.len()` bytes of space remain, this returns
+    /// `LeaseError::EndOfLease` and writes nothing.
+    pub fn write_all(&mut self, src: &[u8]) -> Result<(), LeaseError> {
+        if src.len() > self.remaining() {
+            return Err(LeaseError::EndOfLease);
+        }
+        if !src.is_empty() {
+            self.borrow.try_write_fully_at(self.pos, src)?;
+            self.pos += src.len();
+        }
+        Ok(())
+    }
+
+    /// Writes one item of type `T`. As with `Borrow::write_at`, no alignment
+    /// requirements are placed on the client side.
+    pub fn write_value<T>(&mut self, value: T) -> Result<(), LeaseError>
+    where
+        T: AsBytes,
+    {
+        self.write_all(value.as_bytes())
+    }
+
+    /// Fills the rest of the borrow through `buf` one chunk at a time. For each
+    /// chunk, `body` is handed the chunk's offset within the borrow and a
+    /// slice of `buf` to fill, which is then written out.
+    ///
+    /// If `body` fails, iteration stops and its error is returned; nothing is
+    /// written for the failed chunk.
+    ///
+    /// # Panics
+    ///
+    /// If `buf` is empty and there is space left to fill.
+    pub fn fill_by_chunk<E>(
+        &mut self,
+        buf: &mut [u8],
+        mut body: impl FnMut(usize, &mut [u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<LeaseError>,
+    {
+        while !self.is_full() {
+            assert!(!buf.is_empty());
+            let n = buf.len().min(self.remaining());
+            body(self.pos, &mut buf[..n])?;
+            self.write_all(&buf[..n])?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the entire contents of borrow `from` into the start of borrow `to`,
+/// bouncing the data through `buf`, and returns the number of bytes copied.
+///
+/// The kernel has no facility for copying directly between two leases, so the
+/// data passes through our memory one `buf`-sized chunk at a time. The two
+/// borrows may come from different callers.
+///
+/// If `to` is smaller than `from`, this returns `LeaseError::EndOfLease`
+/// without copying anything.
+///
+/// # Panics
+///
+/// If `buf` is empty and `from` is not.
+pub fn copy_between(
+    from: Borrow<'_>,
+    to: Borrow<'_>,
+    buf: &mut [u8],
+) -> Result<usize, LeaseError> {
+    let mut reader = BorrowReader::new(from)?;
+    let mut writer = BorrowWriter::new(to)?;
+
+    if reader.len() > writer.capacity() {
+        return Err(LeaseError::EndOfLease);
+    }
+
+    reader.for_each_chunk(buf, |_, chunk| writer.write_all(chunk))?;
+    Ok(writer.position())
+}
+
 /// Trait implemented by types that represent a message sent to another task.
 ///
 /// A `Call` type `C` has four parts: the contents of a value of type `C`, which
@@ -39,6 +39,7 @@ use core::marker::PhantomData;
 
 pub mod hl;
 pub mod kipc;
+pub mod ringbuf_registry;
 pub mod task_slot;
 pub mod units;
 pub mod util;
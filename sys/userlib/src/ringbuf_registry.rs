@@ -0,0 +1,278 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Registry of a task's ring buffers, and their serialization
+//!
+//! If the ringbuf crate's "registry" feature is enabled (which enables our
+//! "ringbuf-registry" feature), every ring buffer declared with the
+//! `ringbuf!` macro registers itself by placing a [`RingbufRegistration`] in
+//! the `.ringbuf_registry` section, which the task linker script then keeps
+//! as a table.  This allows the ring buffers of a task to be found -- and
+//! dumped -- without a debugger.  Without the feature, the table is empty,
+//! and costs nothing.
+//!
+//! A server opts in to having its ring buffers dumped by enabling the
+//! feature, giving its operation enum a variant for [`DUMP_OP`], and handing
+//! any such message to [`serve`]:
+//!
+//! ```ignore
+//! hl::recv_without_notification(&mut buffer, |op, msg| match op {
+//!     Op::DumpRingbufs => {
+//!         ringbuf_registry::serve(msg);
+//!         Ok(())
+//!     }
+//!     ...
+//! });
+//! ```
+//!
+//! It then responds to [`DUMP_OP`] by serializing all of its ring buffers, in
+//! the format described below:
+//!
+//! - With no leases, the server replies with the length of the dump in bytes,
+//!   as a little-endian `u32`.
+//! - With a single writable lease, the server writes the dump into it and
+//!   replies with the number of bytes written, as a little-endian `u32`.  If
+//!   the lease is too small, it replies with [`DumpError::TooSmall`].
+//!
+//! The operation number is chosen to be well above those used by any server,
+//! so that it can be added to an existing operation enum.  A server that
+//! hasn't opted in will reply to it as to any unknown operation.
+//!
+//! # Dump format
+//!
+//! All values are little-endian.  A dump consists of a header:
+//!
+//! | Size | Field                                    |
+//! |------|------------------------------------------|
+//! | 4    | Magic, [`DUMP_MAGIC`]                    |
+//! | 2    | Version, [`DUMP_VERSION`]                |
+//! | 2    | Number of ring buffers that follow       |
+//!
+//! Followed by each ring buffer:
+//!
+//! | Size | Field                                               |
+//! |------|-----------------------------------------------------|
+//! | 2    | Length of name                                      |
+//! | n    | Name, as a UTF-8 path (e.g., `task_thermal::__RINGBUF`) |
+//! | 4    | Address of the ring buffer variable                 |
+//! | 1    | Flags: bit 0 is set if the ring buffer is timestamped |
+//! | 1    | Reserved (zero)                                     |
+//! | 2    | Number of entries that follow                       |
+//! | 2    | Size of each entry's payload                        |
+//! | 2    | Index of the most recent entry, or `0xffff` if none |
+//!
+//! Followed by each of its entries, in ring buffer order:
+//!
+//! | Size | Field                                               |
+//! |------|-----------------------------------------------------|
+//! | 2    | Line                                                |
+//! | 2    | Generation                                          |
+//! | 4    | Count                                               |
+//! | 8    | Timer at first occurrence (if timestamped)          |
+//! | 8    | Timer at last occurrence (if timestamped)           |
+//! | n    | Payload, as its in-memory representation            |
+//!
+//! The payload is not self-describing: to decode it, a host finds the ring
+//! buffer variable in the task's debug info (by name or address), and
+//! interprets the payload bytes as the type of its entries' `payload` field.
+
+use crate::hl::{BorrowWriter, LeaseError};
+#[cfg(feature = "ringbuf-registry")]
+use crate::{hl, *};
+
+/// Operation number to which servers respond with a dump of their ring
+/// buffers.
+pub const DUMP_OP: u16 = 0xffe0;
+
+/// Magic number at the start of a dump ("RBD1").
+pub const DUMP_MAGIC: u32 = 0x3144_4252;
+
+/// Version of the dump format.
+pub const DUMP_VERSION: u16 = 1;
+
+/// A registered ring buffer, as emitted by the `ringbuf!` macro.
+#[repr(C)]
+pub struct RingbufRegistration {
+    /// Path of the ring buffer variable.
+    pub name: &'static str,
+    /// Serializes the ring buffer, less its name.
+    pub dump: fn(&mut DumpWriter<'_>) -> Result<(), DumpError>,
+}
+
+/// Errors that can arise in dumping ring buffers, which are also the
+/// response codes for [`DUMP_OP`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum DumpError {
+    /// The lease is missing, isn't writable, or has gone away.
+    BadLease = 2,
+    /// The lease is too small to contain the dump.
+    TooSmall = 3,
+}
+
+impl From<LeaseError> for DumpError {
+    fn from(e: LeaseError) -> Self {
+        match e {
+            LeaseError::EndOfLease => DumpError::TooSmall,
+            _ => DumpError::BadLease,
+        }
+    }
+}
+
+impl From<DumpError> for u32 {
+    fn from(e: DumpError) -> Self {
+        e as u32
+    }
+}
+
+enum Sink<'a> {
+    /// Just count the bytes.
+    Count,
+    /// Write the bytes to a caller's lease.
+    Lease(BorrowWriter<'a>),
+}
+
+/// Serializes ring buffers, either to a lease or to nowhere (to determine
+/// the length of a dump).
+pub struct DumpWriter<'a> {
+    sink: Sink<'a>,
+    written: usize,
+}
+
+impl<'a> DumpWriter<'a> {
+    /// Creates a writer that writes nothing, but counts the bytes.
+    pub fn counter() -> Self {
+        Self {
+            sink: Sink::Count,
+            written: 0,
+        }
+    }
+
+    /// Creates a writer that writes to `writer`.
+    pub fn new(writer: BorrowWriter<'a>) -> Self {
+        Self {
+            sink: Sink::Lease(writer),
+            written: 0,
+        }
+    }
+
+    /// Returns the number of bytes written thus far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), DumpError> {
+        if let Sink::Lease(writer) = &mut self.sink {
+            writer.write_all(bytes)?;
+        }
+
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    /// Writes the header of a ring buffer (following its name).
+    pub fn ringbuf(
+        &mut self,
+        address: usize,
+        timestamped: bool,
+        entries: usize,
+        payload_size: usize,
+        last: Option<usize>,
+    ) -> Result<(), DumpError> {
+        self.write(&(address as u32).to_le_bytes())?;
+        self.write(&[timestamped as u8, 0])?;
+        self.write(&(entries as u16).to_le_bytes())?;
+        self.write(&(payload_size as u16).to_le_bytes())?;
+        self.write(&last.map_or(0xffff, |l| l as u16).to_le_bytes())
+    }
+
+    /// Writes a ring buffer entry.  `times` must be given if (and only if)
+    /// the ring buffer is timestamped, as the timer at the first and the last
+    /// occurrence.
+    pub fn entry(
+        &mut self,
+        line: u16,
+        generation: u16,
+        count: u32,
+        times: Option<[u64; 2]>,
+        payload: &[u8],
+    ) -> Result<(), DumpError> {
+        self.write(&line.to_le_bytes())?;
+        self.write(&generation.to_le_bytes())?;
+        self.write(&count.to_le_bytes())?;
+
+        if let Some(times) = times {
+            for ticks in times.iter() {
+                self.write(&ticks.to_le_bytes())?;
+            }
+        }
+
+        self.write(payload)
+    }
+}
+
+#[cfg(feature = "ringbuf-registry")]
+extern "C" {
+    // Provided by the task linker script.
+    static __ringbuf_registry_start: RingbufRegistration;
+    static __ringbuf_registry_end: RingbufRegistration;
+}
+
+#[cfg(feature = "ringbuf-registry")]
+/// Returns the registered ring buffers of this task.
+pub fn registry() -> &'static [RingbufRegistration] {
+    // Safety: the linker script places the registrations (and nothing else)
+    // between these symbols.
+    unsafe {
+        let start = &__ringbuf_registry_start as *const RingbufRegistration;
+        let end = &__ringbuf_registry_end as *const RingbufRegistration;
+        let len = (end as usize - start as usize)
+            / core::mem::size_of::<RingbufRegistration>();
+
+        core::slice::from_raw_parts(start, len)
+    }
+}
+
+#[cfg(feature = "ringbuf-registry")]
+/// Dumps all of this task's ring buffers to `writer`.
+pub fn dump(writer: &mut DumpWriter<'_>) -> Result<(), DumpError> {
+    let registry = registry();
+
+    writer.write(&DUMP_MAGIC.to_le_bytes())?;
+    writer.write(&DUMP_VERSION.to_le_bytes())?;
+    writer.write(&(registry.len() as u16).to_le_bytes())?;
+
+    for r in registry {
+        writer.write(&(r.name.len() as u16).to_le_bytes())?;
+        writer.write(r.name.as_bytes())?;
+        (r.dump)(writer)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "ringbuf-registry")]
+/// Serves a [`DUMP_OP`] request, replying to its sender.
+pub fn serve(msg: hl::Message<'_>) {
+    let caller = hl::Caller::<u32>::from(msg.sender());
+
+    let result = match msg.lease_count() {
+        0 => {
+            let mut writer = DumpWriter::counter();
+            dump(&mut writer).map(|_| writer.written())
+        }
+        1 => BorrowWriter::new(caller.borrow(0))
+            .map_err(DumpError::from)
+            .and_then(|lease| {
+                let mut writer = DumpWriter::new(lease);
+                dump(&mut writer).map(|_| writer.written())
+            }),
+        _ => Err(DumpError::BadLease),
+    };
+
+    match result {
+        Ok(len) => caller.reply(len as u32),
+        Err(e) => caller.reply_fail(e),
+    }
+}
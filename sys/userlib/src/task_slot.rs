@@ -68,15 +68,25 @@ impl TaskSlot {
     pub const UNBOUND: Self = Self(VolatileConst::new(TaskId::UNBOUND.0));
 
     pub fn get_task_id(&self) -> TaskId {
-        let task_index = self.get_task_index();
+        match self.try_get_task_id() {
+            Some(id) => id,
+            None => panic!("Attempted to get task id of unbound TaskSlot"),
+        }
+    }
 
-        if task_index == TaskId::UNBOUND.0 {
-            panic!("Attempted to get task id of unbound TaskSlot");
+    /// Variant of `get_task_id` that returns `None`, rather than panicking, if
+    /// this slot was never bound to a task.
+    pub fn try_get_task_id(&self) -> Option<TaskId> {
+        if self.is_bound() {
+            Some(refreshed_task_id(self.get_task_index().into()))
+        } else {
+            None
         }
+    }
 
-        let prototype =
-            TaskId::for_index_and_gen(task_index.into(), Generation::default());
-        crate::sys_refresh_task_id(prototype)
+    /// Checks whether this slot has been bound to a task by the build system.
+    pub fn is_bound(&self) -> bool {
+        self.get_task_index() != TaskId::UNBOUND.0
     }
 
     pub fn get_task_index(&self) -> u16 {
@@ -84,6 +94,34 @@ impl TaskSlot {
     }
 }
 
+/// Looks up a task by the name it was given in the application's `app.toml`,
+/// returning its current `TaskId`, or `None` if the image has no task by that
+/// name.
+///
+/// Unlike a `TaskSlot`, this requires no per-peer configuration: the table of
+/// task names is generated from the list of tasks that xtask passes to every
+/// task build, so any task can address any other. The price is a linear
+/// search by string comparison on every call, so tasks that talk to a
+/// particular peer often should look it up once and hang on to the result
+/// (or use `hl::send_with_retry` to track restarts).
+pub fn lookup_task(name: &str) -> Option<TaskId> {
+    let index = crate::TASK_NAMES.iter().position(|&n| n == name)?;
+    Some(refreshed_task_id(index))
+}
+
+/// Returns the `app.toml` name of the task with index `index`, or `None` if
+/// there is no such task.
+pub fn task_name(index: usize) -> Option<&'static str> {
+    crate::TASK_NAMES.get(index).copied()
+}
+
+/// Produces a `TaskId` for task `index` with the generation filled in by the
+/// kernel.
+fn refreshed_task_id(index: usize) -> TaskId {
+    let prototype = TaskId::for_index_and_gen(index, Generation::default());
+    crate::sys_refresh_task_id(prototype)
+}
+
 /// Description of a task slot in .task_slot_table ELF section.
 ///
 /// Most tasks will need to interact with other tasks by sending messages to
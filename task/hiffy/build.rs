@@ -2,6 +2,315 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() {
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Our own configuration, from the `[tasks.hiffy.config]` section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Config {
+    policy: Option<PolicyConfig>,
+    /// Tasks whose ring buffers may be dumped; see `common::ringbuf_dump`.
+    #[serde(default)]
+    ringbuf_dump: Vec<String>,
+}
+
+/// The policy governing the functions that HIF may call; see the `policy`
+/// module for its interpretation.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PolicyConfig {
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    constraints: Vec<ConstraintConfig>,
+}
+
+/// A constraint on a parameter, which must have either a list of `values`,
+/// or an inclusive `range`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConstraintConfig {
+    function: String,
+    parameter: u8,
+    values: Option<Vec<u32>>,
+    range: Option<[u32; 2]>,
+}
+
+fn main() -> Result<()> {
     build_util::expose_target_board();
+
+    let out = PathBuf::from(env::var("OUT_DIR")?);
+
+    //
+    // Our task configuration is optional; in its absence (or in a standalone
+    // build), no policy applies, and no ring buffers may be dumped.
+    //
+    let config = match env::var("HUBRIS_TASK_CONFIG") {
+        Ok(_) => build_util::task_config::<Config>()?,
+        Err(_) => Config::default(),
+    };
+
+    write_ringbuf_dump(&out, &config.ringbuf_dump)?;
+    write_self_index(&out)?;
+    write_policy(&out, config.policy, &functions()?)?;
+
+    Ok(())
+}
+
+///
+/// Resolves the names of the tasks whose ring buffers may be dumped to their
+/// indices.  Each must be a server that has opted in to ring buffer dumps,
+/// as sending to any other task may block us forever.
+///
+fn write_ringbuf_dump(out: &Path, names: &[String]) -> Result<()> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASKS");
+
+    let tasks = env::var("HUBRIS_TASKS").unwrap_or_default();
+    let tasks = tasks.split(',').collect::<Vec<_>>();
+    let mut indices = vec![];
+
+    for name in names {
+        if name == "hiffy" {
+            bail!("hiffy can't dump its own ring buffers");
+        }
+
+        match tasks.iter().position(|t| *t == name.as_str()) {
+            Some(ndx) if !indices.contains(&ndx) => indices.push(ndx),
+            Some(_) => bail!("ringbuf-dump names task {} twice", name),
+            None => bail!("ringbuf-dump names unknown task {}", name),
+        }
+    }
+
+    let mut file = File::create(out.join("ringbuf_dump.rs"))?;
+
+    writeln!(file, "/// Tasks whose ring buffers may be dumped.")?;
+    writeln!(file, "const RINGBUF_DUMP_TASKS: &[usize] = &{:?};", indices)?;
+
+    Ok(())
+}
+
+///
+/// Finds our own task index, to which `send` mustn't send: we would block
+/// forever.  This is unknown (and so unchecked) in a standalone build.
+///
+fn write_self_index(out: &Path) -> Result<()> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASKS");
+
+    let tasks = env::var("HUBRIS_TASKS").unwrap_or_default();
+    let ndx = tasks.split(',').position(|t| t == "hiffy");
+
+    let mut file = File::create(out.join("self_index.rs"))?;
+
+    writeln!(file, "/// Our own task index, if known.")?;
+    writeln!(file, "const HIFFY_TASK_INDEX: Option<usize> = {:?};", ndx)?;
+
+    Ok(())
+}
+
+/// A function that HIF can call, as given to `hiffy_funcs!`.
+struct Function {
+    name: String,
+    /// Number of parameters that can be constrained.
+    fixed: u8,
+}
+
+///
+/// Reads the functions that are compiled in from the invocation of
+/// `hiffy_funcs!` in the module that `main.rs` will pick, honouring any
+/// `#[cfg(feature = "...")]` on each.
+///
+fn functions() -> Result<Vec<Function>> {
+    let module = if env::var("CARGO_FEATURE_STM32H7").is_ok() {
+        "src/stm32h7.rs"
+    } else if env::var("CARGO_FEATURE_LPC55").is_ok() {
+        "src/lpc55.rs"
+    } else {
+        "src/generic.rs"
+    };
+
+    println!("cargo:rerun-if-changed={}", module);
+
+    let src = std::fs::read_to_string(module)?;
+
+    let body = match src.split("hiffy_funcs! {\n").nth(1) {
+        Some(rest) => rest.split("\n}").next().unwrap(),
+        None => bail!("{} has no invocation of hiffy_funcs!", module),
+    };
+
+    let mut functions = vec![];
+    let mut enabled = true;
+
+    for line in body.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if let Some(feature) = line
+            .strip_prefix("#[cfg(feature = \"")
+            .and_then(|l| l.strip_suffix("\")]"))
+        {
+            let var = feature.to_uppercase().replace('-', "_");
+            enabled = env::var(format!("CARGO_FEATURE_{}", var)).is_ok();
+            continue;
+        }
+
+        //
+        // Each entry is `Name(Fixed(n)) => path,` or `Name(Counted(n)) =>
+        // path,`.
+        //
+        let entry = line.split(" =>").next().unwrap();
+
+        let (name, fixed) = match entry
+            .strip_suffix("))")
+            .and_then(|e| e.split_once("("))
+            .and_then(|(name, arity)| Some((name, arity.split_once("(")?)))
+        {
+            Some((name, ("Fixed", n))) | Some((name, ("Counted", n))) => {
+                (name, n.parse::<u8>()?)
+            }
+            _ => {
+                bail!("can't parse hiffy_funcs! entry in {}: {}", module, line)
+            }
+        };
+
+        if enabled {
+            functions.push(Function {
+                name: name.to_string(),
+                fixed,
+            });
+        }
+
+        enabled = true;
+    }
+
+    Ok(functions)
+}
+
+fn write_policy(
+    out: &Path,
+    policy: Option<PolicyConfig>,
+    functions: &[Function],
+) -> Result<()> {
+    let policy = policy.unwrap_or(PolicyConfig {
+        allow: None,
+        constraints: vec![],
+    });
+
+    if let Some(allow) = &policy.allow {
+        for (i, name) in allow.iter().enumerate() {
+            if !functions.iter().any(|f| f.name == *name) {
+                bail!(
+                    "hiffy policy allows function {}, which isn't compiled in",
+                    name
+                );
+            }
+
+            if allow[..i].contains(name) {
+                bail!("hiffy policy allows function {} more than once", name);
+            }
+        }
+    }
+
+    //
+    // Each constraint becomes one or more ranges; a list of values is a
+    // range for each value.
+    //
+    let mut ranges = vec![];
+
+    for c in &policy.constraints {
+        let function = match functions.iter().find(|f| f.name == c.function) {
+            Some(function) => function,
+            None => bail!(
+                "hiffy policy constrains function {}, which isn't compiled in",
+                c.function
+            ),
+        };
+
+        if c.parameter >= function.fixed {
+            bail!(
+                "hiffy policy constrains parameter {} of {}, which has only \
+                {} parameters that can be constrained",
+                c.parameter,
+                c.function,
+                function.fixed
+            );
+        }
+
+        if let Some(allow) = &policy.allow {
+            if !allow.contains(&c.function) {
+                bail!(
+                    "hiffy policy constrains function {}, which it doesn't \
+                    allow",
+                    c.function
+                );
+            }
+        }
+
+        match (&c.values, &c.range) {
+            (Some(values), None) if !values.is_empty() => {
+                for &v in values {
+                    ranges.push((&c.function, c.parameter, v, v));
+                }
+            }
+            (None, Some([min, max])) if min <= max => {
+                ranges.push((&c.function, c.parameter, *min, *max));
+            }
+            (None, Some([min, max])) => {
+                bail!(
+                    "hiffy policy constrains parameter {} of {} to the \
+                    empty range [{}, {}]",
+                    c.parameter,
+                    c.function,
+                    min,
+                    max
+                );
+            }
+            _ => {
+                bail!(
+                    "hiffy policy constraint on parameter {} of {} must \
+                    have either a non-empty list of values or a range",
+                    c.parameter,
+                    c.function
+                );
+            }
+        }
+    }
+
+    let mut file = File::create(out.join("policy.rs"))?;
+
+    writeln!(file, "/// The policy, as configured in `app.toml`.")?;
+    writeln!(file, "const POLICY: Policy = Policy {{")?;
+
+    match &policy.allow {
+        Some(allow) => {
+            writeln!(file, "    allow: Some(&[")?;
+
+            for name in allow {
+                writeln!(file, "        {:?},", name)?;
+            }
+
+            writeln!(file, "    ]),")?;
+        }
+        None => writeln!(file, "    allow: None,")?,
+    }
+
+    writeln!(file, "    constraints: &[")?;
+
+    for (function, parameter, min, max) in ranges {
+        writeln!(file, "        Constraint {{")?;
+        writeln!(file, "            function: {:?},", function)?;
+        writeln!(file, "            parameter: {},", parameter)?;
+        writeln!(file, "            min: {:#x},", min)?;
+        writeln!(file, "            max: {:#x},", max)?;
+        writeln!(file, "        }},")?;
+    }
+
+    writeln!(file, "    ],")?;
+    writeln!(file, "}};")?;
+
+    Ok(())
 }
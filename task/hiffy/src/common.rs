@@ -49,6 +49,355 @@ pub(crate) fn sleep(
     Ok(0)
 }
 
+include!(concat!(env!("OUT_DIR"), "/ringbuf_dump.rs"));
+
+///
+/// Function to dump the ring buffers of a task, which takes a single
+/// parameter: the index of the task.  The dump is returned; see
+/// `userlib::ringbuf_registry` for its format, and `cargo xtask ringbuf` for
+/// its decoding.
+///
+/// As a task that never receives would block us forever, only those tasks
+/// named in the `ringbuf-dump` list in our configuration may be dumped:
+///
+/// ```toml
+/// [tasks.hiffy.config]
+/// ringbuf-dump = ["i2c_driver"]
+/// ```
+///
+/// Each such task must be a server that has opted in to ring buffer dumps
+/// (see `userlib::ringbuf_registry`).
+///
+pub(crate) fn ringbuf_dump(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    use userlib::{ringbuf_registry, sys_send, Generation, Lease, TaskId};
+
+    if stack.len() < 1 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 1;
+
+    let task = match stack[fp] {
+        Some(task) if RINGBUF_DUMP_TASKS.contains(&(task as usize)) => {
+            let prototype =
+                TaskId::for_index_and_gen(task as usize, Generation::default());
+
+            userlib::sys_refresh_task_id(prototype)
+        }
+        Some(_) => {
+            return Err(Failure::Fault(Fault::BadParameter(0)));
+        }
+        None => {
+            return Err(Failure::Fault(Fault::EmptyParameter(0)));
+        }
+    };
+
+    let mut response = [0u8; 4];
+
+    let (code, _) = sys_send(
+        task,
+        ringbuf_registry::DUMP_OP,
+        &[],
+        &mut response,
+        &[Lease::from(rval)],
+    );
+
+    if code != 0 {
+        return Err(Failure::FunctionError(code));
+    }
+
+    Ok(u32::from_le_bytes(response) as usize)
+}
+
+/// Size of the header that [`send`] and [`send_by_name`] place before the
+/// reply: the response code, and the length of the reply, as `u32`s.
+const SEND_HEADER_SIZE: usize = 8;
+
+include!(concat!(env!("OUT_DIR"), "/self_index.rs"));
+
+///
+/// Function to send an arbitrary message to a task, allowing any server to
+/// be reached from Humility without glue of its own here.  This takes eight
+/// parameters:
+///
+/// - the index of the task to send to;
+/// - the operation;
+/// - the offset and length of the message within the data;
+/// - the size of the buffer for the reply;
+/// - the offset and length within the data of a read-only lease;
+/// - the length of a writable lease.
+///
+/// A lease of zero length isn't passed; if both are passed, the read-only
+/// lease is lease 0 and the writable lease is lease 1.  As the data is
+/// read-only to functions, the writable lease lives in the return value:
+/// returned are the response code and the length of the reply (as
+/// little-endian `u32`s), the reply buffer and then the writable lease.
+/// The response code is returned rather than being treated as an error, as
+/// the meaning of a response code is up to the server.
+///
+/// We refuse to send to ourselves, which would block us forever.  We can't
+/// check the other rule that a send must follow: that it go uphill, to a
+/// task of higher priority (numerically lower) than ours.  Sending to a task
+/// of lower priority than hiffy is a priority inversion that will fault
+/// hiffy, so it is up to the caller to avoid it.
+///
+pub(crate) fn send(
+    stack: &[Option<u32>],
+    data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    use userlib::{sys_refresh_task_id, Generation, TaskId, NUM_TASKS};
+
+    if stack.len() < 8 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 8;
+
+    let task = match stack[fp] {
+        Some(task) if task < NUM_TASKS as u32 => {
+            let prototype =
+                TaskId::for_index_and_gen(task as usize, Generation::default());
+
+            sys_refresh_task_id(prototype)
+        }
+        Some(_) => {
+            return Err(Failure::Fault(Fault::BadParameter(0)));
+        }
+        None => {
+            return Err(Failure::Fault(Fault::EmptyParameter(0)));
+        }
+    };
+
+    send_to(task, &stack[fp + 1..], 1, data, rval)
+}
+
+///
+/// Function to send an arbitrary message to a task named by its `app.toml`
+/// name, rather than its index.  This takes nine parameters: the offset and
+/// length of the name within the data, followed by the parameters to
+/// [`send`] that follow the task index.  The return value is as for
+/// [`send`].
+///
+pub(crate) fn send_by_name(
+    stack: &[Option<u32>],
+    data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    if stack.len() < 9 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 9;
+    let name = data_slice(&stack[fp..fp + 2], 0, data)?;
+
+    let name = core::str::from_utf8(name)
+        .map_err(|_| Failure::Fault(Fault::BadParameter(0)))?;
+
+    let task = userlib::task_slot::lookup_task(name)
+        .ok_or(Failure::Fault(Fault::BadParameter(0)))?;
+
+    send_to(task, &stack[fp + 2..], 2, data, rval)
+}
+
+/// Returns the parameter at `frame[ndx]`, which is parameter `base + ndx`.
+fn send_param(
+    frame: &[Option<u32>],
+    base: usize,
+    ndx: usize,
+) -> Result<usize, Failure> {
+    match frame[ndx] {
+        Some(val) => Ok(val as usize),
+        None => Err(Failure::Fault(Fault::EmptyParameter((base + ndx) as u8))),
+    }
+}
+
+/// Returns the slice of `data` given by the offset and length in `frame`,
+/// which are parameters `base` and `base + 1`.
+fn data_slice<'a>(
+    frame: &[Option<u32>],
+    base: usize,
+    data: &'a [u8],
+) -> Result<&'a [u8], Failure> {
+    let offset = send_param(frame, base, 0)?;
+    let len = send_param(frame, base, 1)?;
+
+    match offset.checked_add(len) {
+        Some(end) if end <= data.len() => Ok(&data[offset..end]),
+        _ => Err(Failure::Fault(Fault::AccessOutOfBounds)),
+    }
+}
+
+/// Sends to `task`, as described by the seven parameters to [`send`] that
+/// follow the task index, which are in `frame` starting at parameter
+/// `base`.
+fn send_to(
+    task: userlib::TaskId,
+    frame: &[Option<u32>],
+    base: usize,
+    data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    use userlib::{sys_send, Lease};
+
+    //
+    // The task is always named by parameter 0, be it by index or by name.
+    //
+    if Some(task.index()) == HIFFY_TASK_INDEX {
+        return Err(Failure::Fault(Fault::BadParameter(0)));
+    }
+
+    let op = send_param(frame, base, 0)?;
+
+    if op > u16::MAX as usize {
+        return Err(Failure::Fault(Fault::BadParameter(base as u8)));
+    }
+
+    let message = data_slice(&frame[1..3], base + 1, data)?;
+    let reply_len = send_param(frame, base, 3)?;
+    let read = data_slice(&frame[4..6], base + 4, data)?;
+    let write_len = send_param(frame, base, 6)?;
+
+    let total = reply_len
+        .checked_add(write_len)
+        .and_then(|len| len.checked_add(SEND_HEADER_SIZE));
+
+    let total = match total {
+        Some(total) if total <= rval.len() => total,
+        _ => return Err(Failure::Fault(Fault::ReturnValueOverflow)),
+    };
+
+    let (header, rest) = rval[..total].split_at_mut(SEND_HEADER_SIZE);
+    let (reply, write) = rest.split_at_mut(reply_len);
+
+    let (code, rlen) = match (read.len(), write.len()) {
+        (0, 0) => sys_send(task, op as u16, message, reply, &[]),
+        (0, _) => {
+            sys_send(task, op as u16, message, reply, &[Lease::from(write)])
+        }
+        (_, 0) => {
+            sys_send(task, op as u16, message, reply, &[Lease::from(read)])
+        }
+        (_, _) => sys_send(
+            task,
+            op as u16,
+            message,
+            reply,
+            &[Lease::from(read), Lease::from(write)],
+        ),
+    };
+
+    header[..4].copy_from_slice(&code.to_le_bytes());
+    header[4..].copy_from_slice(&(rlen as u32).to_le_bytes());
+
+    Ok(total)
+}
+
+///
+/// Parses the first six parameters common to the I2C functions: the
+/// controller, port, mux, segment, address and register.  The mux and
+/// segment are optional (but must be specified together), as is the
+/// register.
+///
+#[cfg(feature = "i2c")]
+pub(crate) fn i2c_args(
+    stack: &[Option<u32>],
+) -> Result<
+    (
+        drv_i2c_api::Controller,
+        drv_i2c_api::PortIndex,
+        Option<(drv_i2c_api::Mux, drv_i2c_api::Segment)>,
+        u8,
+        Option<u8>,
+    ),
+    Failure,
+> {
+    let (controller, port, mux) = i2c_bus_args(stack)?;
+
+    let addr = match stack[4] {
+        Some(addr) => addr as u8,
+        None => return Err(Failure::Fault(Fault::EmptyParameter(4))),
+    };
+
+    let register = match stack[5] {
+        Some(register) => Some(register as u8),
+        None => None,
+    };
+
+    Ok((controller, port, mux, addr, register))
+}
+
+///
+/// Parses the first four parameters common to the I2C functions, which
+/// identify a bus: the controller, port, mux and segment.  The mux and
+/// segment are optional, but must be specified together.
+///
+#[cfg(feature = "i2c")]
+pub(crate) fn i2c_bus_args(
+    stack: &[Option<u32>],
+) -> Result<
+    (
+        drv_i2c_api::Controller,
+        drv_i2c_api::PortIndex,
+        Option<(drv_i2c_api::Mux, drv_i2c_api::Segment)>,
+    ),
+    Failure,
+> {
+    use drv_i2c_api::{Controller, Mux, PortIndex, Segment};
+    use userlib::FromPrimitive;
+
+    let controller = match stack[0] {
+        Some(controller) => match Controller::from_u32(controller) {
+            Some(controller) => controller,
+            None => return Err(Failure::Fault(Fault::BadParameter(0))),
+        },
+        None => return Err(Failure::Fault(Fault::EmptyParameter(0))),
+    };
+
+    let port = match stack[1] {
+        Some(port) => {
+            if port > core::u8::MAX.into() {
+                return Err(Failure::Fault(Fault::BadParameter(1)));
+            }
+
+            PortIndex(port as u8)
+        }
+        None => {
+            //
+            // While we once upon a time allowed HIF consumers to specify
+            // a default port, we now expect all HIF consumers to read the
+            // device configuration and correctly specify a port index:
+            // this is an error.
+            //
+            return Err(Failure::Fault(Fault::EmptyParameter(1)));
+        }
+    };
+
+    let mux = match (stack[2], stack[3]) {
+        (Some(mux), Some(segment)) => Some((
+            match Mux::from_u32(mux) {
+                Some(mux) => mux,
+                None => {
+                    return Err(Failure::Fault(Fault::BadParameter(2)));
+                }
+            },
+            match Segment::from_u32(segment) {
+                Some(segment) => segment,
+                None => {
+                    return Err(Failure::Fault(Fault::BadParameter(3)));
+                }
+            },
+        )),
+        _ => None,
+    };
+
+    Ok((controller, port, mux))
+}
+
 #[cfg(feature = "spi")]
 fn spi_args(stack: &[Option<u32>]) -> Result<(TaskId, usize), Failure> {
     if stack.len() < 2 {
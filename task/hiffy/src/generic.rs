@@ -2,16 +2,28 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use hif::Function;
-
 pub enum Functions {
     Sleep(u16, u32),
+    RingbufDump(u32, userlib::ringbuf_registry::DumpError),
+    Send(
+        (userlib::Task, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
+    SendByName(
+        (usize, usize, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
 }
 
 #[no_mangle]
 static HIFFY_FUNCTIONS: Option<&Functions> = None;
 
-pub(crate) static HIFFY_FUNCS: &[Function] = &[crate::common::sleep];
+hiffy_funcs! {
+    Sleep(Fixed(1)) => crate::common::sleep,
+    RingbufDump(Fixed(1)) => crate::common::ringbuf_dump,
+    Send(Fixed(8)) => crate::common::send,
+    SendByName(Fixed(9)) => crate::common::send_by_name,
+}
 
 pub(crate) fn trace_execute(_offset: usize, _op: hif::Op) {}
 
@@ -2,6 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "i2c")]
+use crate::common::i2c_args;
 #[cfg(feature = "spi")]
 use crate::common::{spi_read, spi_write};
 use byteorder::ByteOrder;
@@ -10,9 +12,21 @@ use hif::*;
 use ringbuf::*;
 use userlib::*;
 
+#[cfg(feature = "i2c")]
+use drv_i2c_api::{Controller, Mux, PortIndex, ResponseCode, Segment};
+
+#[cfg(feature = "flash")]
+use hypocalls::FlashStatus;
+
 #[cfg(feature = "gpio")]
 task_slot!(GPIO, gpio_driver);
 
+#[cfg(feature = "i2c")]
+task_slot!(I2C, i2c_driver);
+
+#[cfg(feature = "rng")]
+task_slot!(RNG, rng_driver);
+
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
     Execute((usize, hif::Op)),
@@ -28,6 +42,15 @@ ringbuf!(Trace, 64, Trace::None);
  */
 pub enum Functions {
     Sleep(u16, u32),
+    RingbufDump(u32, userlib::ringbuf_registry::DumpError),
+    Send(
+        (userlib::Task, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
+    SendByName(
+        (usize, usize, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
     #[cfg(feature = "gpio")]
     GpioInput(drv_lpc55_gpio_api::Pin, drv_lpc55_gpio_api::GpioError),
     #[cfg(feature = "gpio")]
@@ -58,6 +81,386 @@ pub enum Functions {
     SpiRead((Task, usize, usize), drv_spi_api::SpiError),
     #[cfg(feature = "spi")]
     SpiWrite((Task, usize), drv_spi_api::SpiError),
+    #[cfg(feature = "i2c")]
+    I2cRead(
+        (Controller, PortIndex, Mux, Segment, u8, u8, usize),
+        ResponseCode,
+    ),
+    #[cfg(feature = "i2c")]
+    I2cWrite(
+        (Controller, PortIndex, Mux, Segment, u8, u8, Buffer, usize),
+        ResponseCode,
+    ),
+    #[cfg(feature = "i2c")]
+    I2cBulkWrite(
+        (Controller, PortIndex, Mux, Segment, u8, u8, usize, usize),
+        ResponseCode,
+    ),
+    #[cfg(feature = "rng")]
+    RngFill(usize, u32),
+    #[cfg(feature = "flash")]
+    FlashReadCmpa((u32, usize), FlashStatus),
+    #[cfg(feature = "flash")]
+    FlashReadCfpa((u32, usize), FlashStatus),
+    #[cfg(feature = "flash")]
+    FlashReadImageHeader((), FlashStatus),
+}
+
+#[cfg(feature = "i2c")]
+pub struct Buffer(u8);
+
+///
+/// The LPC55 I2C driver drives a single controller (I2C4), and knows nothing
+/// of ports or muxes.  We take the same parameters as the STM32H7 I2C
+/// functions nonetheless, and fail an attempt to use any other controller,
+/// port or mux with the error that the I2C server would give for it.
+///
+#[cfg(feature = "i2c")]
+fn i2c_lpc55_args(stack: &[Option<u32>]) -> Result<(u8, Option<u8>), Failure> {
+    let (controller, port, mux, addr, register) = i2c_args(stack)?;
+
+    let err = if controller != Controller::I2C4 {
+        ResponseCode::BadController
+    } else if port != PortIndex(0) {
+        ResponseCode::BadPort
+    } else if mux.is_some() {
+        ResponseCode::MuxNotFound
+    } else {
+        return Ok((addr, register));
+    };
+
+    Err(Failure::FunctionError(err.into()))
+}
+
+///
+/// Performs a single transfer with the LPC55 I2C driver: a write (if the
+/// lease is read-only) or a read (if it is writable) of the device at
+/// `addr`.  The driver's response codes are translated to those of the I2C
+/// API, which is how Humility will interpret them.
+///
+#[cfg(feature = "i2c")]
+fn i2c_transfer(op: u16, addr: u8, lease: Lease<'_>) -> Result<(), Failure> {
+    let (code, _) = sys_send(I2C.get_task_id(), op, &[addr], &mut [], &[lease]);
+
+    let err = match code {
+        0 => return Ok(()),
+        2 => ResponseCode::BadArg,
+        // The device didn't acknowledge its address...
+        4 => ResponseCode::NoDevice,
+        // ...or a byte that we wrote.  (The driver's code 3 covers every
+        // other failure, for which we have nothing better than the default.)
+        5 => ResponseCode::NoRegister,
+        code if extract_new_generation(code).is_some() => ResponseCode::Dead,
+        _ => ResponseCode::BadResponse,
+    };
+
+    Err(Failure::FunctionError(err.into()))
+}
+
+#[cfg(feature = "i2c")]
+const I2C_WRITE: u16 = 1;
+
+#[cfg(feature = "i2c")]
+const I2C_READ: u16 = 2;
+
+#[cfg(feature = "i2c")]
+fn i2c_read(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    if stack.len() < 7 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 7;
+    let (addr, register) = i2c_lpc55_args(&stack[fp..])?;
+
+    //
+    // The LPC55 I2C driver can't perform an SMBus block read (which is what
+    // the STM32H7 version does when no length is specified), so we require
+    // a length.
+    //
+    let n = match stack[fp + 6] {
+        Some(nbytes) => nbytes as usize,
+        None => return Err(Failure::Fault(Fault::EmptyParameter(6))),
+    };
+
+    if rval.len() < n {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    if let Some(reg) = register {
+        i2c_transfer(I2C_WRITE, addr, Lease::from(&[reg][..]))?;
+    }
+
+    i2c_transfer(I2C_READ, addr, Lease::from(&mut rval[0..n]))?;
+    Ok(n)
+}
+
+#[cfg(feature = "i2c")]
+fn i2c_write(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    _rval: &mut [u8],
+) -> Result<usize, Failure> {
+    let mut buf = [0u8; 17];
+
+    //
+    // As with the STM32H7, we need at least 8 parameters, the last of which
+    // is the number of bytes to write.
+    //
+    if stack.len() < 8 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let len = match stack[stack.len() - 1] {
+        Some(len) if len > 0 && len as usize <= buf.len() - 1 => {
+            Ok(len as usize)
+        }
+        _ => Err(Failure::Fault(Fault::BadParameter(7))),
+    }?;
+
+    if stack.len() < 7 + len {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - (7 + len);
+    let (addr, register) = i2c_lpc55_args(&stack[fp..])?;
+
+    let mut offs = 0;
+
+    if let Some(register) = register {
+        buf[offs] = register;
+        offs += 1;
+    }
+
+    let bp = stack.len() - (1 + len);
+
+    for i in 0..len {
+        buf[i + offs] = match stack[bp + i] {
+            None => {
+                return Err(Failure::Fault(Fault::BadParameter(7)));
+            }
+            Some(val) => val as u8,
+        }
+    }
+
+    i2c_transfer(I2C_WRITE, addr, Lease::from(&buf[0..len + offs]))?;
+    Ok(0)
+}
+
+#[cfg(feature = "i2c")]
+fn i2c_bulk_write(
+    stack: &[Option<u32>],
+    data: &[u8],
+    _rval: &mut [u8],
+) -> Result<usize, Failure> {
+    //
+    // We need exactly 8 parameters: the normal i2c paramaters (controller,
+    // port, mux, segment, address, register) plus the offset and length.
+    // Note that the register must be None.
+    //
+    if stack.len() != 8 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let offset = match stack[stack.len() - 2] {
+        Some(offset) if (offset as usize) < data.len() => Ok(offset as usize),
+        _ => Err(Failure::Fault(Fault::BadParameter(6))),
+    }?;
+
+    let len = match stack[stack.len() - 1] {
+        Some(len) if len > 0 && offset + (len as usize) < data.len() => {
+            Ok(len as usize)
+        }
+        _ => Err(Failure::Fault(Fault::BadParameter(7))),
+    }?;
+
+    let fp = stack.len() - 8;
+    let (addr, register) = i2c_lpc55_args(&stack[fp..])?;
+
+    if register.is_some() {
+        return Err(Failure::Fault(Fault::BadParameter(5)));
+    }
+
+    i2c_transfer(I2C_WRITE, addr, Lease::from(&data[offset..offset + len]))?;
+    Ok(0)
+}
+
+///
+/// Function to fill the return value with random bytes from the RNG, which
+/// takes a single parameter: the number of bytes.
+///
+#[cfg(feature = "rng")]
+fn rng_fill(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    if stack.len() < 1 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 1;
+
+    let n = match stack[fp] {
+        Some(n) => n as usize,
+        None => return Err(Failure::Fault(Fault::EmptyParameter(0))),
+    };
+
+    if rval.len() < n {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    let task = RNG.get_task_id();
+
+    for chunk in rval[..n].chunks_mut(4) {
+        let mut number = [0u8; 4];
+
+        // The RNG driver has but one operation, which is zero.
+        let (code, _) = sys_send(task, 0, &[], &mut number, &[]);
+
+        if code != 0 {
+            return Err(Failure::FunctionError(code));
+        }
+
+        chunk.copy_from_slice(&number[..chunk.len()]);
+    }
+
+    Ok(n)
+}
+
+///
+/// Parses the offset and length for a read of the CMPA or CFPA, both of
+/// which must be multiples of 4.
+///
+#[cfg(feature = "flash")]
+fn flash_pfr_args(
+    stack: &[Option<u32>],
+    rval: &mut [u8],
+) -> Result<(u32, usize), Failure> {
+    if stack.len() < 2 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 2;
+
+    let offset = match stack[fp] {
+        Some(offset) if offset % 4 == 0 => offset,
+        Some(_) => return Err(Failure::Fault(Fault::BadParameter(0))),
+        None => return Err(Failure::Fault(Fault::EmptyParameter(0))),
+    };
+
+    let len = match stack[fp + 1] {
+        Some(len) if len > 0 && len % 4 == 0 => len as usize,
+        Some(_) => return Err(Failure::Fault(Fault::BadParameter(1))),
+        None => return Err(Failure::Fault(Fault::EmptyParameter(1))),
+    };
+
+    if rval.len() < len {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    Ok((offset, len))
+}
+
+///
+/// Reads from a page of the protected flash region via `read`, in words,
+/// into the return value.
+///
+#[cfg(feature = "flash")]
+fn flash_read_pfr(
+    stack: &[Option<u32>],
+    rval: &mut [u8],
+    read: fn(u32, &mut [u32]) -> FlashStatus,
+) -> Result<usize, Failure> {
+    let (offset, len) = flash_pfr_args(stack, rval)?;
+    let mut words = [0u32; 16];
+    let mut pos = 0;
+
+    while pos < len {
+        let n = core::cmp::min(len - pos, words.len() * 4);
+        let words = &mut words[..n / 4];
+
+        match read(offset + pos as u32, words) {
+            FlashStatus::Success => {}
+            err => return Err(Failure::FunctionError(err as u32)),
+        }
+
+        for word in words.iter() {
+            byteorder::LittleEndian::write_u32(&mut rval[pos..], *word);
+            pos += 4;
+        }
+    }
+
+    Ok(len)
+}
+
+///
+/// Function to read from the CMPA (the customer manufacturing configuration
+/// page), which takes two parameters: the offset and the length.
+///
+#[cfg(feature = "flash")]
+fn flash_read_cmpa(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    flash_read_pfr(stack, rval, hypocalls::hypo_read_cmpa)
+}
+
+///
+/// Function to read from the active CFPA (the customer field programmable
+/// page), which takes two parameters: the offset and the length.
+///
+#[cfg(feature = "flash")]
+fn flash_read_cfpa(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    flash_read_pfr(stack, rval, hypocalls::hypo_read_cfpa)
+}
+
+///
+/// Function to read the header of the image that was booted, which is
+/// returned as it is in flash.
+///
+#[cfg(feature = "flash")]
+fn flash_read_image_header(
+    _stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    let header = match hypocalls::hypo_get_image_header() {
+        Ok(header) => header,
+        Err(err) => return Err(Failure::FunctionError(err as u32)),
+    };
+
+    let words = [
+        header.sp,
+        header.pc,
+        header.vector_table[0],
+        header.vector_table[1],
+        header.vector_table[2],
+        header.vector_table[3],
+        header.vector_table[4],
+        header.vector_table[5],
+        header.image_length,
+        header.image_type,
+        header.header_offset,
+    ];
+
+    if rval.len() < words.len() * 4 {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    for (i, word) in words.iter().enumerate() {
+        byteorder::LittleEndian::write_u32(&mut rval[i * 4..], *word);
+    }
+
+    Ok(words.len() * 4)
 }
 
 #[cfg(feature = "gpio")]
@@ -254,25 +657,42 @@ fn gpio_reset(
     }
 }
 
-pub(crate) static HIFFY_FUNCS: &[Function] = &[
-    crate::common::sleep,
+hiffy_funcs! {
+    Sleep(Fixed(1)) => crate::common::sleep,
+    RingbufDump(Fixed(1)) => crate::common::ringbuf_dump,
+    Send(Fixed(8)) => crate::common::send,
+    SendByName(Fixed(9)) => crate::common::send_by_name,
     #[cfg(feature = "gpio")]
-    gpio_input,
+    GpioInput(Fixed(1)) => gpio_input,
     #[cfg(feature = "gpio")]
-    gpio_toggle,
+    GpioToggle(Fixed(1)) => gpio_toggle,
     #[cfg(feature = "gpio")]
-    gpio_set,
+    GpioSet(Fixed(1)) => gpio_set,
     #[cfg(feature = "gpio")]
-    gpio_reset,
+    GpioReset(Fixed(1)) => gpio_reset,
     #[cfg(feature = "gpio")]
-    gpio_configure,
+    GpioConfigure(Fixed(7)) => gpio_configure,
     #[cfg(feature = "gpio")]
-    gpio_direction,
+    GpioDirection(Fixed(2)) => gpio_direction,
     #[cfg(feature = "spi")]
-    spi_read,
+    SpiRead(Fixed(3)) => spi_read,
     #[cfg(feature = "spi")]
-    spi_write,
-];
+    SpiWrite(Fixed(2)) => spi_write,
+    #[cfg(feature = "i2c")]
+    I2cRead(Fixed(7)) => i2c_read,
+    #[cfg(feature = "i2c")]
+    I2cWrite(Counted(6)) => i2c_write,
+    #[cfg(feature = "i2c")]
+    I2cBulkWrite(Fixed(8)) => i2c_bulk_write,
+    #[cfg(feature = "rng")]
+    RngFill(Fixed(1)) => rng_fill,
+    #[cfg(feature = "flash")]
+    FlashReadCmpa(Fixed(2)) => flash_read_cmpa,
+    #[cfg(feature = "flash")]
+    FlashReadCfpa(Fixed(2)) => flash_read_cfpa,
+    #[cfg(feature = "flash")]
+    FlashReadImageHeader(Fixed(0)) => flash_read_image_header,
+}
 
 //
 // This definition forces the compiler to emit the DWARF needed for debuggers
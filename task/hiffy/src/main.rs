@@ -11,6 +11,12 @@
 //! debugger places HIF in [`HIFFY_TEXT`], and then indicates that text is
 //! present by incrementing [`HIFFY_KICK`].  This task executes the specified
 //! HIF, with the return stack located in [`HIFFY_RSTACK`].
+//!
+//! Which functions HIF may call, and with what arguments, can be restricted
+//! by a policy in `app.toml`; see the `policy` module.
+//!
+//! With the `serial` feature, HIF can also be delivered over a UART, for
+//! systems without a debugger attached; see the `serial` module.
 
 #![no_std]
 #![no_main]
@@ -19,7 +25,45 @@ use core::sync::atomic::{AtomicU32, Ordering};
 use hif::*;
 use userlib::*;
 
+///
+/// Defines the functions that HIF can call: [`HIFFY_FUNCS`], in the order of
+/// the variants of `Functions`; [`HIFFY_FUNC_INFO`], giving the name (which
+/// must be that of the variant) and [`policy::Arity`] of each; and
+/// `GUARDED_FUNCS`, in which each function is preceded by a check against
+/// the policy.  The build script reads the invocation of this macro to check
+/// the policy against the functions, so each entry must remain on a line of
+/// its own, preceded only by any `#[cfg(feature = "...")]` that governs it.
+///
+macro_rules! hiffy_funcs {
+    ($($(#[$attr:meta])* $name:ident($arity:expr) => $func:path,)*) => {
+        pub(crate) static HIFFY_FUNCS: &[hif::Function] = &[
+            $($(#[$attr])* $func,)*
+        ];
+
+        pub(crate) static HIFFY_FUNC_INFO: &[crate::policy::FunctionInfo] = &[
+            $($(#[$attr])* crate::policy::FunctionInfo {
+                name: stringify!($name),
+                arity: {
+                    use crate::policy::Arity::*;
+                    $arity
+                },
+            },)*
+        ];
+
+        pub(crate) static GUARDED_FUNCS: &[hif::Function] = &[
+            $($(#[$attr])* |stack, data, rval| {
+                crate::policy::check(stringify!($name), stack)?;
+                $func(stack, data, rval)
+            },)*
+        ];
+    };
+}
+
 mod common;
+mod policy;
+
+#[cfg(feature = "serial")]
+mod serial;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "stm32h7")] {
@@ -47,6 +91,17 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "serial")] {
+        /// Longest that we will sleep between checks for work.  With the
+        /// serial transport, we have to check often enough that bytes don't
+        /// overflow the UART driver's buffer in the meantime.
+        const MAX_SLEEP_MS: u64 = 10;
+    } else {
+        const MAX_SLEEP_MS: u64 = 250;
+    }
+}
+
 ///
 /// These HIFFY_* global variables constitute the interface with Humility;
 /// they should not be altered without modifying Humility as well.
@@ -82,11 +137,8 @@ static HIFFY_VERSION_PATCH: AtomicU32 = AtomicU32::new(HIF_VERSION_PATCH);
 
 #[export_name = "main"]
 fn main() -> ! {
-    let mut sleep_ms = 250;
+    let mut sleep_ms = MAX_SLEEP_MS;
     let mut sleeps = 0;
-    let mut stack = [None; 32];
-    let mut scratch = [0u8; 256];
-    const NLABELS: usize = 4;
 
     //
     // Sadly, there seems to be no other way to force these variables to
@@ -101,12 +153,24 @@ fn main() -> ! {
         hl::sleep_for(sleep_ms);
         HIFFY_READY.fetch_sub(1, Ordering::SeqCst);
 
+        //
+        // Activity on the serial line gets the same quick attention as a
+        // kick from the debugger.
+        //
+        #[cfg(feature = "serial")]
+        if serial::poll() {
+            sleep_ms = 1;
+            sleeps = 0;
+            continue;
+        }
+
         if HIFFY_KICK.load(Ordering::SeqCst) == 0 {
             sleeps += 1;
 
-            // Exponentially backoff our sleep value, but no more than 250ms
+            // Exponentially backoff our sleep value, but no more than
+            // MAX_SLEEP_MS
             if sleeps == 10 {
-                sleep_ms = core::cmp::min(sleep_ms * 10, 250);
+                sleep_ms = core::cmp::min(sleep_ms * 10, MAX_SLEEP_MS);
                 sleeps = 0;
             }
 
@@ -121,38 +185,50 @@ fn main() -> ! {
         sleep_ms = 1;
         sleeps = 0;
 
-        let text = unsafe { &HIFFY_TEXT };
-        let data = unsafe { &HIFFY_DATA };
-        let mut rstack = unsafe { &mut HIFFY_RSTACK[0..] };
-
-        let check = |offset: usize, op: &Op| -> Result<(), Failure> {
-            trace_execute(offset, *op);
-            Ok(())
-        };
-
-        let rv = execute::<_, NLABELS>(
-            text,
-            HIFFY_FUNCS,
-            data,
-            &mut stack,
-            &mut rstack,
-            &mut scratch,
-            check,
-        );
-
-        match rv {
-            Ok(_) => {
-                HIFFY_REQUESTS.fetch_add(1, Ordering::SeqCst);
-                trace_success();
-            }
-            Err(failure) => {
-                HIFFY_ERRORS.fetch_add(1, Ordering::SeqCst);
-                unsafe {
-                    HIFFY_FAILURE = Some(failure);
-                }
+        run();
+    }
+}
 
-                trace_failure(failure);
+///
+/// Executes the HIF in [`HIFFY_TEXT`], recording the outcome in
+/// [`HIFFY_REQUESTS`], [`HIFFY_ERRORS`] and [`HIFFY_FAILURE`].
+///
+fn run() {
+    let mut stack = [None; 32];
+    let mut scratch = [0u8; 256];
+    const NLABELS: usize = 4;
+
+    let text = unsafe { &HIFFY_TEXT };
+    let data = unsafe { &HIFFY_DATA };
+    let mut rstack = unsafe { &mut HIFFY_RSTACK[0..] };
+
+    let check = |offset: usize, op: &Op| -> Result<(), Failure> {
+        trace_execute(offset, *op);
+        Ok(())
+    };
+
+    let rv = execute::<_, NLABELS>(
+        text,
+        policy::funcs(),
+        data,
+        &mut stack,
+        &mut rstack,
+        &mut scratch,
+        check,
+    );
+
+    match rv {
+        Ok(_) => {
+            HIFFY_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            trace_success();
+        }
+        Err(failure) => {
+            HIFFY_ERRORS.fetch_add(1, Ordering::SeqCst);
+            unsafe {
+                HIFFY_FAILURE = Some(failure);
             }
+
+            trace_failure(failure);
         }
     }
 }
@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Policy governing the use of HIF functions
+//!
+//! Which functions are compiled in is determined by the features with which
+//! hiffy is built.  Beyond that, the `[tasks.hiffy.config.policy]` section
+//! of `app.toml` can restrict which of those functions may be called, and
+//! the values that their parameters may take:
+//!
+//! ```toml
+//! [tasks.hiffy.config.policy]
+//! allow = ["Sleep", "I2cRead"]
+//!
+//! [[tasks.hiffy.config.policy.constraints]]
+//! function = "I2cRead"
+//! parameter = 0           # the controller
+//! values = [2, 3]
+//! ```
+//!
+//! Functions are named as in the `Functions` enum, and parameters are
+//! numbered from zero, as they are in it.  A parameter may be constrained to
+//! a list of `values`, or to an inclusive `range`; where a parameter has
+//! more than one constraint, it need satisfy only one of them.  In the
+//! absence of an `allow` list, every function that is compiled in may be
+//! called.  A policy that names a function that isn't compiled in, or a
+//! parameter that a function doesn't have, fails the build.
+//!
+//! Note that allowing `Send` or `SendByName` bypasses every other
+//! constraint: they can send any message to any task, including the servers
+//! behind the functions that the policy restricts.  A policy meant to
+//! confine what HIF can do should leave them off its `allow` list.
+//!
+//! A call that the policy refuses fails with
+//! `Failure::FunctionError(POLICY_DENIED)`, a code that no function returns
+//! (see [`POLICY_DENIED`]), and the reason is recorded in [`HIFFY_DENIAL`].
+//! The policy itself is exported as [`HIFFY_POLICY`], so that a debugger can
+//! explain refusals.
+
+use crate::*;
+
+/// A constraint on the values of a parameter of a function.
+#[derive(Copy, Clone, Debug)]
+pub struct Constraint {
+    pub function: &'static str,
+    pub parameter: u8,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Constraint {
+    fn permits(&self, value: Option<u32>) -> bool {
+        match value {
+            Some(value) => value >= self.min && value <= self.max,
+            None => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Policy {
+    /// The functions that may be called; if `None`, any function that is
+    /// compiled in may be.
+    pub allow: Option<&'static [&'static str]>,
+    pub constraints: &'static [Constraint],
+}
+
+impl Policy {
+    fn is_empty(&self) -> bool {
+        self.allow.is_none() && self.constraints.is_empty()
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/policy.rs"));
+
+/// How a function finds its parameters on the stack.
+#[derive(Copy, Clone, Debug)]
+pub enum Arity {
+    /// The function takes this many parameters.
+    Fixed(usize),
+    /// The function takes this many parameters, followed by a variable
+    /// number of values, followed by the number of values.
+    Counted(usize),
+}
+
+impl Arity {
+    /// Returns the parameters on `stack`, if they're all there.
+    fn frame(self, stack: &[Option<u32>]) -> Option<&[Option<u32>]> {
+        let n = match self {
+            Arity::Fixed(n) => n,
+            Arity::Counted(n) => {
+                let count = (*stack.last()?)? as usize;
+                n.checked_add(count)?.checked_add(1)?
+            }
+        };
+
+        Some(&stack[stack.len().checked_sub(n)?..])
+    }
+}
+
+/// What the policy needs to know of each function.
+pub struct FunctionInfo {
+    pub name: &'static str,
+    pub arity: Arity,
+}
+
+/// Function error for a call refused by the policy.  This is reserved: it
+/// lies just below the codes with which the kernel reports a dead task
+/// (`FIRST_DEAD_CODE` and up), which functions may pass through, and
+/// far above any code that a server returns.
+pub const POLICY_DENIED: u32 = userlib::FIRST_DEAD_CODE - 0x100;
+
+/// Why a call was refused.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Denial {
+    /// The function with this index isn't on the allow-list.
+    Function(u8),
+    /// The parameter (second) of the function (first) has a value that the
+    /// constraints on it don't permit.
+    Parameter(u8, u8),
+}
+
+///
+/// These HIFFY_* variables extend the interface with Humility:
+///
+/// - [`HIFFY_POLICY`]     => The policy in effect
+/// - [`HIFFY_DENIAL`]     => Most recent refusal by the policy, if any
+///
+#[used]
+static HIFFY_POLICY: Policy = POLICY;
+
+#[used]
+static mut HIFFY_DENIAL: Option<Denial> = None;
+
+fn deny(denial: Denial) -> Result<(), Failure> {
+    unsafe {
+        HIFFY_DENIAL = Some(denial);
+    }
+
+    Err(Failure::FunctionError(POLICY_DENIED))
+}
+
+/// Checks a call to the function named `name` against the policy.
+pub(crate) fn check(name: &str, stack: &[Option<u32>]) -> Result<(), Failure> {
+    //
+    // The build script has checked that the policy names only functions
+    // that are compiled in, and only parameters that they have.
+    //
+    let ndx = HIFFY_FUNC_INFO.iter().position(|f| f.name == name).unwrap();
+    let info = &HIFFY_FUNC_INFO[ndx];
+
+    if let Some(allow) = POLICY.allow {
+        if !allow.contains(&info.name) {
+            return deny(Denial::Function(ndx as u8));
+        }
+    }
+
+    //
+    // If the parameters aren't all there, we leave it to the function to
+    // complain.
+    //
+    let frame = match info.arity.frame(stack) {
+        Some(frame) => frame,
+        None => return Ok(()),
+    };
+
+    let constraints = POLICY
+        .constraints
+        .iter()
+        .filter(|c| c.function == info.name);
+
+    for c in constraints.clone() {
+        let value = frame[c.parameter as usize];
+
+        if !constraints
+            .clone()
+            .any(|d| d.parameter == c.parameter && d.permits(value))
+        {
+            return deny(Denial::Parameter(ndx as u8, c.parameter));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the table of functions to execute, subject to the policy.
+pub(crate) fn funcs() -> &'static [Function] {
+    if POLICY.is_empty() {
+        HIFFY_FUNCS
+    } else {
+        GUARDED_FUNCS
+    }
+}
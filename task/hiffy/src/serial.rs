@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serial transport for HIF
+//!
+//! This allows the host to do over a UART what it would otherwise do over
+//! SWD: write [`HIFFY_TEXT`] and [`HIFFY_DATA`], run the program, and read
+//! back [`HIFFY_RSTACK`] and the outcome.  The framing and the protocol are
+//! defined in the `hiffy-serial` crate.
+//!
+//! The UART driver (in the `usart_driver` task slot) buffers what it
+//! receives, and we poll it for bytes whenever we wake.  We handle requests
+//! one at a time, replying to each before reading further.
+//!
+//! [`HIFFY_TEXT`]: crate::HIFFY_TEXT
+//! [`HIFFY_DATA`]: crate::HIFFY_DATA
+//! [`HIFFY_RSTACK`]: crate::HIFFY_RSTACK
+
+use core::sync::atomic::Ordering;
+use hiffy_serial::{
+    Decoder, NackReason, Request, Response, MAX_FRAME, MAX_PAYLOAD,
+};
+use userlib::*;
+
+task_slot!(USART, usart_driver);
+
+/// Operations on the UART driver.
+const OP_WRITE: u16 = 1;
+const OP_READ: u16 = 2;
+
+static mut DECODER: Decoder = Decoder::new();
+static mut FRAME: [u8; MAX_FRAME] = [0; MAX_FRAME];
+static mut REPLY: [u8; MAX_PAYLOAD] = [0; MAX_PAYLOAD];
+
+///
+/// Reads whatever the UART has received, handling any requests that it
+/// completes.  Returns true if anything was received.
+///
+pub(crate) fn poll() -> bool {
+    let usart = USART.get_task_id();
+    let decoder = unsafe { &mut DECODER };
+    let reply = unsafe { &mut REPLY };
+    let mut buf = [0u8; 32];
+    let mut received = false;
+
+    loop {
+        let n = read(usart, &mut buf);
+
+        if n == 0 {
+            return received;
+        }
+
+        received = true;
+
+        for &byte in &buf[..n] {
+            let result = match decoder.push(byte) {
+                None => continue,
+                Some(Ok(frame)) => handle(frame.kind, frame.payload, reply),
+                Some(Err(_)) => Err(NackReason::BadFrame),
+            };
+
+            match result {
+                Ok((kind, len)) => respond(usart, kind, &reply[..len]),
+                Err(reason) => respond(usart, Response::Nack, &[reason as u8]),
+            }
+        }
+    }
+}
+
+/// Reads into `buf` whatever the UART has received, returning its length.
+fn read(usart: TaskId, buf: &mut [u8]) -> usize {
+    let mut len = [0u8; 4];
+
+    let (code, _) =
+        sys_send(usart, OP_READ, &[], &mut len, &[Lease::from(buf)]);
+
+    if code != 0 {
+        return 0;
+    }
+
+    u32::from_le_bytes(len) as usize
+}
+
+fn respond(usart: TaskId, kind: Response, payload: &[u8]) {
+    let frame = unsafe { &mut FRAME };
+
+    // Our payloads are never larger than MAX_PAYLOAD, so this can't fail.
+    let len = hiffy_serial::encode(kind as u8, payload, frame).unwrap();
+
+    // If the UART can't send, there's no one for us to tell.
+    let _ =
+        sys_send(usart, OP_WRITE, &[], &mut [], &[Lease::from(&frame[..len])]);
+}
+
+///
+/// Handles a request, returning the kind of response and the length of its
+/// payload, which is in `reply`.
+///
+fn handle(
+    kind: u8,
+    payload: &[u8],
+    reply: &mut [u8],
+) -> Result<(Response, usize), NackReason> {
+    use hiffy_serial::split_u32;
+
+    match Request::from_u8(kind).ok_or(NackReason::BadRequest)? {
+        Request::WriteText => {
+            write(unsafe { &mut crate::HIFFY_TEXT }, payload)?;
+            Ok((Response::Ack, 0))
+        }
+        Request::WriteData => {
+            write(unsafe { &mut crate::HIFFY_DATA }, payload)?;
+            Ok((Response::Ack, 0))
+        }
+        Request::Kick => {
+            if !payload.is_empty() {
+                return Err(NackReason::BadRequest);
+            }
+
+            crate::run();
+            status(reply)
+        }
+        Request::ReadRstack => {
+            let (offset, rest) =
+                split_u32(payload).ok_or(NackReason::BadRequest)?;
+            let (len, rest) = split_u32(rest).ok_or(NackReason::BadRequest)?;
+
+            if !rest.is_empty() || len as usize > reply.len() {
+                return Err(NackReason::BadRequest);
+            }
+
+            let rstack = unsafe { &crate::HIFFY_RSTACK };
+            let src = region(rstack, offset, len as usize)?;
+
+            reply[..src.len()].copy_from_slice(src);
+            Ok((Response::Rstack, src.len()))
+        }
+        Request::Status => {
+            if !payload.is_empty() {
+                return Err(NackReason::BadRequest);
+            }
+
+            status(reply)
+        }
+    }
+}
+
+/// Returns `len` bytes of `buf` at `offset`, if they're all there.
+fn region(buf: &[u8], offset: u32, len: usize) -> Result<&[u8], NackReason> {
+    let offset = offset as usize;
+
+    match offset.checked_add(len) {
+        Some(end) if end <= buf.len() => Ok(&buf[offset..end]),
+        _ => Err(NackReason::OutOfBounds),
+    }
+}
+
+/// Writes the bytes in `payload` at the offset with which it starts.
+fn write(buf: &mut [u8], payload: &[u8]) -> Result<(), NackReason> {
+    let (offset, bytes) =
+        hiffy_serial::split_u32(payload).ok_or(NackReason::BadRequest)?;
+
+    let offset = offset as usize;
+
+    match offset.checked_add(bytes.len()) {
+        Some(end) if end <= buf.len() => {
+            buf[offset..end].copy_from_slice(bytes);
+            Ok(())
+        }
+        _ => Err(NackReason::OutOfBounds),
+    }
+}
+
+///
+/// Fills `reply` with our status: the request and error counts, followed by
+/// the contents of [`HIFFY_FAILURE`](crate::HIFFY_FAILURE), as the debugger
+/// would find them in memory.
+///
+fn status(reply: &mut [u8]) -> Result<(Response, usize), NackReason> {
+    let requests = crate::HIFFY_REQUESTS.load(Ordering::SeqCst);
+    let errors = crate::HIFFY_ERRORS.load(Ordering::SeqCst);
+
+    let failure = unsafe {
+        let failure = &crate::HIFFY_FAILURE;
+
+        core::slice::from_raw_parts(
+            failure as *const _ as *const u8,
+            core::mem::size_of_val(failure),
+        )
+    };
+
+    let len = 8 + failure.len();
+
+    reply[..4].copy_from_slice(&requests.to_le_bytes());
+    reply[4..8].copy_from_slice(&errors.to_le_bytes());
+    reply[8..len].copy_from_slice(failure);
+
+    Ok((Response::Status, len))
+}
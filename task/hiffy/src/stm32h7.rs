@@ -2,6 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "i2c")]
+use crate::common::{i2c_args, i2c_bus_args};
 #[cfg(feature = "spi")]
 use crate::common::{spi_read, spi_write};
 use hif::*;
@@ -11,7 +13,7 @@ use userlib::*;
 
 #[cfg(feature = "i2c")]
 use drv_i2c_api::{
-    Controller, I2cDevice, Mux, PortIndex, ResponseCode, Segment,
+    Controller, I2cDevice, Mux, PortIndex, ProbeMethod, ResponseCode, Segment,
 };
 
 #[cfg(feature = "i2c")]
@@ -49,6 +51,15 @@ pub struct Buffer(u8);
 //
 pub enum Functions {
     Sleep(u16, u32),
+    RingbufDump(u32, userlib::ringbuf_registry::DumpError),
+    Send(
+        (userlib::Task, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
+    SendByName(
+        (usize, usize, u16, usize, usize, usize, usize, usize, usize),
+        u32,
+    ),
     #[cfg(feature = "i2c")]
     I2cRead(
         (Controller, PortIndex, Mux, Segment, u8, u8, usize),
@@ -64,6 +75,15 @@ pub enum Functions {
         (Controller, PortIndex, Mux, Segment, u8, u8, usize, usize),
         ResponseCode,
     ),
+    #[cfg(feature = "i2c")]
+    I2cScan(
+        (Controller, PortIndex, Mux, Segment, ProbeMethod),
+        ResponseCode,
+    ),
+    #[cfg(feature = "i2c")]
+    I2cStats((Controller, PortIndex, Mux, Segment, bool), ResponseCode),
+    #[cfg(feature = "i2c")]
+    I2cMuxInterrupts((Controller, PortIndex, Mux), ResponseCode),
     #[cfg(feature = "gpio")]
     GpioInput(drv_stm32h7_gpio_api::Port, drv_stm32h7_gpio_api::GpioError),
     #[cfg(feature = "gpio")]
@@ -114,77 +134,6 @@ pub enum Functions {
     QspiVerify((u32, usize, usize), drv_gimlet_hf_api::HfError),
 }
 
-#[cfg(feature = "i2c")]
-fn i2c_args(
-    stack: &[Option<u32>],
-) -> Result<
-    (
-        Controller,
-        PortIndex,
-        Option<(Mux, Segment)>,
-        u8,
-        Option<u8>,
-    ),
-    Failure,
-> {
-    let controller = match stack[0] {
-        Some(controller) => match Controller::from_u32(controller) {
-            Some(controller) => controller,
-            None => return Err(Failure::Fault(Fault::BadParameter(0))),
-        },
-        None => return Err(Failure::Fault(Fault::EmptyParameter(0))),
-    };
-
-    let port = match stack[1] {
-        Some(port) => {
-            if port > core::u8::MAX.into() {
-                return Err(Failure::Fault(Fault::BadParameter(1)));
-            }
-
-            PortIndex(port as u8)
-        }
-        None => {
-            //
-            // While we once upon a time allowed HIF consumers to specify
-            // a default port, we now expect all HIF consumers to read the
-            // device configuration and correctly specify a port index:
-            // this is an error.
-            //
-            return Err(Failure::Fault(Fault::EmptyParameter(1)));
-        }
-    };
-
-    let mux = match (stack[2], stack[3]) {
-        (Some(mux), Some(segment)) => Some((
-            match Mux::from_u32(mux) {
-                Some(mux) => mux,
-                None => {
-                    return Err(Failure::Fault(Fault::BadParameter(2)));
-                }
-            },
-            match Segment::from_u32(segment) {
-                Some(segment) => segment,
-                None => {
-                    return Err(Failure::Fault(Fault::BadParameter(3)));
-                }
-            },
-        )),
-        _ => None,
-    };
-
-    let addr = match stack[4] {
-        Some(addr) => addr as u8,
-        None => return Err(Failure::Fault(Fault::EmptyParameter(4))),
-    };
-
-    let register = match stack[5] {
-        Some(register) => Some(register as u8),
-        None => None,
-    };
-
-    Ok((controller, port, mux, addr, register))
-}
-
 #[cfg(feature = "i2c")]
 fn i2c_read(
     stack: &[Option<u32>],
@@ -338,6 +287,131 @@ fn i2c_bulk_write(
     }
 }
 
+///
+/// Scans a bus for devices, returning a bitmap of the addresses that are
+/// present (see [`drv_i2c_api::scan`]).
+///
+#[cfg(feature = "i2c")]
+fn i2c_scan(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    use drv_i2c_api::SCAN_BITMAP_SIZE;
+
+    if stack.len() < 5 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 5;
+    let (controller, port, mux) = i2c_bus_args(&stack[fp..])?;
+
+    let method = match stack[fp + 4] {
+        Some(method) => match ProbeMethod::from_u32(method) {
+            Some(method) => method,
+            None => return Err(Failure::Fault(Fault::BadParameter(4))),
+        },
+        None => return Err(Failure::Fault(Fault::EmptyParameter(4))),
+    };
+
+    if rval.len() < SCAN_BITMAP_SIZE {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    let task = I2C.get_task_id();
+
+    match drv_i2c_api::scan(task, controller, port, mux, method) {
+        Ok(bitmap) => {
+            rval[..SCAN_BITMAP_SIZE].copy_from_slice(&bitmap);
+            Ok(SCAN_BITMAP_SIZE)
+        }
+        Err(err) => Err(Failure::FunctionError(err.into())),
+    }
+}
+
+///
+/// Returns the health statistics for a bus (as a `drv_i2c_api::BusStats`),
+/// clearing them afterwards if the last parameter is non-zero.
+///
+#[cfg(feature = "i2c")]
+fn i2c_stats(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    use zerocopy::AsBytes;
+
+    if stack.len() < 5 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 5;
+    let (controller, port, mux) = i2c_bus_args(&stack[fp..])?;
+
+    let clear = match stack[fp + 4] {
+        Some(clear) => clear != 0,
+        None => return Err(Failure::Fault(Fault::EmptyParameter(4))),
+    };
+
+    let task = I2C.get_task_id();
+
+    match drv_i2c_api::bus_stats(task, controller, port, mux, clear) {
+        Ok(stats) => {
+            let bytes = stats.as_bytes();
+
+            if rval.len() < bytes.len() {
+                return Err(Failure::Fault(Fault::ReturnValueOverflow));
+            }
+
+            rval[..bytes.len()].copy_from_slice(bytes);
+            Ok(bytes.len())
+        }
+        Err(err) => Err(Failure::FunctionError(err.into())),
+    }
+}
+
+///
+/// Returns the segments of a mux that have their interrupt line asserted,
+/// as a bitmask in which bit 0 is the first segment.  The mux is given by
+/// its controller, port and mux.
+///
+#[cfg(feature = "i2c")]
+fn i2c_mux_interrupts(
+    stack: &[Option<u32>],
+    _data: &[u8],
+    rval: &mut [u8],
+) -> Result<usize, Failure> {
+    if stack.len() < 3 {
+        return Err(Failure::Fault(Fault::MissingParameters));
+    }
+
+    let fp = stack.len() - 3;
+    let (controller, port, _) =
+        i2c_bus_args(&[stack[fp], stack[fp + 1], None, None])?;
+
+    let mux = match stack[fp + 2] {
+        Some(mux) => match Mux::from_u32(mux) {
+            Some(mux) => mux,
+            None => return Err(Failure::Fault(Fault::BadParameter(2))),
+        },
+        None => return Err(Failure::Fault(Fault::EmptyParameter(2))),
+    };
+
+    if rval.is_empty() {
+        return Err(Failure::Fault(Fault::ReturnValueOverflow));
+    }
+
+    let task = I2C.get_task_id();
+
+    match drv_i2c_api::mux_interrupts(task, controller, port, mux) {
+        Ok(interrupts) => {
+            rval[0] = interrupts;
+            Ok(1)
+        }
+        Err(err) => Err(Failure::FunctionError(err.into())),
+    }
+}
+
 #[cfg(feature = "gpio")]
 fn gpio_args(
     stack: &[Option<u32>],
@@ -525,43 +599,52 @@ fn gpio_configure(
     }
 }
 
-pub(crate) static HIFFY_FUNCS: &[Function] = &[
-    crate::common::sleep,
+hiffy_funcs! {
+    Sleep(Fixed(1)) => crate::common::sleep,
+    RingbufDump(Fixed(1)) => crate::common::ringbuf_dump,
+    Send(Fixed(8)) => crate::common::send,
+    SendByName(Fixed(9)) => crate::common::send_by_name,
+    #[cfg(feature = "i2c")]
+    I2cRead(Fixed(7)) => i2c_read,
     #[cfg(feature = "i2c")]
-    i2c_read,
+    I2cWrite(Counted(6)) => i2c_write,
     #[cfg(feature = "i2c")]
-    i2c_write,
+    I2cBulkWrite(Fixed(8)) => i2c_bulk_write,
     #[cfg(feature = "i2c")]
-    i2c_bulk_write,
+    I2cScan(Fixed(5)) => i2c_scan,
+    #[cfg(feature = "i2c")]
+    I2cStats(Fixed(5)) => i2c_stats,
+    #[cfg(feature = "i2c")]
+    I2cMuxInterrupts(Fixed(3)) => i2c_mux_interrupts,
     #[cfg(feature = "gpio")]
-    gpio_input,
+    GpioInput(Fixed(1)) => gpio_input,
     #[cfg(feature = "gpio")]
-    gpio_toggle,
+    GpioToggle(Fixed(2)) => gpio_toggle,
     #[cfg(feature = "gpio")]
-    gpio_set,
+    GpioSet(Fixed(2)) => gpio_set,
     #[cfg(feature = "gpio")]
-    gpio_reset,
+    GpioReset(Fixed(2)) => gpio_reset,
     #[cfg(feature = "gpio")]
-    gpio_configure,
+    GpioConfigure(Fixed(7)) => gpio_configure,
     #[cfg(feature = "spi")]
-    spi_read,
+    SpiRead(Fixed(3)) => spi_read,
     #[cfg(feature = "spi")]
-    spi_write,
+    SpiWrite(Fixed(2)) => spi_write,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_read_id,
+    QspiReadId(Fixed(0)) => crate::common::qspi_read_id,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_read_status,
+    QspiReadStatus(Fixed(0)) => crate::common::qspi_read_status,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_bulk_erase,
+    QspiBulkErase(Fixed(0)) => crate::common::qspi_bulk_erase,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_page_program,
+    QspiPageProgram(Fixed(3)) => crate::common::qspi_page_program,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_read,
+    QspiRead(Fixed(2)) => crate::common::qspi_read,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_sector_erase,
+    QspiSectorErase(Fixed(1)) => crate::common::qspi_sector_erase,
     #[cfg(feature = "qspi")]
-    crate::common::qspi_verify,
-];
+    QspiVerify(Fixed(3)) => crate::common::qspi_verify,
+}
 
 //
 // This definition forces the compiler to emit the DWARF needed for debuggers
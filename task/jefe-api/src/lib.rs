@@ -0,0 +1,295 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the supervisor (Jefe).
+//!
+//! Unlike other servers, the supervisor is always task 0 and is never
+//! restarted, so it doesn't need a task slot: these functions send to it
+//! directly.
+
+#![no_std]
+
+use abi::{FaultInfo, FaultSource, UsageError};
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum Op {
+    Heartbeat = 1,
+    ReadFault = 2,
+}
+
+/// Response codes returned by the supervisor.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum JefeError {
+    BadOperation = 1,
+    BadArg = 2,
+    NoSuchFault = 3,
+}
+
+impl From<u32> for JefeError {
+    fn from(x: u32) -> Self {
+        match x {
+            1 => JefeError::BadOperation,
+            2 => JefeError::BadArg,
+            3 => JefeError::NoSuchFault,
+            _ => panic!(),
+        }
+    }
+}
+
+impl From<JefeError> for u32 {
+    fn from(rc: JefeError) -> Self {
+        rc as u32
+    }
+}
+
+/// Returns the supervisor's `TaskId`.
+fn supervisor() -> TaskId {
+    TaskId::for_index_and_gen(0, Generation::default())
+}
+
+/// Kinds of fault, corresponding to the variants of `abi::FaultInfo`.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum FaultKind {
+    MemoryAccess = 1,
+    StackOverflow = 2,
+    BusError = 3,
+    DivideByZero = 4,
+    IllegalText = 5,
+    IllegalInstruction = 6,
+    InvalidOperation = 7,
+    SyscallUsage = 8,
+    Panic = 9,
+    Injected = 10,
+}
+
+///
+/// A fault taken by a task, as recorded by the supervisor in its fault
+/// history.  This is a flat, padding-free encoding of `abi::FaultInfo` (and
+/// then some), such that any bit pattern is a valid record: the supervisor
+/// can keep its history in memory that survives a warm reset, and we can
+/// send records over IPC.  Use `fault` to recover the `FaultInfo`.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FaultRecord {
+    /// Time of the fault, in kernel ticks since the boot in which it was
+    /// taken.
+    pub timestamp: u64,
+    /// Number of times the supervisor had restarted the task.
+    pub restarts: u32,
+    /// Index of the faulted task.
+    pub task: u16,
+    /// Generation of the faulted task.
+    pub generation: u8,
+    /// Origin of the fault: 0 for `FaultSource::User`, 1 for
+    /// `FaultSource::Kernel`, or `NO_SOURCE` if the fault doesn't carry one.
+    pub source: u8,
+    /// Kind of fault, as a `FaultKind`.
+    pub kind: u8,
+    /// Non-zero if `address` is valid.
+    pub has_address: u8,
+    pub reserved: u16,
+    /// Address associated with the fault, if any.
+    pub address: u32,
+    /// Kind-specific detail: the code of an `InvalidOperation`, the
+    /// `UsageError` of a `SyscallUsage`, or the injecting `TaskId` of an
+    /// `Injected` fault.
+    pub detail: u32,
+    /// Sequence number of this record; the first fault recorded (since the
+    /// history was last cleared) is 0.
+    pub sequence: u32,
+}
+
+impl FaultRecord {
+    /// Value of `source` for faults that carry no source.
+    pub const NO_SOURCE: u8 = 0xff;
+
+    pub fn new(
+        task: TaskId,
+        fault: &FaultInfo,
+        timestamp: u64,
+        restarts: u32,
+        sequence: u32,
+    ) -> Self {
+        let mut record = Self {
+            timestamp,
+            restarts,
+            task: task.index() as u16,
+            generation: task.generation().into(),
+            source: Self::NO_SOURCE,
+            sequence,
+            ..Default::default()
+        };
+
+        let (kind, address, detail) = match *fault {
+            FaultInfo::MemoryAccess { address, .. } => {
+                (FaultKind::MemoryAccess, address, 0)
+            }
+            FaultInfo::StackOverflow { address } => {
+                (FaultKind::StackOverflow, Some(address), 0)
+            }
+            FaultInfo::BusError { address, .. } => {
+                (FaultKind::BusError, address, 0)
+            }
+            FaultInfo::DivideByZero => (FaultKind::DivideByZero, None, 0),
+            FaultInfo::IllegalText => (FaultKind::IllegalText, None, 0),
+            FaultInfo::IllegalInstruction => {
+                (FaultKind::IllegalInstruction, None, 0)
+            }
+            FaultInfo::InvalidOperation(code) => {
+                (FaultKind::InvalidOperation, None, code)
+            }
+            FaultInfo::SyscallUsage(e) => {
+                (FaultKind::SyscallUsage, None, usage_error_code(e))
+            }
+            FaultInfo::Panic => (FaultKind::Panic, None, 0),
+            FaultInfo::Injected(who) => {
+                (FaultKind::Injected, None, u32::from(who.0))
+            }
+        };
+
+        record.kind = kind as u8;
+        record.detail = detail;
+
+        //
+        // Only memory and bus faults record whether they were taken by the
+        // task or by the kernel on its behalf.
+        //
+        match *fault {
+            FaultInfo::MemoryAccess { source, .. }
+            | FaultInfo::BusError { source, .. } => {
+                record.source = match source {
+                    FaultSource::User => 0,
+                    FaultSource::Kernel => 1,
+                };
+            }
+            _ => {}
+        }
+
+        if let Some(address) = address {
+            record.has_address = 1;
+            record.address = address;
+        }
+
+        record
+    }
+
+    /// Returns the `TaskId` of the faulted task.
+    pub fn task_id(&self) -> TaskId {
+        TaskId::for_index_and_gen(
+            usize::from(self.task),
+            Generation::from(self.generation),
+        )
+    }
+
+    /// Returns the origin of the fault, if it has one.
+    pub fn source(&self) -> Option<FaultSource> {
+        match self.source {
+            0 => Some(FaultSource::User),
+            1 => Some(FaultSource::Kernel),
+            _ => None,
+        }
+    }
+
+    /// Reconstructs the `FaultInfo`, if the record is valid.
+    pub fn fault(&self) -> Option<FaultInfo> {
+        let address = if self.has_address != 0 {
+            Some(self.address)
+        } else {
+            None
+        };
+
+        Some(match FaultKind::from_u8(self.kind)? {
+            FaultKind::MemoryAccess => FaultInfo::MemoryAccess {
+                address,
+                source: self.source()?,
+            },
+            FaultKind::StackOverflow => {
+                FaultInfo::StackOverflow { address: address? }
+            }
+            FaultKind::BusError => FaultInfo::BusError {
+                address,
+                source: self.source()?,
+            },
+            FaultKind::DivideByZero => FaultInfo::DivideByZero,
+            FaultKind::IllegalText => FaultInfo::IllegalText,
+            FaultKind::IllegalInstruction => FaultInfo::IllegalInstruction,
+            FaultKind::InvalidOperation => {
+                FaultInfo::InvalidOperation(self.detail)
+            }
+            FaultKind::SyscallUsage => {
+                FaultInfo::SyscallUsage(usage_error(self.detail)?)
+            }
+            FaultKind::Panic => FaultInfo::Panic,
+            FaultKind::Injected => {
+                FaultInfo::Injected(TaskId(self.detail as u16))
+            }
+        })
+    }
+}
+
+fn usage_error_code(e: UsageError) -> u32 {
+    match e {
+        UsageError::BadSyscallNumber => 1,
+        UsageError::InvalidSlice => 2,
+        UsageError::TaskOutOfRange => 3,
+        UsageError::IllegalTask => 4,
+        UsageError::LeaseOutOfRange => 5,
+        UsageError::OffsetOutOfRange => 6,
+        UsageError::NoIrq => 7,
+        UsageError::BadKernelMessage => 8,
+    }
+}
+
+fn usage_error(code: u32) -> Option<UsageError> {
+    Some(match code {
+        1 => UsageError::BadSyscallNumber,
+        2 => UsageError::InvalidSlice,
+        3 => UsageError::TaskOutOfRange,
+        4 => UsageError::IllegalTask,
+        5 => UsageError::LeaseOutOfRange,
+        6 => UsageError::OffsetOutOfRange,
+        7 => UsageError::NoIrq,
+        8 => UsageError::BadKernelMessage,
+        _ => return None,
+    })
+}
+
+///
+/// Tells the supervisor that we're alive. A task that has a
+/// `[tasks.X.heartbeat]` section in its `app.toml` must call this at least
+/// once per heartbeat period, or it will be faulted -- or, if its heartbeat
+/// is marked `critical`, the system will be reset.
+///
+pub fn heartbeat() {
+    let (code, _) =
+        sys_send(supervisor(), Op::Heartbeat as u16, &[], &mut [], &[]);
+
+    if code != 0 {
+        panic!();
+    }
+}
+
+///
+/// Reads the `n`th most recent fault from the supervisor's fault history,
+/// where 0 is the most recent.  Returns `NoSuchFault` once `n` reaches the
+/// number of faults retained.  As faults may be recorded between calls,
+/// callers walking the history should check the records' sequence numbers.
+///
+pub fn read_fault(n: u32) -> Result<FaultRecord, JefeError> {
+    #[derive(AsBytes)]
+    #[repr(C)]
+    struct ReadFault(u32);
+
+    impl hl::Call for ReadFault {
+        const OP: u16 = Op::ReadFault as u16;
+        type Response = FaultRecord;
+        type Err = JefeError;
+    }
+
+    hl::send(supervisor(), &ReadFault(n))
+}
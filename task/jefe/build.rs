@@ -0,0 +1,226 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{bail, Result};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A task's restart policy, as rendered by xtask from the `[tasks.X.restart]`
+/// section of `app.toml`. The times here are in milliseconds, which is to say,
+/// kernel ticks.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    backoff: u32,
+    backoff_max: u32,
+    max_restarts: Option<u32>,
+    window: u32,
+    notification: u32,
+    dependents: Vec<String>,
+}
+
+/// A task's heartbeat, from the `[tasks.X.heartbeat]` section of `app.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Heartbeat {
+    period: u32,
+    critical: bool,
+}
+
+/// The supervisor's view of a task, as rendered by xtask.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TaskConfig {
+    restart: Option<RestartPolicy>,
+    heartbeat: Option<Heartbeat>,
+}
+
+/// Our own configuration, from the `[tasks.jefe.config]` section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Config {
+    watchdog: Option<WatchdogConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct WatchdogConfig {
+    /// Watchdog timeout, in milliseconds.
+    timeout: u32,
+}
+
+/// Watchdog timeout in the absence of any configuration, in milliseconds.
+const DEFAULT_WATCHDOG_TIMEOUT: u32 = 1000;
+
+/// Jefe feeds the watchdog from its periodic timer, which fires every 100 ms;
+/// a timeout needs to comfortably exceed that.
+const MIN_WATCHDOG_TIMEOUT: u32 = 300;
+
+/// The longest timeout that all supported watchdogs can express.
+const MAX_WATCHDOG_TIMEOUT: u32 = 32000;
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASKS");
+    println!("cargo:rerun-if-env-changed=HUBRIS_SUPERVISOR_CONFIG");
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASK_CONFIG");
+
+    //
+    // In a standalone build, there is only the one (anonymous) task -- which
+    // matches what userlib will do with `NUM_TASKS`.
+    //
+    let tasks = match env::var("HUBRIS_TASKS") {
+        Ok(tasks) => tasks.split(',').map(String::from).collect(),
+        Err(_) => vec![String::from("anonymous")],
+    };
+
+    let config: IndexMap<String, TaskConfig> =
+        match env::var("HUBRIS_SUPERVISOR_CONFIG") {
+            Ok(config) => toml::from_slice(config.as_bytes())?,
+            Err(_) => IndexMap::new(),
+        };
+
+    for name in config.keys() {
+        if !tasks.contains(name) {
+            bail!("supervisor config refers to unknown task '{}'", name);
+        }
+    }
+
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    write_restart_policies(out, &tasks, &config)?;
+    write_heartbeats(out, &tasks, &config)?;
+    write_watchdog_config(out)?;
+
+    Ok(())
+}
+
+fn write_restart_policies(
+    out: &Path,
+    tasks: &[String],
+    config: &IndexMap<String, TaskConfig>,
+) -> Result<()> {
+    let index_of = |name: &str| -> Result<usize> {
+        match tasks.iter().position(|t| t == name) {
+            Some(ndx) => Ok(ndx),
+            None => bail!("restart policy refers to unknown task '{}'", name),
+        }
+    };
+
+    let mut file = File::create(out.join("restart_policies.rs"))?;
+
+    writeln!(
+        file,
+        "pub(crate) static RESTART_POLICIES: [RestartPolicy; {}] = [",
+        tasks.len()
+    )?;
+
+    for task in tasks {
+        match config.get(task).and_then(|c| c.restart.as_ref()) {
+            None => {
+                writeln!(file, "    // {}", task)?;
+                writeln!(file, "    RestartPolicy::DEFAULT,")?;
+            }
+            Some(policy) => {
+                let dependents = policy
+                    .dependents
+                    .iter()
+                    .map(|d| index_of(d))
+                    .collect::<Result<Vec<_>>>()?;
+
+                writeln!(file, "    // {}", task)?;
+                writeln!(file, "    RestartPolicy {{")?;
+                writeln!(file, "        backoff: {},", policy.backoff)?;
+                writeln!(file, "        backoff_max: {},", policy.backoff_max)?;
+                writeln!(
+                    file,
+                    "        max_restarts: {:?},",
+                    policy.max_restarts
+                )?;
+                writeln!(file, "        window: {},", policy.window)?;
+                writeln!(
+                    file,
+                    "        notification: {:#x},",
+                    policy.notification
+                )?;
+                writeln!(file, "        dependents: &{:?},", dependents)?;
+                writeln!(file, "    }},")?;
+            }
+        }
+    }
+
+    writeln!(file, "];")?;
+
+    Ok(())
+}
+
+fn write_heartbeats(
+    out: &Path,
+    tasks: &[String],
+    config: &IndexMap<String, TaskConfig>,
+) -> Result<()> {
+    let mut file = File::create(out.join("heartbeats.rs"))?;
+
+    writeln!(
+        file,
+        "pub(crate) static HEARTBEATS: [Option<Heartbeat>; {}] = [",
+        tasks.len()
+    )?;
+
+    for task in tasks {
+        writeln!(file, "    // {}", task)?;
+
+        match config.get(task).and_then(|c| c.heartbeat.as_ref()) {
+            None => {
+                writeln!(file, "    None,")?;
+            }
+            Some(heartbeat) => {
+                writeln!(
+                    file,
+                    "    Some(Heartbeat {{ period: {}, critical: {} }}),",
+                    heartbeat.period, heartbeat.critical
+                )?;
+            }
+        }
+    }
+
+    writeln!(file, "];")?;
+
+    Ok(())
+}
+
+fn write_watchdog_config(out: &Path) -> Result<()> {
+    //
+    // Our task configuration is optional; in its absence (or in a standalone
+    // build), we use a default timeout.
+    //
+    let config = match env::var("HUBRIS_TASK_CONFIG") {
+        Ok(_) => build_util::task_config::<Config>()?,
+        Err(_) => Config::default(),
+    };
+
+    let timeout = match config.watchdog {
+        Some(watchdog) => watchdog.timeout,
+        None => DEFAULT_WATCHDOG_TIMEOUT,
+    };
+
+    if !(MIN_WATCHDOG_TIMEOUT..=MAX_WATCHDOG_TIMEOUT).contains(&timeout) {
+        bail!(
+            "watchdog timeout of {} ms is outside of [{}, {}] ms",
+            timeout,
+            MIN_WATCHDOG_TIMEOUT,
+            MAX_WATCHDOG_TIMEOUT
+        );
+    }
+
+    let mut file = File::create(out.join("watchdog_config.rs"))?;
+
+    writeln!(file, "/// Watchdog timeout, in milliseconds.")?;
+    writeln!(file, "pub(crate) const TIMEOUT: u32 = {};", timeout)?;
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Task heartbeats
+//!
+//! A task that can wedge without faulting (e.g., by blocking forever on a
+//! device) can opt into being monitored by having a `[tasks.X.heartbeat]`
+//! section in its `app.toml`:
+//!
+//! ```toml
+//! [tasks.thermal.heartbeat]
+//! period = 500                # in ms
+//! critical = false
+//! ```
+//!
+//! Such a task must call `task_jefe_api::heartbeat()` at least once per
+//! period.  If it misses its heartbeat, Jefe faults it -- at which point it
+//! is restarted according to its restart policy.  If the task is marked
+//! `critical`, Jefe additionally stops feeding the hardware watchdog (see the
+//! `watchdog` module), resetting the chip.
+//!
+//! A task is only held to its heartbeat while it is running: the clock
+//! starts anew whenever it is (re)started.
+
+use ringbuf::*;
+use userlib::*;
+
+/// A task's heartbeat requirement. The period is in kernel ticks.
+pub struct Heartbeat {
+    /// Maximum time between heartbeats.
+    pub period: u64,
+    /// If set, a missed heartbeat resets the chip.
+    pub critical: bool,
+}
+
+include!(concat!(env!("OUT_DIR"), "/heartbeats.rs"));
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Missed(u16, u64),
+    Starving(u16),
+}
+
+ringbuf!(Trace, 8, Trace::None);
+
+pub struct Heartbeats {
+    /// Time of each task's last heartbeat (or of its last start).
+    last: [u64; NUM_TASKS],
+    /// Whether a critical task has missed its heartbeat.
+    starving: bool,
+}
+
+impl Heartbeats {
+    pub const fn new() -> Self {
+        Self {
+            last: [0; NUM_TASKS],
+            starving: false,
+        }
+    }
+
+    /// Records a heartbeat from `task` at time `now`.
+    pub fn beat(&mut self, task: usize, now: u64) {
+        self.last[task] = now;
+    }
+
+    /// Restarts the heartbeat clock of `task`, which has just been started.
+    pub fn reset(&mut self, task: usize, now: u64) {
+        self.last[task] = now;
+    }
+
+    ///
+    /// Checks that every monitored task has posted its heartbeat, faulting
+    /// those that haven't.  Returns `true` if the watchdog should be fed --
+    /// which it should be unless a critical task has missed its heartbeat.
+    ///
+    pub fn check(&mut self, now: u64) -> bool {
+        for (i, heartbeat) in HEARTBEATS.iter().enumerate() {
+            let heartbeat = match heartbeat {
+                Some(heartbeat) => heartbeat,
+                None => continue,
+            };
+
+            let elapsed = now.saturating_sub(self.last[i]);
+
+            if elapsed <= heartbeat.period {
+                continue;
+            }
+
+            match kipc::read_task_status(i) {
+                abi::TaskState::Healthy(abi::SchedState::Stopped)
+                | abi::TaskState::Faulted { .. } => {
+                    // The task isn't running, so it can't be expected to
+                    // send a heartbeat; its clock starts when it restarts.
+                    self.last[i] = now;
+                }
+
+                abi::TaskState::Healthy(..) => {
+                    ringbuf_entry!(Trace::Missed(i as u16, elapsed));
+                    sys_log!("Task #{} missed heartbeat", i);
+
+                    if heartbeat.critical && !self.starving {
+                        ringbuf_entry!(Trace::Starving(i as u16));
+                        self.starving = true;
+                    }
+
+                    //
+                    // Fault the task, which will have it restarted (and will
+                    // leave a record of where it was wedged).  If it's
+                    // critical, this is really only useful as a post-mortem,
+                    // as the watchdog will be resetting us shortly.
+                    //
+                    kipc::fault_task(i);
+                    self.last[i] = now;
+                }
+            }
+        }
+
+        !self.starving
+    }
+}
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fault history
+//!
+//! Beyond logging a fault to the console, Jefe records it in a bounded ring
+//! of `FaultRecord`s (as defined in `task-jefe-api`), retaining the most
+//! recent `FAULT_HISTORY_SIZE` faults.  The history can be read out by tasks
+//! via the `ReadFault` operation, and is kept in a variable at a well-known
+//! symbol, `JEFE_FAULT_HISTORY`, for the benefit of the debugger.
+//!
+//! If built with the `fault-history-noinit` feature, the history is placed
+//! in the `.uninit` section, where it survives a warm reset (e.g., via the
+//! watchdog).  As we can't trust memory that we haven't initialized, the
+//! history is checked on start: if its magic or any retained record's
+//! sequence number is amiss, it is cleared.  Note that the timestamp of a
+//! record is relative to the boot in which it was taken; records with a
+//! sequence number below `boot_sequence` predate the current boot.
+
+use core::cmp;
+use task_jefe_api::FaultRecord;
+use userlib::*;
+
+/// Number of faults retained.
+const FAULT_HISTORY_SIZE: usize = 16;
+
+/// Marks an initialized history ("JFH1").
+const FAULT_HISTORY_MAGIC: u32 = 0x4a46_4831;
+
+#[repr(C)]
+pub struct FaultHistory {
+    /// `FAULT_HISTORY_MAGIC` if this history has been initialized.
+    magic: u32,
+    /// Number of boots over which this history has been retained.
+    boots: u32,
+    /// Sequence number of the first fault recorded in the current boot.
+    boot_sequence: u32,
+    /// Sequence number of the next fault to be recorded.
+    next: u32,
+    /// Records, indexed by sequence number modulo `FAULT_HISTORY_SIZE`.
+    records: [FaultRecord; FAULT_HISTORY_SIZE],
+}
+
+impl FaultHistory {
+    const EMPTY: Self = Self {
+        magic: 0,
+        boots: 0,
+        boot_sequence: 0,
+        next: 0,
+        records: [FaultRecord {
+            timestamp: 0,
+            restarts: 0,
+            task: 0,
+            generation: 0,
+            source: FaultRecord::NO_SOURCE,
+            kind: 0,
+            has_address: 0,
+            reserved: 0,
+            address: 0,
+            detail: 0,
+            sequence: 0,
+        }; FAULT_HISTORY_SIZE],
+    };
+
+    /// Returns the number of records retained.
+    fn retained(&self) -> u32 {
+        cmp::min(self.next, FAULT_HISTORY_SIZE as u32)
+    }
+
+    fn slot(sequence: u32) -> usize {
+        sequence as usize % FAULT_HISTORY_SIZE
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == FAULT_HISTORY_MAGIC
+            && (self.next - self.retained()..self.next)
+                .all(|seq| self.records[Self::slot(seq)].sequence == seq)
+    }
+}
+
+//
+// Every bit pattern is a valid `FaultHistory`, so it's sound for this to be
+// uninitialized memory.
+//
+#[cfg_attr(feature = "fault-history-noinit", link_section = ".uninit")]
+static mut JEFE_FAULT_HISTORY: FaultHistory = FaultHistory::EMPTY;
+
+fn history() -> &'static mut FaultHistory {
+    // Safety: we are single-threaded, and no reference outlives its caller.
+    unsafe { &mut JEFE_FAULT_HISTORY }
+}
+
+///
+/// Prepares the history for use, retaining any history from a previous boot
+/// if it passes muster.
+///
+pub fn init() {
+    let history = history();
+
+    if history.is_valid() {
+        history.boots = history.boots.wrapping_add(1);
+        history.boot_sequence = history.next;
+        sys_log!("Retained {} faults from previous boot", history.retained());
+    } else {
+        *history = FaultHistory::EMPTY;
+        history.magic = FAULT_HISTORY_MAGIC;
+        history.boots = 1;
+    }
+}
+
+///
+/// Records a fault taken by task `task` at time `now`, after the task had
+/// been restarted `restarts` times.
+///
+pub fn record(task: usize, fault: &abi::FaultInfo, restarts: u32, now: u64) {
+    let history = history();
+    let sequence = history.next;
+
+    // The generation of the faulted task is its current generation, as it
+    // has yet to be restarted.
+    let prototype = TaskId::for_index_and_gen(task, Generation::default());
+    let id = sys_refresh_task_id(prototype);
+
+    history.records[FaultHistory::slot(sequence)] =
+        FaultRecord::new(id, fault, now, restarts, sequence);
+
+    history.next = sequence.wrapping_add(1);
+}
+
+/// Returns the `n`th most recent fault, if it has been retained.
+pub fn read(n: u32) -> Option<FaultRecord> {
+    let history = history();
+
+    if n >= history.retained() {
+        return None;
+    }
+
+    let sequence = history.next - 1 - n;
+    Some(history.records[FaultHistory::slot(sequence)])
+}
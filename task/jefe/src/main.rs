@@ -7,13 +7,18 @@
 //! The supervisor is responsible for:
 //!
 //! - Maintaining the system console output (currently via semihosting).
-//! - Monitoring tasks for failures and restarting them.
+//! - Monitoring tasks for failures and restarting them, subject to per-task
+//!   restart policies (see the `restart` module).
+//! - Monitoring the heartbeats of tasks that have opted into them (see the
+//!   `heartbeat` module).
+//! - Feeding the hardware watchdog, if so configured (see the `watchdog`
+//!   module).
+//! - Keeping a history of task faults (see the `history` module).
 //!
 //! It will probably become responsible for:
 //!
 //! - Evacuating kernel log information.
 //! - Coordinating certain shared resources, such as the RCC and GPIO muxing.
-//! - Managing a watchdog timer.
 //!
 //! It's unwise for the supervisor to use `SEND`, ever, except to talk to the
 //! kernel. This is because a `SEND` to a misbehaving task could block forever,
@@ -27,8 +32,16 @@
 #![no_main]
 
 mod external;
+mod heartbeat;
+mod history;
+mod restart;
+mod watchdog;
 
+use heartbeat::Heartbeats;
+use restart::{Decision, Restarts};
+use task_jefe_api::{FaultRecord, JefeError, Op};
 use userlib::*;
+use zerocopy::AsBytes;
 
 fn log_fault(t: usize, fault: &abi::FaultInfo) {
     match fault {
@@ -98,9 +111,13 @@ pub enum Disposition {
 fn main() -> ! {
     sys_log!("viva el jefe");
 
+    history::init();
+
     let mut disposition: [Disposition; NUM_TASKS] =
         [Disposition::Restart; NUM_TASKS];
     let mut logged: [bool; NUM_TASKS] = [false; NUM_TASKS];
+    let mut restarts = Restarts::new();
+    let mut heartbeats = Heartbeats::new();
 
     // We'll have notification 0 wired up to receive information about task
     // faults.
@@ -117,46 +134,87 @@ fn main() -> ! {
 
     sys_set_timer(Some(deadline), TIMER_MASK);
 
+    // Our periodic timer must fire well within the watchdog's timeout.
+    watchdog::start();
+
     external::set_ready();
 
     loop {
-        let msginfo = sys_recv_open(&mut [], fault_mask | TIMER_MASK);
+        let mut msg = [0; 4];
+        let msginfo = sys_recv_open(&mut msg, fault_mask | TIMER_MASK);
 
         if msginfo.sender == TaskId::KERNEL {
             // Check to see if we have any external requests
             let changed = external::check(&mut disposition);
+            let now = sys_get_timer().now;
 
-            // If our timer went off, we need to reestablish it
-            if msginfo.operation & TIMER_MASK != 0 {
+            // If our periodic timer went off, we need to advance it, check
+            // our tasks' heartbeats, and feed the watchdog -- unless a
+            // critical task has wedged.  (Our timer may also go off early to
+            // perform a deferred restart.)
+            if msginfo.operation & TIMER_MASK != 0 && now >= deadline {
                 deadline += TIMER_INTERVAL;
-                sys_set_timer(Some(deadline), TIMER_MASK);
+
+                if heartbeats.check(now) {
+                    watchdog::feed();
+                }
             }
 
-            // If our disposition has changed or if we have been notified of
-            // a faulting task, we need to iterate over all of our tasks.
-            if changed || (msginfo.operation & fault_mask) != 0 {
+            let restart_due = match restarts.next_deadline() {
+                Some(when) => now >= when,
+                None => false,
+            };
+
+            // If our disposition has changed, if we have been notified of
+            // a faulting task, or if a deferred restart has come due, we
+            // need to iterate over all of our tasks.
+            if changed || (msginfo.operation & fault_mask) != 0 || restart_due {
                 for i in 0..NUM_TASKS {
                     match kipc::read_task_status(i) {
                         abi::TaskState::Faulted { fault, .. } => {
                             if !logged[i] {
                                 log_fault(i, &fault);
+                                history::record(
+                                    i,
+                                    &fault,
+                                    restarts.total(i),
+                                    now,
+                                );
                                 logged[i] = true;
                             }
 
-                            if disposition[i] == Disposition::Restart {
-                                // Stand it back up
-                                kipc::restart_task(i, true);
-                                logged[i] = false;
+                            if disposition[i] != Disposition::Restart {
+                                restarts.cancel(i);
+                                continue;
+                            }
+
+                            match restarts.on_fault(i, now) {
+                                Decision::Restart => {
+                                    // Stand it back up
+                                    kipc::restart_task(i, true);
+                                    heartbeats.reset(i, now);
+                                    logged[i] = false;
+                                    restarts.notify_dependents(i);
+                                }
+                                Decision::Defer => {}
+                                Decision::Hold => {
+                                    disposition[i] = Disposition::Hold;
+                                }
                             }
                         }
 
                         abi::TaskState::Healthy(abi::SchedState::Stopped) => {
+                            restarts.cancel(i);
+
                             if disposition[i] == Disposition::Start {
                                 kipc::restart_task(i, true);
+                                heartbeats.reset(i, now);
                             }
                         }
 
                         abi::TaskState::Healthy(..) => {
+                            restarts.cancel(i);
+
                             if disposition[i] == Disposition::Fault {
                                 kipc::fault_task(i);
                             }
@@ -164,9 +222,59 @@ fn main() -> ! {
                     }
                 }
             }
+
+            // Wake for our next periodic check or deferred restart,
+            // whichever comes first.
+            let wake = match restarts.next_deadline() {
+                Some(when) => when.min(deadline),
+                None => deadline,
+            };
+
+            sys_set_timer(Some(wake), TIMER_MASK);
         } else {
-            // ...huh. A task has sent a message to us. That seems wrong.
-            sys_log!("Unexpected message from {}", msginfo.sender.0);
+            // A task has sent a message to us; we reply immediately, lest we
+            // leave it blocked.
+            match Op::from_u32(msginfo.operation) {
+                Some(Op::Heartbeat) => {
+                    let now = sys_get_timer().now;
+                    heartbeats.beat(msginfo.sender.index(), now);
+                    sys_reply(msginfo.sender, 0, &[]);
+                }
+
+                Some(Op::ReadFault) => {
+                    let size = core::mem::size_of::<FaultRecord>();
+
+                    if msginfo.message_len != msg.len()
+                        || msginfo.response_capacity < size
+                    {
+                        sys_reply(
+                            msginfo.sender,
+                            JefeError::BadArg.into(),
+                            &[],
+                        );
+                        continue;
+                    }
+
+                    match history::read(u32::from_le_bytes(msg)) {
+                        Some(record) => {
+                            sys_reply(msginfo.sender, 0, record.as_bytes());
+                        }
+                        None => {
+                            let rc = JefeError::NoSuchFault.into();
+                            sys_reply(msginfo.sender, rc, &[]);
+                        }
+                    }
+                }
+
+                None => {
+                    sys_log!("Unexpected message from {}", msginfo.sender.0);
+                    sys_reply(
+                        msginfo.sender,
+                        JefeError::BadOperation.into(),
+                        &[],
+                    );
+                }
+            }
         }
     }
 }
@@ -0,0 +1,233 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Restart policies for faulted tasks
+//!
+//! Absent any other direction, Jefe restarts a faulted task immediately, and
+//! will keep doing so for as long as the task keeps faulting.  For a task that
+//! faults because (say) a device it depends on has wedged, this can result in
+//! a tight fault loop that starves the rest of the system.  To avoid this, a
+//! task can be given a restart policy in its `[tasks.X.restart]` section in
+//! the `app.toml`:
+//!
+//! ```toml
+//! [tasks.i2c_driver.restart]
+//! backoff = 10                # delay before first restart, in ms
+//! backoff-max = 1000          # the delay doubles up to this limit
+//! max-restarts = 8            # hold the task after this many restarts...
+//! window = 60000              # ...within this many ms
+//! notify-dependents = 0x8000  # post this to our clients on restart
+//! ```
+//!
+//! Restarts are counted within a window that begins with the first restart;
+//! once a fault occurs after the window has elapsed, the count and the backoff
+//! are reset.  A task that exceeds `max-restarts` within a window has its
+//! disposition set to `Hold`; releasing it (e.g., via Humility) gives it a
+//! fresh window.
+//!
+//! If `notify-dependents` is set, the given notification bits are posted to
+//! every task that has the restarted task in its `task-slots` -- allowing
+//! clients to discard any state that they hold on the server's behalf.
+//!
+//! Every decision made here is recorded in a ring buffer.
+
+use ringbuf::*;
+use userlib::*;
+
+/// A task's restart policy. The times here are in kernel ticks.
+pub struct RestartPolicy {
+    /// Delay before the first restart within a window.
+    pub backoff: u64,
+    /// Upper bound on the delay, which doubles with each restart.
+    pub backoff_max: u64,
+    /// Number of restarts within a window after which the task is held.
+    pub max_restarts: Option<u32>,
+    /// Length of the window over which restarts are counted.
+    pub window: u64,
+    /// Notification bits to post to our dependents when we restart.
+    pub notification: u32,
+    /// Indices of tasks that have this task in their task slots.
+    pub dependents: &'static [usize],
+}
+
+impl RestartPolicy {
+    /// The policy for tasks without one: restart immediately, forever.
+    pub const DEFAULT: Self = Self {
+        backoff: 0,
+        backoff_max: 0,
+        max_restarts: None,
+        window: u64::MAX,
+        notification: 0,
+        dependents: &[],
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/restart_policies.rs"));
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    WindowReset(u16),
+    Restart(u16, u32),
+    Backoff(u16, u64),
+    Hold(u16, u32),
+    Cancel(u16),
+    Notify(u16, u16),
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+/// What Jefe should do with a faulted task whose disposition is `Restart`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Decision {
+    /// Restart the task now.
+    Restart,
+    /// Leave the task faulted; a restart has been scheduled for later.
+    Defer,
+    /// Leave the task faulted, and change its disposition to `Hold`.
+    Hold,
+}
+
+/// Per-task restart history.
+#[derive(Copy, Clone)]
+struct History {
+    /// Time at which the current window began.
+    window_start: u64,
+    /// Number of restarts within the current window.
+    restarts: u32,
+    /// Delay to apply before the next restart.
+    backoff: u64,
+    /// Time at which a deferred restart should be performed, if any.
+    pending: Option<u64>,
+    /// Whether the task was held because it exceeded its restart limit.
+    held: bool,
+    /// Number of restarts since boot.
+    total: u32,
+}
+
+impl History {
+    const EMPTY: Self = Self {
+        window_start: 0,
+        restarts: 0,
+        backoff: 0,
+        pending: None,
+        held: false,
+        total: 0,
+    };
+}
+
+pub struct Restarts {
+    history: [History; NUM_TASKS],
+}
+
+impl Restarts {
+    pub const fn new() -> Self {
+        Self {
+            history: [History::EMPTY; NUM_TASKS],
+        }
+    }
+
+    ///
+    /// Decides what to do with task `task`, which has been found faulted with
+    /// a disposition of `Restart` at time `now`.  This is called on every
+    /// scan in which the task remains faulted, so a deferred restart is only
+    /// counted once.
+    ///
+    pub fn on_fault(&mut self, task: usize, now: u64) -> Decision {
+        let policy = &RESTART_POLICIES[task];
+        let h = &mut self.history[task];
+        let ndx = task as u16;
+
+        if let Some(deadline) = h.pending {
+            if now < deadline {
+                return Decision::Defer;
+            }
+
+            h.pending = None;
+            h.total = h.total.wrapping_add(1);
+            ringbuf_entry!(Trace::Restart(ndx, h.restarts));
+            return Decision::Restart;
+        }
+
+        //
+        // If we held this task and we're now being asked to restart it, it
+        // has been released: give it a fresh window.
+        //
+        let expired = now.saturating_sub(h.window_start) >= policy.window;
+
+        if h.held || h.restarts == 0 || expired {
+            if h.restarts != 0 {
+                ringbuf_entry!(Trace::WindowReset(ndx));
+            }
+
+            h.window_start = now;
+            h.restarts = 0;
+            h.backoff = policy.backoff;
+            h.held = false;
+        }
+
+        if let Some(max) = policy.max_restarts {
+            if h.restarts >= max {
+                h.held = true;
+                ringbuf_entry!(Trace::Hold(ndx, h.restarts));
+                return Decision::Hold;
+            }
+        }
+
+        h.restarts += 1;
+
+        let delay = h.backoff;
+        h.backoff = (h.backoff * 2).min(policy.backoff_max);
+
+        if delay == 0 {
+            h.total = h.total.wrapping_add(1);
+            ringbuf_entry!(Trace::Restart(ndx, h.restarts));
+            Decision::Restart
+        } else {
+            h.pending = Some(now + delay);
+            ringbuf_entry!(Trace::Backoff(ndx, delay));
+            Decision::Defer
+        }
+    }
+
+    ///
+    /// Cancels any deferred restart of `task` -- e.g., because it has been
+    /// held, or restarted by some other means.
+    ///
+    pub fn cancel(&mut self, task: usize) {
+        if self.history[task].pending.take().is_some() {
+            ringbuf_entry!(Trace::Cancel(task as u16));
+        }
+    }
+
+    /// Returns the number of times `task` has been restarted since boot.
+    pub fn total(&self, task: usize) -> u32 {
+        self.history[task].total
+    }
+
+    /// Returns the earliest time at which a deferred restart is due, if any.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.history.iter().filter_map(|h| h.pending).min()
+    }
+
+    ///
+    /// Notifies the dependents of `task` (if its policy asks for it) that it
+    /// has been restarted.  This uses only `sys_post`, which -- unlike
+    /// `sys_send` -- cannot block on a misbehaving task.
+    ///
+    pub fn notify_dependents(&self, task: usize) {
+        let policy = &RESTART_POLICIES[task];
+
+        if policy.notification == 0 {
+            return;
+        }
+
+        for &dependent in policy.dependents {
+            let prototype =
+                TaskId::for_index_and_gen(dependent, Generation::default());
+            sys_post(sys_refresh_task_id(prototype), policy.notification);
+            ringbuf_entry!(Trace::Notify(dependent as u16, task as u16));
+        }
+    }
+}
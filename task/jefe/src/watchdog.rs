@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware watchdog support
+//!
+//! If built with the `watchdog-stm32h7` or `watchdog-lpc55` feature, Jefe
+//! starts the independent watchdog (IWDG1) or windowed watchdog (WWDT),
+//! respectively, and feeds it from its periodic timer.  If Jefe itself
+//! wedges -- or if it stops feeding the watchdog because a critical task has
+//! missed its heartbeat -- the chip is reset.  Once started, neither watchdog
+//! can be stopped.
+//!
+//! The timeout is taken from Jefe's own configuration in the `app.toml`,
+//! defaulting to one second:
+//!
+//! ```toml
+//! [tasks.jefe.config.watchdog]
+//! timeout = 2000              # in ms
+//! ```
+//!
+//! The watchdog's registers must be mapped into Jefe via its `uses`: on the
+//! STM32H7, this is the IWDG1 block at 0x5800_4800; on the LPC55, it is the
+//! WWDT block at 0x4000_C000 -- and, so that we can enable the WWDT clock
+//! without having to send to the SYSCON driver, the SYSCON block at
+//! 0x4000_0000.
+//!
+//! We deliberately go to the registers directly rather than via a PAC: there
+//! are only a handful of them, and we would rather not drag a PAC into the
+//! supervisor for their sake.  Without either feature, this module does
+//! nothing.
+
+#[cfg(all(feature = "watchdog-stm32h7", feature = "watchdog-lpc55"))]
+compile_error!("at most one watchdog feature may be enabled");
+
+#[allow(dead_code)]
+mod config {
+    include!(concat!(env!("OUT_DIR"), "/watchdog_config.rs"));
+}
+
+#[cfg(feature = "watchdog-stm32h7")]
+mod imp {
+    use super::config::TIMEOUT;
+    use core::ptr;
+
+    const IWDG: usize = 0x5800_4800;
+    const KR: *mut u32 = IWDG as *mut u32;
+    const PR: *mut u32 = (IWDG + 0x04) as *mut u32;
+    const RLR: *mut u32 = (IWDG + 0x08) as *mut u32;
+    const SR: *const u32 = (IWDG + 0x0c) as *const u32;
+
+    const KEY_START: u32 = 0xcccc;
+    const KEY_UNLOCK: u32 = 0x5555;
+    const KEY_FEED: u32 = 0xaaaa;
+
+    /// The IWDG is clocked by the LSI, which runs at (roughly) 32 kHz.
+    const LSI_KHZ: u32 = 32;
+
+    /// The reload register is 12 bits wide.
+    const RLR_MAX: u32 = 0xfff;
+
+    pub fn start() {
+        //
+        // Find the smallest prescaler (from /4 to /256) that will accommodate
+        // our timeout.
+        //
+        let (pr, reload) = (0..=6)
+            .map(|pr| (pr, TIMEOUT * LSI_KHZ / (4 << pr)))
+            .find(|&(_, ticks)| ticks <= RLR_MAX + 1)
+            .unwrap();
+
+        unsafe {
+            ptr::write_volatile(KR, KEY_START);
+            ptr::write_volatile(KR, KEY_UNLOCK);
+            ptr::write_volatile(PR, pr);
+            ptr::write_volatile(RLR, reload - 1);
+
+            // Wait for the prescaler and reload values to be latched.
+            while ptr::read_volatile(SR) != 0 {
+                continue;
+            }
+
+            ptr::write_volatile(KR, KEY_FEED);
+        }
+    }
+
+    pub fn feed() {
+        unsafe {
+            ptr::write_volatile(KR, KEY_FEED);
+        }
+    }
+}
+
+#[cfg(feature = "watchdog-lpc55")]
+mod imp {
+    use super::config::TIMEOUT;
+    use core::ptr;
+
+    const SYSCON: usize = 0x4000_0000;
+    const AHBCLKCTRLSET0: *mut u32 = (SYSCON + 0x220) as *mut u32;
+    const WDTCLKDIV: *mut u32 = (SYSCON + 0x38c) as *mut u32;
+
+    /// Bit for the WWDT in `AHBCLKCTRL0`.
+    const AHBCLKCTRL0_WWDT: u32 = 1 << 22;
+
+    /// Set in `WDTCLKDIV` while a divider change is in progress.
+    const WDTCLKDIV_REQFLAG: u32 = 1 << 31;
+
+    const WWDT: usize = 0x4000_c000;
+    const MOD: *mut u32 = WWDT as *mut u32;
+    const TC: *mut u32 = (WWDT + 0x04) as *mut u32;
+    const FEED: *mut u32 = (WWDT + 0x08) as *mut u32;
+
+    const MOD_WDEN: u32 = 1 << 0;
+    const MOD_WDRESET: u32 = 1 << 1;
+
+    /// The WWDT is clocked by the 1 MHz FRO (which runs out of reset) with a
+    /// fixed divide-by-4, and we leave `WDTCLKDIV` at divide-by-1.
+    const TICKS_PER_MS: u32 = 1000 / 4;
+
+    /// The timer constant is 24 bits wide, and must be at least 0xff.
+    const TC_MIN: u32 = 0xff;
+    const TC_MAX: u32 = 0xff_ffff;
+
+    pub fn start() {
+        let tc = (TIMEOUT * TICKS_PER_MS).max(TC_MIN).min(TC_MAX);
+
+        unsafe {
+            ptr::write_volatile(AHBCLKCTRLSET0, AHBCLKCTRL0_WWDT);
+
+            // Take the divider out of halt at divide-by-1.
+            ptr::write_volatile(WDTCLKDIV, 0);
+
+            while ptr::read_volatile(WDTCLKDIV) & WDTCLKDIV_REQFLAG != 0 {
+                continue;
+            }
+
+            ptr::write_volatile(TC, tc);
+            ptr::write_volatile(MOD, MOD_WDEN | MOD_WDRESET);
+        }
+
+        // The WWDT doesn't start counting until it is first fed.
+        feed();
+    }
+
+    pub fn feed() {
+        //
+        // The two writes of the feed sequence must not be interleaved with
+        // any other WWDT access -- which, as we are the only ones touching
+        // it, they won't be.
+        //
+        unsafe {
+            ptr::write_volatile(FEED, 0xaa);
+            ptr::write_volatile(FEED, 0x55);
+        }
+    }
+}
+
+#[cfg(not(any(feature = "watchdog-stm32h7", feature = "watchdog-lpc55")))]
+mod imp {
+    pub fn start() {}
+
+    pub fn feed() {}
+}
+
+/// Starts the watchdog, which must then be fed within its timeout.
+pub use imp::start;
+
+/// Feeds the watchdog.
+pub use imp::feed;
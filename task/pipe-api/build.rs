@@ -0,0 +1,127 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// The parts of the app-wide configuration that we care about.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Config {
+    pipe: Option<PipeConfig>,
+}
+
+/// The `[config.pipe]` section of `app.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PipeConfig {
+    channels: Vec<ChannelConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ChannelConfig {
+    name: String,
+    /// Capacity of the channel, in bytes.
+    capacity: usize,
+}
+
+/// Largest capacity that a channel may have; the statistics (and offsets
+/// within the channel) are 32 bits, but the pipe server's RAM is the real
+/// limit.
+const MAX_CAPACITY: usize = 64 * 1024;
+
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_APP_CONFIG");
+
+    //
+    // In a standalone build (or an app that doesn't configure any pipes),
+    // there are simply no channels.
+    //
+    let config = match env::var("HUBRIS_APP_CONFIG") {
+        Ok(_) => build_util::config::<Config>()?,
+        Err(_) => Config::default(),
+    };
+
+    let channels = match config.pipe {
+        Some(pipe) => pipe.channels,
+        None => vec![],
+    };
+
+    for (i, channel) in channels.iter().enumerate() {
+        let name = &channel.name;
+
+        if name.is_empty()
+            || !name.starts_with(|c: char| c.is_ascii_lowercase())
+            || !name.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'
+            })
+        {
+            bail!(
+                "pipe channel name '{}' must be lowercase alphanumerics \
+                and dashes, starting with a letter",
+                name
+            );
+        }
+
+        if channels[..i].iter().any(|c| c.name == *name) {
+            bail!("duplicate pipe channel '{}'", name);
+        }
+
+        if channel.capacity == 0 || channel.capacity > MAX_CAPACITY {
+            bail!(
+                "pipe channel '{}' has capacity of {} bytes; must be in \
+                [1, {}]",
+                name,
+                channel.capacity,
+                MAX_CAPACITY
+            );
+        }
+    }
+
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let mut file = File::create(out.join("channels.rs"))?;
+
+    writeln!(file, "/// The channels configured in `app.toml`.")?;
+    writeln!(
+        file,
+        "pub const CHANNELS: [ChannelInfo; {}] = [",
+        channels.len()
+    )?;
+
+    for channel in &channels {
+        writeln!(
+            file,
+            "    ChannelInfo {{ name: {:?}, capacity: {} }},",
+            channel.name, channel.capacity
+        )?;
+    }
+
+    writeln!(file, "];")?;
+
+    writeln!(file, "\n/// Named handles for each configured channel.")?;
+    writeln!(file, "pub mod channels {{")?;
+    writeln!(file, "    use super::Channel;")?;
+
+    for (i, channel) in channels.iter().enumerate() {
+        writeln!(
+            file,
+            "\n    /// `{}`, of {} bytes",
+            channel.name, channel.capacity
+        )?;
+        writeln!(
+            file,
+            "    pub const {}: Channel = Channel({});",
+            channel.name.to_ascii_uppercase().replace('-', "_"),
+            i
+        )?;
+    }
+
+    writeln!(file, "}}")?;
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the pipe server.
+//!
+//! The pipe server provides channels: bounded byte FIFOs between tasks. The
+//! channels are configured in the `[config.pipe]` section of `app.toml`,
+//! with a name and a capacity in bytes:
+//!
+//! ```toml
+//! [[config.pipe.channels]]
+//! name = "console"
+//! capacity = 512
+//! ```
+//!
+//! Each channel gets a constant in [`channels`], named for it in upper case
+//! (with dashes becoming underscores), e.g. `channels::CONSOLE`.
+//!
+//! Any number of tasks may write to a channel. A write that doesn't fit
+//! isn't an error: as much as fits is accepted, and the rest is dropped
+//! and counted in the channel's [`ChannelStats`].
+//!
+//! A reader can choose how to wait for data: [`Pipe::read`] blocks until
+//! there is data to be had, while [`Pipe::try_read`] returns immediately --
+//! and, if there was nothing to read, has the server post a notification to
+//! the reader once there is. Only one reader may be blocked on a channel at
+//! a time.
+
+#![no_std]
+
+use core::cell::Cell;
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum Op {
+    Write = 1,
+    Read = 2,
+    Stats = 3,
+}
+
+/// Response codes returned by the pipe server.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum PipeError {
+    BadOperation = 1,
+    /// There is no such channel.
+    BadChannel = 2,
+    /// The lease is missing, of the wrong kind, or empty.
+    BadLease = 3,
+    /// Another task is already blocked reading from the channel.
+    Busy = 4,
+    /// The server restarted, and the operation was not attempted. (This is
+    /// produced by the client, rather than the server.)
+    ServerRestarted = 5,
+}
+
+impl From<PipeError> for u32 {
+    fn from(rc: PipeError) -> Self {
+        rc as u32
+    }
+}
+
+/// A channel, as configured in `app.toml`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelInfo {
+    pub name: &'static str,
+    /// Capacity of the channel, in bytes.
+    pub capacity: usize,
+}
+
+/// A handle for a channel; see [`channels`] for the configured channels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Channel(u32);
+
+impl Channel {
+    /// Looks up a channel by name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        CHANNELS
+            .iter()
+            .position(|c| c.name == name)
+            .map(|ndx| Channel(ndx as u32))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn info(self) -> &'static ChannelInfo {
+        &CHANNELS[self.index()]
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/channels.rs"));
+
+/// The message for [`Op::Read`], which is accompanied by a writable lease.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ReadArgs {
+    pub channel: u32,
+    /// If zero, the read blocks until there is data. Otherwise, the read
+    /// doesn't block; if there is no data, these notification bits are
+    /// posted to the reader once there is.
+    pub notification: u32,
+}
+
+/// The statistics of a channel, as returned by [`Op::Stats`]. The counts
+/// are of bytes, and wrap.
+#[derive(Copy, Clone, Debug, Default, PartialEq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ChannelStats {
+    pub capacity: u32,
+    /// Bytes currently in the channel.
+    pub len: u32,
+    /// Most bytes that have been in the channel at once.
+    pub high_water: u32,
+    /// Bytes accepted by writes.
+    pub written: u32,
+    /// Bytes taken by reads.
+    pub read: u32,
+    /// Bytes dropped because the channel was full.
+    pub overflowed: u32,
+    /// Number of writes that had bytes dropped.
+    pub overflows: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pipe(Cell<TaskId>);
+
+impl From<TaskId> for Pipe {
+    fn from(t: TaskId) -> Self {
+        Self(Cell::new(t))
+    }
+}
+
+impl Pipe {
+    /// Writes `data` to `channel`, returning the number of bytes accepted;
+    /// any that don't fit are dropped.
+    pub fn write(
+        &self,
+        channel: Channel,
+        data: &[u8],
+    ) -> Result<usize, PipeError> {
+        let mut accepted = 0u32;
+
+        self.send(
+            Op::Write,
+            channel.0.as_bytes(),
+            accepted.as_bytes_mut(),
+            &[Lease::from(data)],
+        )?;
+
+        Ok(accepted as usize)
+    }
+
+    /// Reads from `channel` into `buf`, blocking until there is at least
+    /// one byte to be read. Returns the number of bytes read.
+    pub fn read(
+        &self,
+        channel: Channel,
+        buf: &mut [u8],
+    ) -> Result<usize, PipeError> {
+        self.read_args(channel, 0, buf)
+    }
+
+    /// Reads from `channel` into `buf` without blocking, returning the
+    /// number of bytes read. If there was nothing to read, `notification`
+    /// (which must be non-zero) will be posted to us once there is.
+    pub fn try_read(
+        &self,
+        channel: Channel,
+        notification: u32,
+        buf: &mut [u8],
+    ) -> Result<usize, PipeError> {
+        assert!(notification != 0);
+        self.read_args(channel, notification, buf)
+    }
+
+    fn read_args(
+        &self,
+        channel: Channel,
+        notification: u32,
+        buf: &mut [u8],
+    ) -> Result<usize, PipeError> {
+        let args = ReadArgs {
+            channel: channel.0,
+            notification,
+        };
+
+        let mut read = 0u32;
+
+        self.send(
+            Op::Read,
+            args.as_bytes(),
+            read.as_bytes_mut(),
+            &[Lease::from(buf)],
+        )?;
+
+        Ok(read as usize)
+    }
+
+    /// Returns the statistics for `channel`.
+    pub fn stats(&self, channel: Channel) -> Result<ChannelStats, PipeError> {
+        let mut stats = ChannelStats::default();
+
+        self.send(Op::Stats, channel.0.as_bytes(), stats.as_bytes_mut(), &[])?;
+
+        Ok(stats)
+    }
+
+    fn send(
+        &self,
+        op: Op,
+        outgoing: &[u8],
+        incoming: &mut [u8],
+        leases: &[Lease<'_>],
+    ) -> Result<(), PipeError> {
+        let task = self.0.get();
+
+        let (rc, rlen) = sys_send(task, op as u16, outgoing, incoming, leases);
+
+        if rc == 0 {
+            // Detect truncated response messages.
+            assert!(rlen == incoming.len());
+            Ok(())
+        } else if let Some(g) = abi::extract_new_generation(rc) {
+            // Detect server death and update task, but do not retry.
+            self.0.set(TaskId::for_index_and_gen(task.index(), g));
+            Err(PipeError::ServerRestarted)
+        } else if let Some(err) = PipeError::from_u32(rc) {
+            Err(err)
+        } else {
+            // An unexpected error code from the server is some sort of
+            // configuration error that we can't reasonably recover from.
+            panic!()
+        }
+    }
+}
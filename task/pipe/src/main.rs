@@ -0,0 +1,251 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Pipe server.
+//!
+//! This server provides the channels configured in `[config.pipe]`: bounded
+//! byte FIFOs through which tasks can stream data to one another. See
+//! `task-pipe-api` for the semantics of reads and writes.
+//!
+//! The channels are carved out of a single buffer, sized at build time to
+//! the sum of their capacities. A reader that blocks on an empty channel
+//! isn't replied to until a write arrives; we keep its `TaskId`, and copy
+//! into its lease directly from the channel when there is data.
+
+#![no_std]
+#![no_main]
+
+use task_pipe_api::{ChannelStats, Op, PipeError, ReadArgs, CHANNELS};
+use userlib::hl::{BorrowReader, BorrowWriter, LeaseError};
+use userlib::*;
+
+/// Total capacity of all channels.
+const STORAGE_SIZE: usize = {
+    let mut size = 0;
+    let mut i = 0;
+
+    while i < CHANNELS.len() {
+        size += CHANNELS[i].capacity;
+        i += 1;
+    }
+
+    size
+};
+
+static mut STORAGE: [u8; STORAGE_SIZE] = [0; STORAGE_SIZE];
+
+/// The state of a channel, the contents of which are in
+/// `STORAGE[start..start + capacity]`.
+#[derive(Copy, Clone)]
+struct Fifo {
+    start: usize,
+    capacity: usize,
+    /// Offset of the oldest byte, relative to `start`.
+    head: usize,
+    len: usize,
+    /// A reader that is blocked awaiting data.
+    reader: Option<TaskId>,
+    /// A reader to be notified, with these bits, when there is data.
+    subscriber: Option<(TaskId, u32)>,
+    stats: ChannelStats,
+}
+
+impl Fifo {
+    const EMPTY: Self = Self {
+        start: 0,
+        capacity: 0,
+        head: 0,
+        len: 0,
+        reader: None,
+        subscriber: None,
+        stats: ChannelStats {
+            capacity: 0,
+            len: 0,
+            high_water: 0,
+            written: 0,
+            read: 0,
+            overflowed: 0,
+            overflows: 0,
+        },
+    };
+
+    /// Appends as much of `data` as fits, returning the number of bytes
+    /// accepted.
+    fn push(&mut self, storage: &mut [u8], data: &[u8]) -> usize {
+        let buf = &mut storage[self.start..self.start + self.capacity];
+        let n = data.len().min(self.capacity - self.len);
+
+        for (i, &byte) in data[..n].iter().enumerate() {
+            buf[(self.head + self.len + i) % self.capacity] = byte;
+        }
+
+        self.len += n;
+        self.stats.written = self.stats.written.wrapping_add(n as u32);
+        self.stats.high_water = self.stats.high_water.max(self.len as u32);
+        n
+    }
+
+    /// Records that `n` bytes of a write had to be dropped.
+    fn overflowed(&mut self, n: usize) {
+        self.stats.overflowed = self.stats.overflowed.wrapping_add(n as u32);
+        self.stats.overflows = self.stats.overflows.wrapping_add(1);
+    }
+
+    /// Moves as much of our contents as will fit into `writer`, returning
+    /// the number of bytes moved.
+    fn drain(
+        &mut self,
+        storage: &[u8],
+        writer: &mut BorrowWriter<'_>,
+    ) -> Result<usize, LeaseError> {
+        let buf = &storage[self.start..self.start + self.capacity];
+        let n = self.len.min(writer.remaining());
+
+        // Our contents may wrap around the end of the buffer, in which case
+        // they come in two pieces.
+        let first = n.min(self.capacity - self.head);
+        writer.write_all(&buf[self.head..self.head + first])?;
+        writer.write_all(&buf[..n - first])?;
+
+        self.head = (self.head + n) % self.capacity;
+        self.len -= n;
+        self.stats.read = self.stats.read.wrapping_add(n as u32);
+        Ok(n)
+    }
+
+    /// Hands data to a waiting reader, if there is data to hand over.
+    fn wake(&mut self, storage: &[u8]) {
+        if self.len == 0 {
+            return;
+        }
+
+        if let Some(task) = self.reader.take() {
+            let caller = hl::Caller::<u32>::from(task);
+
+            let result = BorrowWriter::new(caller.borrow(0))
+                .and_then(|mut writer| self.drain(storage, &mut writer));
+
+            match result {
+                Ok(n) => caller.reply(n as u32),
+                Err(_) => caller.reply_fail(PipeError::BadLease),
+            }
+        }
+
+        if self.len != 0 {
+            if let Some((task, bits)) = self.subscriber.take() {
+                sys_post(task, bits);
+            }
+        }
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    // Safety: this is the only reference to the storage that we create.
+    let storage = unsafe { &mut STORAGE[..] };
+    let mut fifos = [Fifo::EMPTY; CHANNELS.len()];
+    let mut start = 0;
+
+    for (fifo, channel) in fifos.iter_mut().zip(CHANNELS.iter()) {
+        fifo.start = start;
+        fifo.capacity = channel.capacity;
+        fifo.stats.capacity = channel.capacity as u32;
+        start += channel.capacity;
+    }
+
+    let mut buffer = [0; core::mem::size_of::<ReadArgs>()];
+    let mut chunk = [0; 32];
+
+    loop {
+        hl::recv_without_notification(&mut buffer, |op, msg| match op {
+            Op::Write => {
+                let (&channel, caller) = msg
+                    .fixed_with_leases::<u32, u32>(1)
+                    .ok_or(PipeError::BadOperation)?;
+
+                let fifo = fifos
+                    .get_mut(channel as usize)
+                    .ok_or(PipeError::BadChannel)?;
+
+                let mut reader = BorrowReader::new(caller.borrow(0))
+                    .map_err(|_| PipeError::BadLease)?;
+                let mut accepted = 0;
+
+                reader
+                    .for_each_chunk(&mut chunk, |_, data| {
+                        accepted += fifo.push(storage, data);
+                        Ok::<_, LeaseError>(())
+                    })
+                    .map_err(|_| PipeError::BadLease)?;
+
+                if accepted < reader.len() {
+                    fifo.overflowed(reader.len() - accepted);
+                }
+
+                caller.reply(accepted as u32);
+                fifo.wake(storage);
+                Ok::<_, PipeError>(())
+            }
+            Op::Read => {
+                let (args, caller) = msg
+                    .fixed_with_leases::<ReadArgs, u32>(1)
+                    .ok_or(PipeError::BadOperation)?;
+
+                let fifo = fifos
+                    .get_mut(args.channel as usize)
+                    .ok_or(PipeError::BadChannel)?;
+
+                let result = match BorrowWriter::new(caller.borrow(0)) {
+                    Ok(writer) if writer.capacity() == 0 => {
+                        return Err(PipeError::BadLease);
+                    }
+                    Ok(mut writer) if fifo.len != 0 => {
+                        Some(fifo.drain(storage, &mut writer))
+                    }
+                    Ok(_) => None,
+                    Err(_) => return Err(PipeError::BadLease),
+                };
+
+                match result {
+                    Some(Ok(n)) => caller.reply(n as u32),
+                    Some(Err(_)) => return Err(PipeError::BadLease),
+                    None if args.notification == 0 => {
+                        // A reader that has since restarted is no longer
+                        // waiting for anything.
+                        if let Some(reader) = fifo.reader {
+                            if sys_refresh_task_id(reader) == reader {
+                                return Err(PipeError::Busy);
+                            }
+                        }
+
+                        // Leave the caller blocked until there is data.
+                        fifo.reader = Some(caller.task_id());
+                    }
+                    None => {
+                        fifo.subscriber =
+                            Some((caller.task_id(), args.notification));
+                        caller.reply(0);
+                    }
+                }
+
+                Ok(())
+            }
+            Op::Stats => {
+                let (&channel, caller) = msg
+                    .fixed::<u32, ChannelStats>()
+                    .ok_or(PipeError::BadOperation)?;
+
+                let fifo =
+                    fifos.get(channel as usize).ok_or(PipeError::BadChannel)?;
+
+                caller.reply(ChannelStats {
+                    len: fifo.len as u32,
+                    ..fifo.stats
+                });
+
+                Ok(())
+            }
+        });
+    }
+}
@@ -4,13 +4,19 @@
 
 //! Power monitoring
 //!
-//! This is a primordial power monitoring task.
+//! This is a primordial power monitoring task, which reads the voltage and
+//! current sensors in the inventory generated from the I2C configuration in
+//! `app.toml`.  On Gemini BU, it also toggles the rails of the ISL68224
+//! evaluation board.
 //!
 
 #![no_std]
 #![no_main]
 
-use drv_i2c_devices::isl68224::*;
+use drv_i2c_devices::isl68224::Isl68224;
+use drv_i2c_devices::raa229618::Raa229618;
+use drv_i2c_devices::tps546b24a::Tps546b24a;
+use i2c_config::sensors::{Device, Kind, Sensor};
 use ringbuf::*;
 use userlib::units::*;
 use userlib::*;
@@ -18,14 +24,6 @@ use userlib::*;
 task_slot!(I2C, i2c_driver);
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
-#[derive(Copy, Clone, PartialEq)]
-#[allow(dead_code)]
-enum Device {
-    Adm1272,
-    Tps546b24a,
-    Isl68224,
-}
-
 #[derive(Copy, Clone, PartialEq)]
 #[allow(dead_code)]
 enum Command {
@@ -37,53 +35,107 @@ enum Command {
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
-    Datum(Device, Command),
+    /// A reading of the sensor with the given index in the inventory
+    Datum(usize, Command),
+    /// A failed read of the sensor with the given index
+    Failed(usize),
+    /// We have no driver for the sensor with the given index
+    NoDriver(usize),
     None,
 }
 
 ringbuf!(Trace, 16, Trace::None);
 
-fn trace(dev: Device, cmd: Command) {
-    ringbuf_entry!(Trace::Datum(dev, cmd));
+///
+/// Reads a voltage or current sensor, returning `None` if we have no driver
+/// for it.
+///
+fn read(sensor: &Sensor) -> Option<Result<Command, ()>> {
+    let device = &sensor.i2c;
+    let rail = sensor.index;
+
+    let rval = match (sensor.kind, sensor.device) {
+        (Kind::Voltage, Device::Isl68224) => Isl68224::new(device, rail)
+            .read_vout()
+            .map(Command::VOut)
+            .ok(),
+        (Kind::Current, Device::Isl68224) => Isl68224::new(device, rail)
+            .read_iout()
+            .map(Command::IOut)
+            .ok(),
+        (Kind::Voltage, Device::Raa229618) => Raa229618::new(device, rail)
+            .read_vout()
+            .map(Command::VOut)
+            .ok(),
+        (Kind::Current, Device::Raa229618) => Raa229618::new(device, rail)
+            .read_iout()
+            .map(Command::IOut)
+            .ok(),
+        (Kind::Voltage, Device::Tps546b24a) => {
+            Tps546b24a::new(device).read_vout().map(Command::VOut).ok()
+        }
+        (Kind::Current, Device::Tps546b24a) => {
+            Tps546b24a::new(device).read_iout().map(Command::IOut).ok()
+        }
+        (
+            Kind::Voltage | Kind::Current,
+            Device::Adt7420
+            | Device::Max31790
+            | Device::Max6634
+            | Device::Mcp9808
+            | Device::Pct2075
+            | Device::Tmp116
+            | Device::Tmp117,
+        ) => return None,
+        (Kind::Temperature | Kind::Fan, _) => return None,
+    };
+
+    Some(rval.ok_or(()))
 }
 
 #[export_name = "main"]
 fn main() -> ! {
     let task = I2C.get_task_id();
+    let sensors = i2c_config::sensors::sensors(task);
 
-    cfg_if::cfg_if! {
-        if #[cfg(target_board = "gemini-bu-1")] {
-            let (device, rail) = i2c_config::pmbus::isl_evl_vout0(task);
-            let mut isl0 = Isl68224::new(&device, rail);
-
-            let (device, rail) = i2c_config::pmbus::isl_evl_vout1(task);
-            let mut isl1 = Isl68224::new(&device, rail);
-        } else {
-            cfg_if::cfg_if! {
-                if #[cfg(feature = "standalone")] {
-                    let device = &i2c_config::devices::mock(task);
-                    let mut isl0 = Isl68224::new(&device, 0);
-                    let mut isl1 = Isl68224::new(&device, 0);
-                } else {
-                    compile_error!("unknown board");
-                }
-            }
-        }
-    }
+    //
+    // On Gemini BU, we also exercise the ISL68224 evaluation board by
+    // turning its first two rails on and off in turn.
+    //
+    #[cfg(target_board = "gemini-bu-1")]
+    let (mut isl0, mut isl1) = {
+        let (device, rail) = i2c_config::pmbus::isl_evl_vout0(task);
+        let isl0 = Isl68224::new(&device, rail);
+
+        let (device, rail) = i2c_config::pmbus::isl_evl_vout1(task);
+        let isl1 = Isl68224::new(&device, rail);
+
+        (isl0, isl1)
+    };
 
     loop {
-        isl0.turn_off().unwrap();
-        isl1.turn_on().unwrap();
-        hl::sleep_for(1000);
+        #[cfg(target_board = "gemini-bu-1")]
+        {
+            isl0.turn_off().unwrap();
+            isl1.turn_on().unwrap();
+            hl::sleep_for(1000);
+
+            isl0.turn_on().unwrap();
+            isl1.turn_off().unwrap();
+        }
 
-        isl0.turn_on().unwrap();
-        isl1.turn_off().unwrap();
         hl::sleep_for(1000);
 
-        let vout = isl0.read_vout().unwrap();
-        trace(Device::Isl68224, Command::VOut(vout));
+        for (ndx, sensor) in sensors.iter().enumerate() {
+            if sensor.kind != Kind::Voltage && sensor.kind != Kind::Current {
+                continue;
+            }
 
-        let vout = isl1.read_vout().unwrap();
-        trace(Device::Isl68224, Command::VOut(vout));
+            match read(sensor) {
+                Some(Ok(cmd)) => ringbuf_entry!(Trace::Datum(ndx, cmd)),
+                Some(Err(_)) => ringbuf_entry!(Trace::Failed(ndx)),
+                None => ringbuf_entry!(Trace::NoDriver(ndx)),
+            }
+        }
     }
 }
@@ -8,13 +8,21 @@
 //! sensors and control fan duty cycles to actively manage thermals.  Right now,
 //! though it is merely reading every fan and temp sensor that it can find...
 //!
+//! The sensors are those in the inventory generated from the I2C
+//! configuration in `app.toml`.
+//!
 
 #![no_std]
 #![no_main]
 
+use drv_i2c_devices::adt7420::Adt7420;
 use drv_i2c_devices::max31790::*;
+use drv_i2c_devices::max6634::Max6634;
+use drv_i2c_devices::mcp9808::Mcp9808;
+use drv_i2c_devices::pct2075::Pct2075;
 use drv_i2c_devices::tmp116::*;
 use drv_i2c_devices::TempSensor;
+use i2c_config::sensors::{Device, Kind, Sensor};
 use userlib::units::*;
 use userlib::*;
 
@@ -38,36 +46,104 @@ fn print_temp<T: core::fmt::Display>(temp: Celsius, device: &T) {
     );
 }
 
-fn read_fans(fctrl: &Max31790) {
-    let mut ndx = 0;
+fn initialize_fans(sensor: &Sensor) {
+    match sensor.device {
+        Device::Max31790 => {
+            let fctrl = Max31790::new(&sensor.i2c);
+
+            loop {
+                match fctrl.initialize() {
+                    Ok(_) => {
+                        sys_log!("{}: initialization successful", fctrl);
+                        break;
+                    }
+                    Err(err) => {
+                        sys_log!("{}: initialization failed: {:?}", fctrl, err);
+                        hl::sleep_for(1000);
+                    }
+                }
+            }
+        }
+        Device::Adt7420
+        | Device::Isl68224
+        | Device::Max6634
+        | Device::Mcp9808
+        | Device::Pct2075
+        | Device::Raa229618
+        | Device::Tmp116
+        | Device::Tmp117
+        | Device::Tps546b24a => {
+            sys_log!("{}: no fan driver for {:?}", sensor.name, sensor.device);
+        }
+    }
+}
 
-    for fan in 0..MAX_FANS {
-        let fan = Fan::new(fan).unwrap();
+fn read_fan(sensor: &Sensor) {
+    match sensor.device {
+        Device::Max31790 => {
+            let fctrl = Max31790::new(&sensor.i2c);
 
-        match fctrl.fan_rpm(fan) {
-            Ok(rval) if rval.0 != 0 => {
-                sys_log!("{}: {}: RPM={}", fctrl, fan, rval.0);
-            }
-            Ok(_) => {}
-            Err(err) => {
-                sys_log!("{}: {}: failed: {:?}", fctrl, fan, err);
+            let fan = match Fan::new(sensor.index) {
+                Ok(fan) => fan,
+                Err(_) => {
+                    sys_log!("{}: invalid fan {}", sensor.name, sensor.index);
+                    return;
+                }
+            };
+
+            match fctrl.fan_rpm(fan) {
+                Ok(rval) if rval.0 != 0 => {
+                    sys_log!("{}: {}: RPM={}", sensor.name, fan, rval.0);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    sys_log!("{}: {}: failed: {:?}", sensor.name, fan, err);
+                }
             }
         }
-
-        ndx = ndx + 1;
+        Device::Adt7420
+        | Device::Isl68224
+        | Device::Max6634
+        | Device::Mcp9808
+        | Device::Pct2075
+        | Device::Raa229618
+        | Device::Tmp116
+        | Device::Tmp117
+        | Device::Tps546b24a => {}
     }
 }
 
-fn temp_read<E: core::fmt::Debug, T: TempSensor<E> + core::fmt::Display>(
+fn temp_read<E: core::fmt::Debug, T: TempSensor<E>>(
+    sensor: &Sensor,
     device: &T,
 ) {
     match device.read_temperature() {
         Ok(temp) => {
-            print_temp(temp, device);
+            print_temp(temp, &sensor.name);
         }
 
         Err(err) => {
-            sys_log!("{}: failed to read temp: {:?}", device, err);
+            sys_log!("{}: failed to read temp: {:?}", sensor.name, err);
+        }
+    }
+}
+
+fn read_temperature(sensor: &Sensor) {
+    let device = &sensor.i2c;
+
+    match sensor.device {
+        Device::Adt7420 => temp_read(sensor, &Adt7420::new(device)),
+        Device::Max6634 => temp_read(sensor, &Max6634::new(device)),
+        Device::Mcp9808 => temp_read(sensor, &Mcp9808::new(device)),
+        Device::Pct2075 => temp_read(sensor, &Pct2075::new(device)),
+        Device::Tmp116 | Device::Tmp117 => {
+            temp_read(sensor, &Tmp116::new(device))
+        }
+        Device::Isl68224
+        | Device::Max31790
+        | Device::Raa229618
+        | Device::Tps546b24a => {
+            sys_log!("{}: no temp driver for {:?}", sensor.name, sensor.device);
         }
     }
 }
@@ -75,53 +151,27 @@ fn temp_read<E: core::fmt::Debug, T: TempSensor<E> + core::fmt::Display>(
 #[export_name = "main"]
 fn main() -> ! {
     let task = I2C.get_task_id();
-    use i2c_config::devices;
-
-    cfg_if::cfg_if! {
-        if #[cfg(target_board = "gemini-bu-1")] {
-            let fctrl = Max31790::new(&devices::max31790(task)[0]);
-            let tmp116: [Tmp116; 0] = [];
-        } else if #[cfg(target_board = "gimlet-1")] {
-            let tmp116 = [
-                Tmp116::new(&devices::tmp117_front_zone1(task)),
-                Tmp116::new(&devices::tmp117_front_zone2(task)),
-                Tmp116::new(&devices::tmp117_front_zone3(task)),
-                Tmp116::new(&devices::tmp117_rear_zone1(task)),
-                Tmp116::new(&devices::tmp117_rear_zone2(task)),
-                Tmp116::new(&devices::tmp117_rear_zone3(task)),
-            ];
-
-            let fctrl = Max31790::new(&devices::max31790(task)[0]);
-        } else {
-            cfg_if::cfg_if! {
-                if #[cfg(feature = "standalone")] {
-                    let fctrl = Max31790::new(&devices::mock(task));
-                    let tmp116: [Tmp116; 0] = [];
-                } else {
-                    compile_error!("unknown board");
-                }
-            }
-        }
-    }
+    let sensors = i2c_config::sensors::sensors(task);
 
-    loop {
-        match fctrl.initialize() {
-            Ok(_) => {
-                sys_log!("{}: initialization successful", fctrl);
-                break;
-            }
-            Err(err) => {
-                sys_log!("{}: initialization failed: {:?}", fctrl, err);
-                hl::sleep_for(1000);
-            }
+    //
+    // A fan controller may have many fans, but we only want to initialize
+    // it once.
+    //
+    for (ndx, sensor) in sensors.iter().enumerate() {
+        let fan = |s: &Sensor| s.kind == Kind::Fan && s.i2c == sensor.i2c;
+
+        if fan(sensor) && !sensors[..ndx].iter().any(fan) {
+            initialize_fans(sensor);
         }
     }
 
     loop {
-        read_fans(&fctrl);
-
-        for device in &tmp116 {
-            temp_read(device);
+        for sensor in &sensors {
+            match sensor.kind {
+                Kind::Temperature => read_temperature(sensor),
+                Kind::Fan => read_fan(sensor),
+                _ => {}
+            }
         }
 
         hl::sleep_for(1000);
@@ -65,6 +65,9 @@ test_cases! {
     test_borrow_info,
     test_borrow_read,
     test_borrow_write,
+    test_borrow_reader,
+    test_borrow_writer,
+    test_borrow_copy_between,
     test_borrow_without_peer_waiting,
     test_supervisor_fault_notification,
     test_timer_advance,
@@ -75,7 +78,13 @@ test_cases! {
     test_refresh_task_id_basic,
     test_refresh_task_id_off_by_one,
     test_refresh_task_id_off_by_many,
+    test_task_lookup_by_name,
     test_lpc55_flash_write,
+    test_lpc55_flash_erase,
+    test_lpc55_image_info,
+    test_lpc55_pfr_read,
+    test_pipe_read_write,
+    test_pipe_overflow,
     test_post,
 }
 
@@ -102,6 +111,172 @@ fn test_lpc55_flash_write() {
 #[cfg(not(feature = "lpc55"))]
 fn test_lpc55_flash_write() {}
 
+#[cfg(feature = "lpc55")]
+fn test_lpc55_flash_erase() {
+    use hypocalls::FlashStatus;
+
+    assert_eq!(hypocalls::hypo_erase_flash(0, 512), FlashStatus::Success);
+
+    // Verify that we reject non-zero ids and empty erases
+    assert_eq!(hypocalls::hypo_erase_flash(1, 512), FlashStatus::InvalidArg);
+    assert_eq!(hypocalls::hypo_erase_flash(0, 0), FlashStatus::InvalidArg);
+
+    // Erases must be a whole number of pages, and fit in the region
+    assert_eq!(
+        hypocalls::hypo_erase_flash(0, 100),
+        FlashStatus::AlignmentError
+    );
+    assert_eq!(
+        hypocalls::hypo_erase_flash(0, 0x10_0000),
+        FlashStatus::SizeError
+    );
+}
+
+#[cfg(not(feature = "lpc55"))]
+fn test_lpc55_flash_erase() {}
+
+#[cfg(feature = "lpc55")]
+fn test_lpc55_image_info() {
+    let header = hypocalls::hypo_get_image_header().unwrap();
+    assert_ne!(header.sp, 0);
+    assert_ne!(header.image_length, 0);
+
+    // We are running from the image, so our code should be within it
+    let start = header.pc & !0xfff;
+    let pc = test_lpc55_image_info as usize as u32;
+    assert!(pc < start + header.image_length);
+
+    hypocalls::hypo_get_image_version().unwrap();
+
+    // Until there is an image B, we can only have booted A -- and can't ask
+    // to boot anything else.
+    let (slot, _) = hypocalls::hypo_get_boot_info().unwrap();
+    assert_eq!(slot, hypocalls::BootSlot::A);
+    assert_eq!(
+        hypocalls::hypo_boot_other_slot_next(),
+        hypocalls::FlashStatus::InvalidArg
+    );
+}
+
+#[cfg(not(feature = "lpc55"))]
+fn test_lpc55_image_info() {}
+
+#[cfg(feature = "lpc55")]
+fn test_lpc55_pfr_read() {
+    use hypocalls::FlashStatus;
+
+    let mut buf = [0u32; 4];
+
+    assert_eq!(hypocalls::hypo_read_cmpa(0, &mut buf), FlashStatus::Success);
+    assert_eq!(hypocalls::hypo_read_cfpa(0, &mut buf), FlashStatus::Success);
+
+    // Reads must be word aligned, non-empty, and within the page
+    assert_eq!(
+        hypocalls::hypo_read_cmpa(2, &mut buf),
+        FlashStatus::AlignmentError
+    );
+    assert_eq!(
+        hypocalls::hypo_read_cfpa(0, &mut []),
+        FlashStatus::InvalidArg
+    );
+    assert_eq!(
+        hypocalls::hypo_read_cmpa(512 - 8, &mut buf),
+        FlashStatus::SizeError
+    );
+    assert_eq!(
+        hypocalls::hypo_read_cfpa(u32::MAX - 3, &mut buf),
+        FlashStatus::SizeError
+    );
+}
+
+#[cfg(not(feature = "lpc55"))]
+fn test_lpc55_pfr_read() {}
+
+/// Returns the pipe server and its channel for testing, if the image has
+/// them.
+#[cfg(feature = "pipe")]
+fn pipe_channel() -> Option<(task_pipe_api::Pipe, task_pipe_api::Channel)> {
+    let channel = task_pipe_api::Channel::by_name("test")?;
+    Some((task_pipe_api::Pipe::from(PIPE.get_task_id()), channel))
+}
+
+/// Tests that bytes written to a pipe can be read back, and that a reader
+/// that finds nothing is notified of a subsequent write.
+#[cfg(feature = "pipe")]
+fn test_pipe_read_write() {
+    const ARBITRARY_NOTIFICATION: u32 = 1 << 17;
+
+    let (pipe, channel) = pipe_channel().unwrap();
+    let mut buf = [0; 8];
+
+    assert_eq!(pipe.write(channel, b"hello"), Ok(5));
+    assert_eq!(
+        pipe.try_read(channel, ARBITRARY_NOTIFICATION, &mut buf),
+        Ok(5)
+    );
+    assert_eq!(&buf[..5], b"hello");
+
+    // The channel is now empty, so this should arrange for a notification.
+    assert_eq!(
+        pipe.try_read(channel, ARBITRARY_NOTIFICATION, &mut buf),
+        Ok(0)
+    );
+    assert_eq!(pipe.write(channel, b"!"), Ok(1));
+
+    let rm = sys_recv_closed(&mut [], ARBITRARY_NOTIFICATION, TaskId::KERNEL)
+        .unwrap();
+    assert_eq!(rm.operation, ARBITRARY_NOTIFICATION);
+
+    // A blocking read returns straight away if there's data.
+    assert_eq!(pipe.read(channel, &mut buf), Ok(1));
+    assert_eq!(buf[0], b'!');
+
+    // Reads into nothing are refused.
+    assert_eq!(
+        pipe.read(channel, &mut []),
+        Err(task_pipe_api::PipeError::BadLease)
+    );
+}
+
+#[cfg(not(feature = "pipe"))]
+fn test_pipe_read_write() {}
+
+/// Tests that writes to a full pipe are truncated, and counted.
+#[cfg(feature = "pipe")]
+fn test_pipe_overflow() {
+    let (pipe, channel) = pipe_channel().unwrap();
+    let capacity = channel.info().capacity;
+    let data = [0xa5; 32];
+
+    assert!(capacity + 4 <= data.len());
+
+    let before = pipe.stats(channel).unwrap();
+    assert_eq!(before.len, 0);
+
+    assert_eq!(pipe.write(channel, &data[..capacity + 4]), Ok(capacity));
+
+    let after = pipe.stats(channel).unwrap();
+    assert_eq!(after.len as usize, capacity);
+    assert_eq!(after.high_water as usize, capacity);
+    assert_eq!(after.overflowed, before.overflowed + 4);
+    assert_eq!(after.overflows, before.overflows + 1);
+    assert_eq!(after.written as usize, before.written as usize + capacity);
+
+    // Nothing more fits.
+    assert_eq!(pipe.write(channel, &data[..1]), Ok(0));
+
+    let mut buf = [0; 32];
+    assert_eq!(pipe.read(channel, &mut buf), Ok(capacity));
+    assert!(buf[..capacity].iter().all(|&b| b == 0xa5));
+
+    let stats = pipe.stats(channel).unwrap();
+    assert_eq!(stats.len, 0);
+    assert_eq!(stats.overflows, before.overflows + 2);
+}
+
+#[cfg(not(feature = "pipe"))]
+fn test_pipe_overflow() {}
+
 /// Tests that we can send a message to our assistant, and that the assistant
 /// can reply. Technically this is also a test of RECV/REPLY on the assistant
 /// side but hey.
@@ -611,6 +786,182 @@ fn test_borrow_write() {
     );
 }
 
+/// Tests the `hl::BorrowReader` cursor, including its bounds checking.
+fn test_borrow_reader() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    // Don't actually care about the response in this case
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+
+            // Borrow #1 is the read-only one, and can't be written.
+            assert_eq!(
+                hl::BorrowWriter::new(caller.borrow(1)).err(),
+                Some(hl::LeaseError::BadAttributes)
+            );
+
+            let mut reader = hl::BorrowReader::new(caller.borrow(1)).unwrap();
+            assert_eq!(reader.len(), 5);
+
+            let mut dest = [0; 2];
+            reader.read_exact(&mut dest).unwrap();
+            assert_eq!(&dest, b"he");
+            assert_eq!(reader.position(), 2);
+
+            // Reading past the end must fail without advancing.
+            let mut big = [0; 4];
+            assert_eq!(
+                reader.read_exact(&mut big),
+                Err(hl::LeaseError::EndOfLease)
+            );
+            assert_eq!(reader.position(), 2);
+
+            // Chunked reads see the rest of the borrow, in order.
+            let mut chunk = [0; 2];
+            let mut rest = [0; 3];
+            reader
+                .for_each_chunk(&mut chunk, |offset, data| {
+                    rest[offset - 2..][..data.len()].copy_from_slice(data);
+                    Ok::<_, hl::LeaseError>(())
+                })
+                .unwrap();
+            assert_eq!(&rest, b"llo");
+            assert!(reader.is_empty());
+            assert_eq!(reader.read(&mut big), Ok(0));
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
+/// Tests the `hl::BorrowWriter` cursor, including its bounds checking.
+fn test_borrow_writer() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    // Don't actually care about the response in this case
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+
+            // Borrow #0 is the read-write one.
+            let mut writer = hl::BorrowWriter::new(caller.borrow(0)).unwrap();
+            assert_eq!(writer.capacity(), 16);
+
+            writer.write_all(b"hello, ").unwrap();
+            writer.write_value(*b"llama").unwrap();
+            assert_eq!(writer.position(), 12);
+
+            // Writing past the end must fail without writing anything.
+            assert_eq!(
+                writer.write_all(b"(s)!!"),
+                Err(hl::LeaseError::EndOfLease)
+            );
+            assert_eq!(writer.position(), 12);
+
+            // Chunked fill covers exactly the remaining space.
+            let mut chunk = [0; 3];
+            writer
+                .fill_by_chunk(&mut chunk, |offset, data| {
+                    for (i, b) in data.iter_mut().enumerate() {
+                        *b = b"(s)!"[offset - 12 + i];
+                    }
+                    Ok::<_, hl::LeaseError>(())
+                })
+                .unwrap();
+            assert!(writer.is_full());
+            assert_eq!(writer.write(b"x"), Ok(0));
+
+            let mut readback = [0; 16];
+            caller.borrow(0).read_fully_at(0, &mut readback).unwrap();
+            assert_eq!(&readback, b"hello, llama(s)!");
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
+/// Tests that `hl::copy_between` moves data from one borrow to another, and
+/// refuses to copy into a borrow that's too small.
+fn test_borrow_copy_between() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    // Don't actually care about the response in this case
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+
+            // Use a deliberately small bounce buffer to force several chunks.
+            let mut buf = [0; 2];
+
+            // 5-byte read-only borrow #1 into 16-byte read-write borrow #0.
+            let n =
+                hl::copy_between(caller.borrow(1), caller.borrow(0), &mut buf)
+                    .unwrap();
+            assert_eq!(n, 5);
+
+            let mut readback = [0; 5];
+            caller.borrow(0).read_fully_at(0, &mut readback).unwrap();
+            assert_eq!(&readback, b"hello");
+
+            // The other way around doesn't fit (and isn't writable anyway).
+            assert!(hl::copy_between(
+                caller.borrow(0),
+                caller.borrow(1),
+                &mut buf
+            )
+            .is_err());
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
 /// Tests the three borrow syscalls on a task that is not waiting in reply,
 /// which should return `DEFECT` but not cause either task to fault.
 fn test_borrow_without_peer_waiting() {
@@ -877,6 +1228,26 @@ fn test_refresh_task_id_off_by_many() {
     assert_eq!(fault, FaultInfo::SyscallUsage(UsageError::TaskOutOfRange));
 }
 
+/// Tests that tasks can be found by name at runtime, and that the results agree
+/// with our (link-time resolved) task slots.
+fn test_task_lookup_by_name() {
+    assert!(ASSIST.is_bound());
+    assert_eq!(ASSIST.try_get_task_id(), Some(assist_task_id()));
+
+    assert_eq!(task_slot::lookup_task("assist"), Some(assist_task_id()));
+    assert_eq!(
+        task_slot::lookup_task("suite").map(|id| id.index()),
+        Some(usize::from(SUITE.get_task_index()))
+    );
+    assert_eq!(task_slot::lookup_task("no-such-task"), None);
+
+    assert_eq!(
+        task_slot::task_name(ASSIST.get_task_index().into()),
+        Some("assist")
+    );
+    assert_eq!(task_slot::task_name(NUM_TASKS), None);
+}
+
 /// Tests that notification bit posting works roughly as we'd expect.
 fn test_post() {
     let assist = assist_task_id();
@@ -923,6 +1294,8 @@ task_slot!(ASSIST, assist);
 // Our own identity
 task_slot!(SUITE, suite);
 task_slot!(RUNNER, runner);
+#[cfg(feature = "pipe")]
+task_slot!(PIPE, pipe);
 
 /// Gets the current expected `TaskId` for the assistant.
 fn assist_task_id() -> TaskId {